@@ -0,0 +1,770 @@
+use midly::{
+    num::{u15, u24, u28, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
+};
+use rustsheet::{
+    bar::{Line, LineType, NotePosition, SingleLine},
+    dynamics::Dynamic,
+    key::Mode,
+    notes::{Articulation, Note, NoteInfo, Tone},
+    span::SpanKind,
+    tempo::{Duration, TempoMap, TimeSignature},
+    MusicSheet,
+};
+
+use crate::{MidiConfig, MidiError};
+
+/// MIDI offsets note numbers by one octave, C4 is 60
+const MIDI_C0: u32 = 12;
+const MAX_MIDI_NOTE: u32 = 127;
+const DRUM_CHANNEL: usize = 9;
+const MICROSECONDS_PER_MINUTE: u32 = 60_000_000;
+/// How many times longer notes with a fermata are held
+const FERMATA_HOLD: u32 = 2;
+/// How much louder accented notes are played
+const ACCENT_VELOCITY: u8 = 20;
+const MARCATO_VELOCITY: u8 = 30;
+/// How much hairpins without a dynamic at their end change the velocity, about one dynamic level
+const HAIRPIN_VELOCITY: u8 = 16;
+
+/// An event at an absolute tick, converted to a delta when writing the track
+struct Event<'a> {
+    tick: u64,
+    kind: TrackEventKind<'a>,
+}
+
+impl Event<'_> {
+    /// Events at the same tick are sorted so that notes are released before new ones start
+    fn order(&self) -> u8 {
+        match self.kind {
+            TrackEventKind::Meta(_) => 0,
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOff { .. },
+                ..
+            } => 1,
+            _ => 2,
+        }
+    }
+}
+
+pub fn export_sheet(sheet: &MusicSheet, config: &MidiConfig) -> Result<Vec<u8>, MidiError> {
+    if config.ticks_per_quarter == 0 || config.ticks_per_quarter > u16::from(u15::max_value()) {
+        return Err(MidiError::InvalidConfig(
+            "ticks per quarter must be between 1 and 32767",
+        ));
+    }
+    if config.bpm == 0 {
+        return Err(MidiError::InvalidConfig("bpm can't be zero"));
+    }
+    if config.velocity == 0 || config.velocity > 127 {
+        return Err(MidiError::InvalidConfig(
+            "velocity must be between 1 and 127",
+        ));
+    }
+
+    // the music is played as it sounds, with its repeats and jumps written out
+    let mut sheet = sheet
+        .to_concert_pitch()
+        .map_err(MidiError::ConcertPitch)?
+        .unroll();
+    // syllables that don't end their word are followed by a hyphen, the way karaoke files have them
+    for lyric in &mut sheet.lyrics {
+        if lyric.syllabic.continues() {
+            lyric.text.push('-');
+        }
+    }
+    let sheet = &sheet;
+
+    // the conductor track goes first, but it needs the fermatas of every line
+    let mut fermatas = Vec::new();
+    let mut lines = Vec::with_capacity(sheet.lines.len());
+    for (i, line) in sheet.lines.iter().enumerate() {
+        lines.push(line_track(sheet, line, i, config, &mut fermatas)?);
+    }
+
+    let mut tracks = Vec::with_capacity(sheet.lines.len() + 1);
+    tracks.push(conductor_track(sheet, config, fermatas)?);
+    tracks.extend(lines);
+
+    let smf = Smf {
+        header: Header::new(
+            Format::Parallel,
+            Timing::Metrical(u15::new(config.ticks_per_quarter)),
+        ),
+        tracks,
+    };
+
+    let mut out = Vec::new();
+    smf.write_std(&mut out)?;
+
+    Ok(out)
+}
+
+/// The first track, with everything that applies to the whole sheet.
+///
+/// The tempo follows the markings of the sheet, gradual changes going in small steps.
+/// Fermatas are held by slowing the tempo down from when they start to when they end.
+fn conductor_track<'a>(
+    sheet: &'a MusicSheet,
+    config: &MidiConfig,
+    mut fermatas: Vec<(Duration, Duration)>,
+) -> Result<Track<'a>, MidiError> {
+    let tempo_event = |tick, quarters_per_minute: f64| Event {
+        tick,
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(
+            (f64::from(MICROSECONDS_PER_MINUTE) / quarters_per_minute)
+                .round()
+                .min(f64::from(u24::max_value().as_int())) as u32,
+        ))),
+    };
+    let mut events = vec![Event {
+        tick: 0,
+        kind: TrackEventKind::Meta(MetaMessage::TrackName(sheet.title.as_bytes())),
+    }];
+
+    // fermatas of different voices and lines that overlap are held together
+    fermatas.sort();
+    let mut held: Vec<(Duration, Duration)> = Vec::new();
+    for (start, end) in fermatas {
+        match held.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => held.push((start, end)),
+        }
+    }
+
+    // accelerandos and ritardandos change the tempo every quaver
+    let steps = TempoMap::new(sheet, f64::from(config.bpm)).steps(Duration::new(1, 8));
+    let mut changes: Vec<Duration> = steps
+        .iter()
+        .map(|(pos, _)| *pos)
+        .chain(held.iter().flat_map(|(start, end)| [*start, *end]))
+        .collect();
+    changes.sort();
+    changes.dedup();
+    let mut last = None;
+    for pos in changes {
+        let step = steps.partition_point(|(at, _)| *at <= pos) - 1;
+        let mut tempo = steps[step].1;
+        if held.iter().any(|(start, end)| *start <= pos && pos < *end) {
+            tempo /= f64::from(FERMATA_HOLD);
+        }
+        if last != Some(tempo) {
+            events.push(tempo_event(to_ticks(pos, config), tempo));
+            last = Some(tempo);
+        }
+    }
+
+    // time and key signatures are taken from the first line, the others are expected to agree
+    if let Some(staff) = sheet.lines.first().map(|l| first_staff(&l.line)) {
+        let mut bar_start = Duration::ZERO;
+        let mut ts = TimeSignature::default();
+        for bar in &staff.bars {
+            if let Some(signature) = bar.header.time_signature {
+                ts = signature;
+                events.push(Event {
+                    tick: to_ticks(bar_start, config),
+                    kind: TrackEventKind::Meta(time_signature(&ts)?),
+                });
+            }
+            if let Some(key) = bar.header.key_signature {
+                // MIDI only knows major and minor, other modes keep their signature
+                events.push(Event {
+                    tick: to_ticks(bar_start, config),
+                    kind: TrackEventKind::Meta(MetaMessage::KeySignature(
+                        key.fifths(),
+                        key.mode() == Mode::Minor,
+                    )),
+                });
+            }
+            bar_start = bar_start + ts.bar_duration().max(bar.duration());
+        }
+    }
+
+    Ok(into_track(events))
+}
+
+/// The track of a line, adding when its fermatas start and end to `fermatas`
+fn line_track<'a>(
+    sheet: &'a MusicSheet,
+    line: &'a Line,
+    index: usize,
+    config: &MidiConfig,
+    fermatas: &mut Vec<(Duration, Duration)>,
+) -> Result<Track<'a>, MidiError> {
+    let channel = channel(index)?;
+    let mut events = Vec::new();
+
+    if let Some(name) = &line.name {
+        events.push(Event {
+            tick: 0,
+            kind: TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())),
+        });
+    }
+
+    for (s, staff) in line.line.staves().into_iter().enumerate() {
+        let position = NotePosition {
+            line: index,
+            staff: s,
+            bar: 0,
+            voice: 0,
+            index: 0,
+        };
+        staff_events(
+            sheet,
+            staff,
+            position,
+            channel,
+            config,
+            &mut events,
+            fermatas,
+        )?;
+    }
+
+    Ok(into_track(events))
+}
+
+/// Adds the events of a staff, whose line and staff number are in `position`,
+/// with a lyric event for the syllable of the lowest verse sung on each note
+fn staff_events<'a>(
+    sheet: &'a MusicSheet,
+    staff: &SingleLine,
+    position: NotePosition,
+    channel: u4,
+    config: &MidiConfig,
+    events: &mut Vec<Event<'a>>,
+    fermatas: &mut Vec<(Duration, Duration)>,
+) -> Result<(), MidiError> {
+    let mut bar_start = Duration::ZERO;
+    let mut ts = TimeSignature::default();
+    let loudness = Loudness::new(sheet, position, config);
+
+    for (b, bar) in staff.bars.iter().enumerate() {
+        if let Some(signature) = bar.header.time_signature {
+            ts = signature;
+        }
+
+        // every voice starts with the bar, and the longest one ends it
+        let mut end = bar_start;
+        for (v, notes) in bar.voices.iter().enumerate() {
+            let mut pos = bar_start;
+            for (i, note) in notes.iter().enumerate() {
+                let position = NotePosition {
+                    bar: b,
+                    voice: v,
+                    index: i,
+                    ..position
+                };
+                let tick = to_ticks(pos, config);
+                let sung = sheet
+                    .lyrics
+                    .iter()
+                    .filter(|lyric| lyric.position == position)
+                    .min_by_key(|lyric| lyric.verse);
+                if let Some(lyric) = sung {
+                    events.push(Event {
+                        tick,
+                        kind: TrackEventKind::Meta(MetaMessage::Lyric(lyric.text.as_bytes())),
+                    });
+                }
+                let played = MidiConfig {
+                    velocity: loudness.velocity(tick),
+                    ..*config
+                };
+                let first = events.len();
+                note_events(note, &mut pos, (1, 1), channel, &played, events, fermatas)?;
+                merge_ties(
+                    events,
+                    first,
+                    &sheet.tied_from_previous(position),
+                    &sheet.tied_into_next(position),
+                )?;
+            }
+            end = end.max(pos);
+        }
+
+        // underfull bars still take their whole time
+        bar_start = bar_start + ts.bar_duration().max(end - bar_start);
+    }
+
+    Ok(())
+}
+
+/// How loud a staff is played over time, from its dynamics and hairpins, in ticks
+struct Loudness {
+    /// The velocity before the first dynamic
+    initial: u8,
+    /// Where the velocity changes and to what, in order
+    levels: Vec<(u64, u8)>,
+    /// Notes played louder than the others around them, like a sforzando
+    accents: Vec<(u64, u8)>,
+    /// Hairpins, from where they start to where they end and the velocity they reach
+    ramps: Vec<(u64, u64, u8)>,
+}
+
+impl Loudness {
+    /// The loudness of the staff at `position`, from the dynamics and hairpins written on it
+    fn new(sheet: &MusicSheet, position: NotePosition, config: &MidiConfig) -> Self {
+        let onset = |p: NotePosition| {
+            if p.line != position.line || p.staff != position.staff {
+                return None;
+            }
+            sheet.onset(p).map(|pos| to_ticks(pos, config))
+        };
+
+        let mut loudness = Self {
+            initial: config.velocity,
+            levels: Vec::new(),
+            accents: Vec::new(),
+            ramps: Vec::new(),
+        };
+        for mark in &sheet.dynamics {
+            let Some(tick) = onset(mark.position) else {
+                continue;
+            };
+            let (attack, level) = dynamic_velocity(mark.dynamic);
+            if level != Some(attack) {
+                loudness.accents.push((tick, attack));
+            }
+            if let Some(level) = level {
+                loudness.levels.push((tick, level));
+            }
+        }
+        loudness.levels.sort_by_key(|(tick, _)| *tick);
+
+        let mut hairpins: Vec<_> = sheet
+            .spans
+            .iter()
+            .filter(|span| span.kind.is_hairpin())
+            .filter_map(|span| Some((onset(span.start)?, onset(span.end)?, span.kind)))
+            .filter(|(start, end, _)| start < end)
+            .collect();
+        hairpins.sort_by_key(|(start, _, _)| *start);
+        for (start, end, kind) in hairpins {
+            let from = loudness.level(start);
+            let written = loudness
+                .levels
+                .iter()
+                .chain(&loudness.accents)
+                .find(|(tick, _)| *tick == end);
+            let target = match (written, kind) {
+                (Some((_, velocity)), _) => *velocity,
+                // without a dynamic to reach, the hairpin goes one level further and stays there
+                (None, SpanKind::Diminuendo) => {
+                    let target = from.saturating_sub(HAIRPIN_VELOCITY).max(1);
+                    loudness.set_level(end, target);
+                    target
+                }
+                (None, _) => {
+                    let target = from.saturating_add(HAIRPIN_VELOCITY).min(127);
+                    loudness.set_level(end, target);
+                    target
+                }
+            };
+            loudness.ramps.push((start, end, target));
+        }
+
+        loudness
+    }
+
+    /// The velocity of a note starting at `tick`
+    fn velocity(&self, tick: u64) -> u8 {
+        if let Some((_, velocity)) = self.accents.iter().find(|(at, _)| *at == tick) {
+            return *velocity;
+        }
+
+        match self
+            .ramps
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&tick))
+        {
+            Some(&(start, end, target)) => {
+                let from = i64::from(self.level(start));
+                let change =
+                    (i64::from(target) - from) * (tick - start) as i64 / (end - start) as i64;
+                (from + change) as u8
+            }
+            None => self.level(tick),
+        }
+    }
+
+    /// The velocity set by the last dynamic at or before `tick`
+    fn level(&self, tick: u64) -> u8 {
+        self.levels
+            .iter()
+            .take_while(|(at, _)| *at <= tick)
+            .last()
+            .map_or(self.initial, |(_, velocity)| *velocity)
+    }
+
+    fn set_level(&mut self, tick: u64, velocity: u8) {
+        let index = self.levels.partition_point(|(at, _)| *at <= tick);
+        self.levels.insert(index, (tick, velocity));
+    }
+}
+
+/// The velocity of the note under a dynamic, and the one of the notes after it,
+/// if the dynamic changes it
+fn dynamic_velocity(dynamic: Dynamic) -> (u8, Option<u8>) {
+    let level = |velocity| (velocity, Some(velocity));
+    match dynamic {
+        Dynamic::Ppp => level(16),
+        Dynamic::Pp => level(33),
+        Dynamic::P => level(49),
+        Dynamic::Mp => level(64),
+        Dynamic::Mf => level(80),
+        Dynamic::F => level(96),
+        Dynamic::Ff => level(112),
+        Dynamic::Fff => level(127),
+        Dynamic::Sfz => (112, None),
+        Dynamic::Fp => (96, Some(49)),
+    }
+}
+
+/// Adds the events of a note starting at `pos`, played at `scale` times its written length
+/// because of the tuplets it's in, then moves `pos` to its end.
+///
+/// Notes with a fermata add when they start and end to `fermatas`.
+fn note_events(
+    note: &Note,
+    pos: &mut Duration,
+    (num, den): (u64, u64),
+    channel: u4,
+    config: &MidiConfig,
+    events: &mut Vec<Event>,
+    fermatas: &mut Vec<(Duration, Duration)>,
+) -> Result<(), MidiError> {
+    let start = *pos;
+    match note {
+        Note::Note(info) => {
+            let end = *pos + info.size.duration().scale(num, den);
+            let (quarters, velocity) = articulate(info, config);
+            let start_tick = to_ticks(*pos, config);
+            let end_tick = to_ticks(end, config);
+            push_note(
+                events,
+                channel,
+                &info.tone,
+                start_tick,
+                start_tick + sounding(end_tick - start_tick, quarters),
+                velocity,
+            )?;
+            *pos = end;
+        }
+        Note::Chord(chord) => {
+            let end = *pos + chord.size.duration().scale(num, den);
+            for tone in chord.tones() {
+                push_note(
+                    events,
+                    channel,
+                    tone,
+                    to_ticks(*pos, config),
+                    to_ticks(end, config),
+                    config.velocity,
+                )?;
+            }
+            *pos = end;
+        }
+        Note::Tuplet(tuplet) => {
+            let scale = (
+                num * u64::from(tuplet.normal()),
+                den * u64::from(tuplet.actual()),
+            );
+            for note in tuplet.notes() {
+                note_events(note, pos, scale, channel, config, events, fermatas)?;
+            }
+        }
+        Note::WithGrace { grace, note } => {
+            // the grace note takes its time from the start of the main note
+            let end = *pos + note.size.duration().scale(num, den);
+            let start_tick = to_ticks(*pos, config);
+            let end_tick = to_ticks(end, config);
+            let grace_end =
+                start_tick + u64::from(config.grace_ticks).min((end_tick - start_tick) / 2);
+            let (quarters, velocity) = articulate(note, config);
+            push_note(
+                events,
+                channel,
+                grace,
+                start_tick,
+                grace_end,
+                config.velocity,
+            )?;
+            push_note(
+                events,
+                channel,
+                &note.tone,
+                grace_end,
+                grace_end + sounding(end_tick - grace_end, quarters),
+                velocity,
+            )?;
+            *pos = end;
+        }
+        Note::Rest(size) => *pos = *pos + size.duration().scale(num, den),
+    }
+
+    if note.has_fermata() {
+        fermatas.push((start, *pos));
+    }
+
+    Ok(())
+}
+
+/// How many quarters of its length a note sounds for, and how loud, with its articulations
+fn articulate(info: &NoteInfo, config: &MidiConfig) -> (u64, u8) {
+    let mut quarters = 4;
+    let mut velocity = config.velocity;
+    for articulation in &info.articulations {
+        match articulation {
+            Articulation::Staccato => quarters = quarters.min(2),
+            Articulation::Staccatissimo => quarters = quarters.min(1),
+            Articulation::Marcato => {
+                quarters = quarters.min(3);
+                velocity = velocity.saturating_add(MARCATO_VELOCITY);
+            }
+            Articulation::Accent => velocity = velocity.saturating_add(ACCENT_VELOCITY),
+            Articulation::Tenuto | Articulation::Fermata => {}
+        }
+    }
+
+    (quarters, velocity.min(127))
+}
+
+/// The ticks a note lasting `ticks` sounds for, always at least one
+fn sounding(ticks: u64, quarters: u64) -> u64 {
+    (ticks * quarters / 4).max(1)
+}
+
+/// Tied tones are played once for all the notes they join, so the events from `first` on
+/// lose the note ons of the tones `held` from the previous note and the note offs of
+/// the ones `carried` on into the next
+fn merge_ties(
+    events: &mut Vec<Event>,
+    first: usize,
+    held: &[Tone],
+    carried: &[Tone],
+) -> Result<(), MidiError> {
+    if held.is_empty() && carried.is_empty() {
+        return Ok(());
+    }
+
+    let held = held.iter().map(midi_note).collect::<Result<Vec<_>, _>>()?;
+    let carried = carried
+        .iter()
+        .map(midi_note)
+        .collect::<Result<Vec<_>, _>>()?;
+    let kept: Vec<Event> = events
+        .drain(first..)
+        .filter(|e| match e.kind {
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { key, .. },
+                ..
+            } => !held.contains(&key.as_int()),
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOff { key, .. },
+                ..
+            } => !carried.contains(&key.as_int()),
+            _ => true,
+        })
+        .collect();
+    events.extend(kept);
+
+    Ok(())
+}
+
+fn push_note(
+    events: &mut Vec<Event>,
+    channel: u4,
+    tone: &Tone,
+    start: u64,
+    end: u64,
+    velocity: u8,
+) -> Result<(), MidiError> {
+    let key = u7::new(midi_note(tone)?);
+    let vel = u7::new(velocity);
+
+    events.push(Event {
+        tick: start,
+        kind: TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::NoteOn { key, vel },
+        },
+    });
+    events.push(Event {
+        tick: end,
+        kind: TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::NoteOff { key, vel },
+        },
+    });
+
+    Ok(())
+}
+
+/// Sorts the events and turns their absolute ticks into deltas
+fn into_track(mut events: Vec<Event>) -> Track {
+    events.sort_by_key(|e| (e.tick, e.order()));
+
+    let mut track = Vec::with_capacity(events.len() + 1);
+    let mut last = 0;
+    for event in events {
+        track.push(TrackEvent {
+            delta: u28::new((event.tick - last) as u32),
+            kind: event.kind,
+        });
+        last = event.tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    track
+}
+
+fn first_staff(line: &LineType) -> &SingleLine {
+    match line {
+        LineType::Single(staff) => staff,
+        LineType::Double(upper, _) => upper,
+    }
+}
+
+/// Gives every line its own channel, leaving out the drum channel
+fn channel(line: usize) -> Result<u4, MidiError> {
+    let channel = if line < DRUM_CHANNEL { line } else { line + 1 };
+    if channel > usize::from(u8::from(u4::max_value())) {
+        return Err(MidiError::TooManyLines);
+    }
+
+    Ok(u4::new(channel as u8))
+}
+
+fn midi_note(tone: &Tone) -> Result<u8, MidiError> {
+    let note = tone.get_semitones_since_c0() + MIDI_C0;
+    if note > MAX_MIDI_NOTE {
+        return Err(MidiError::ToneOutOfRange(*tone));
+    }
+
+    Ok(note as u8)
+}
+
+fn time_signature(ts: &TimeSignature) -> Result<MetaMessage<'static>, MidiError> {
+    // MIDI stores the denominator as a power of two
+    let den = ts.den.get();
+    if !den.is_power_of_two() {
+        return Err(MidiError::UnsupportedTimeSignature(*ts));
+    }
+
+    Ok(MetaMessage::TimeSignature(
+        ts.num.get(),
+        den.trailing_zeros() as u8,
+        24,
+        8,
+    ))
+}
+
+fn to_ticks(pos: Duration, config: &MidiConfig) -> u64 {
+    let ticks_per_whole = 4 * u64::from(config.ticks_per_quarter);
+    ((pos.num() * ticks_per_whole) + (pos.den() / 2)) / pos.den()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustsheet::{notation, span::Span};
+
+    /// Where a note is in the only staff: its bar, voice and index
+    type At = (usize, usize, usize);
+
+    /// The notes played by the first line, as their key and the ticks they start and end at
+    fn played(input: &str, ties: &[(At, At)]) -> Vec<(u8, u64, u64)> {
+        let position = |(bar, voice, index): At| NotePosition {
+            line: 0,
+            staff: 0,
+            bar,
+            voice,
+            index,
+        };
+        let line = LineType::Single(notation::parse_line(input).unwrap());
+        let sheet = MusicSheet {
+            spans: ties
+                .iter()
+                .map(|&(start, end)| Span::tie(position(start), position(end)))
+                .collect(),
+            ..MusicSheet::new(
+                "Test".to_string(),
+                "Someone".to_string(),
+                vec![Line::new(line)],
+            )
+        };
+        let midi = export_sheet(&sheet, &MidiConfig::default()).unwrap();
+        let smf = Smf::parse(&midi).unwrap();
+
+        let mut tick = 0;
+        let mut held = Vec::new();
+        let mut notes = Vec::new();
+        for event in &smf.tracks[1] {
+            tick += u64::from(event.delta.as_int());
+            match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => held.push((key.as_int(), tick)),
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOff { key, .. },
+                    ..
+                } => {
+                    let i = held.iter().position(|&(k, _)| k == key.as_int()).unwrap();
+                    let (key, start) = held.remove(i);
+                    notes.push((key, start, tick));
+                }
+                _ => {}
+            }
+        }
+        assert!(held.is_empty(), "notes left on: {held:?}");
+        notes.sort_unstable_by_key(|&(key, start, _)| (start, key));
+
+        notes
+    }
+
+    #[test]
+    fn plays_tied_notes_once() {
+        assert_eq!(
+            played("C4:2 C4:2 |", &[((0, 0, 0), (0, 0, 1))]),
+            [(60, 0, 1920)]
+        );
+        // without the tie they're two notes
+        assert_eq!(played("C4:2 C4:2 |", &[]), [(60, 0, 960), (60, 960, 1920)]);
+    }
+
+    #[test]
+    fn ties_notes_across_barlines_and_in_chains() {
+        let across = played("C4:1 | C4:2 D4:2 |", &[((0, 0, 0), (1, 0, 0))]);
+        assert_eq!(across, [(60, 0, 2880), (62, 2880, 3840)]);
+
+        let chain = played(
+            "C4:4 C4:4 C4:4 D4:4 |",
+            &[((0, 0, 0), (0, 0, 1)), ((0, 0, 1), (0, 0, 2))],
+        );
+        assert_eq!(chain, [(60, 0, 1440), (62, 1440, 1920)]);
+    }
+
+    #[test]
+    fn ties_only_the_tones_chords_share() {
+        let chords = played("<C4 E4>:2 <C4 G4>:2 |", &[((0, 0, 0), (0, 0, 1))]);
+        assert_eq!(chords, [(60, 0, 1920), (64, 0, 960), (67, 960, 1920)]);
+
+        // a note tied into a chord holds through it
+        let into = played("E4:2 <C4 E4>:2 |", &[((0, 0, 0), (0, 0, 1))]);
+        assert_eq!(into, [(64, 0, 1920), (60, 960, 1920)]);
+
+        // ties between different tones don't join anything
+        let apart = played("C4:2 D4:2 |", &[((0, 0, 0), (0, 0, 1))]);
+        assert_eq!(apart, [(60, 0, 960), (62, 960, 1920)]);
+    }
+
+    #[test]
+    fn ties_notes_of_other_voices() {
+        let voices = played("E5:1 & C4:2 C4:2 |", &[((0, 1, 0), (0, 1, 1))]);
+        assert_eq!(voices, [(60, 0, 1920), (76, 0, 1920)]);
+    }
+}
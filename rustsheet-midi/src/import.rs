@@ -0,0 +1,583 @@
+use std::cmp::Reverse;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use rustsheet::{
+    bar::{Bar, BarHeader, Clef, Line, LineType, NotePosition, SingleLine},
+    key::{KeySignature, Mode},
+    notes::{Note, NoteInfo, Octave, Tone, ToneModifiers, Tuplet},
+    span::Span,
+    tempo::{Duration, NoteSize, NoteSizeUnit, Tempo, TimeSignature},
+    MusicSheet,
+};
+
+use crate::{MidiConfig, MidiError};
+
+/// MIDI offsets note numbers by one octave, C4 is 60
+const MIDI_C0: u8 = 12;
+const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
+/// Lines with an average pitch from middle C up get a treble clef
+const TREBLE_THRESHOLD: u64 = 60;
+/// Quaver triplets are the only tuplets that are recognized
+const TRIPLET: usize = 3;
+
+/// A note read from a track, from its note on to its note off
+#[derive(Debug, Clone, Copy)]
+struct RawNote {
+    start: u64,
+    end: u64,
+    key: u8,
+}
+
+/// A note (or a rest, without a key) once snapped to the grid
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: u64,
+    end: u64,
+    key: Option<u8>,
+    /// Whether the note goes on after a barline, tied to the start of the next bar
+    tied: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BarSpan {
+    start: u64,
+    end: u64,
+    /// Only set when the bar changes the time signature
+    time_signature: Option<TimeSignature>,
+    /// Only set when the bar changes the key signature
+    key_signature: Option<KeySignature>,
+}
+
+/// Positions are snapped on a grid where a crotchet is split both in the
+/// finest binary subdivision and in three, so straight notes and triplets
+/// both land on whole units.
+struct Grid {
+    ticks_per_quarter: u64,
+    quarter: u64,
+    /// Units between two straight grid points
+    straight: u64,
+    /// Units between two triplet grid points
+    triplet: u64,
+}
+
+impl Grid {
+    fn new(ticks_per_quarter: u16, finest: NoteSizeUnit) -> Self {
+        // how many of the finest notes fit in a crotchet
+        let subdivisions = 1 << (NoteSizeUnit::Crotchet as u64 - finest as u64);
+        let quarter = subdivisions * TRIPLET as u64;
+
+        Self {
+            ticks_per_quarter: u64::from(ticks_per_quarter),
+            quarter,
+            straight: TRIPLET as u64,
+            triplet: subdivisions,
+        }
+    }
+
+    fn whole(&self) -> u64 {
+        4 * self.quarter
+    }
+
+    /// Position of a tick in units, before snapping
+    fn exact(&self, tick: u64) -> f64 {
+        tick as f64 * self.quarter as f64 / self.ticks_per_quarter as f64
+    }
+
+    fn snap(&self, tick: u64, step: u64) -> u64 {
+        (self.exact(tick) / step as f64).round() as u64 * step
+    }
+
+    /// How far the tick moves when snapped
+    fn error(&self, tick: u64, step: u64) -> f64 {
+        (self.exact(tick) - self.snap(tick, step) as f64).abs()
+    }
+
+    fn bar_length(&self, ts: &TimeSignature) -> u64 {
+        self.whole() * u64::from(ts.num.get()) / u64::from(ts.den.get())
+    }
+}
+
+pub fn import_sheet(data: &[u8], config: &MidiConfig) -> Result<MusicSheet, MidiError> {
+    if config.grid as u8 > NoteSizeUnit::Crotchet as u8 {
+        return Err(MidiError::InvalidConfig(
+            "the import grid can't be coarser than a crotchet",
+        ));
+    }
+
+    let smf = Smf::parse(data)?;
+    let ticks_per_quarter = match smf.header.timing {
+        Timing::Metrical(ticks) if ticks.as_int() > 0 => ticks.as_int(),
+        _ => return Err(MidiError::UnsupportedTiming),
+    };
+    let grid = Grid::new(ticks_per_quarter, config.grid);
+
+    let mut title = None;
+    let mut time_signatures = Vec::new();
+    let mut key_signatures = Vec::new();
+    let mut tempos = Vec::new();
+    let mut tracks = Vec::new();
+
+    for (i, track) in smf.tracks.iter().enumerate() {
+        let mut tick = 0;
+        let mut name = None;
+        // notes by channel, so that single track files still get one line per instrument
+        let mut channels: [Vec<RawNote>; 16] = Default::default();
+        // note on events waiting for their note off
+        let mut pending: Vec<(u8, u8, u64)> = Vec::new();
+
+        for event in track {
+            tick += u64::from(event.delta.as_int());
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::TrackName(bytes)) => {
+                    name = Some(String::from_utf8_lossy(bytes).into_owned());
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(num, den, _, _)) => {
+                    // the denominator is stored as a power of two
+                    let ts = 1u8
+                        .checked_shl(u32::from(den))
+                        .and_then(|d| TimeSignature::new(num, d).ok())
+                        .ok_or(MidiError::InvalidTimeSignature(num, den))?;
+                    time_signatures.push((tick, ts));
+                }
+                TrackEventKind::Meta(MetaMessage::KeySignature(fifths, minor)) => {
+                    let mode = if minor { Mode::Minor } else { Mode::Major };
+                    let key = KeySignature::new(fifths, mode)
+                        .map_err(|_| MidiError::InvalidKeySignature(fifths))?;
+                    key_signatures.push((tick, key));
+                }
+                TrackEventKind::Meta(MetaMessage::Tempo(micros)) if micros.as_int() > 0 => {
+                    let bpm = (MICROSECONDS_PER_MINUTE / f64::from(micros.as_int())).round();
+                    tempos.push((tick, bpm.clamp(1.0, f64::from(u16::MAX)) as u16));
+                }
+                TrackEventKind::Midi { channel, message } => {
+                    let channel = channel.as_int();
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            pending.push((channel, key.as_int(), tick));
+                        }
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            let key = key.as_int();
+                            if let Some(p) =
+                                pending.iter().position(|p| p.0 == channel && p.1 == key)
+                            {
+                                let (_, _, start) = pending.remove(p);
+                                channels[usize::from(channel)].push(RawNote {
+                                    start,
+                                    end: tick,
+                                    key,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut has_notes = false;
+        for notes in channels.into_iter().filter(|n| !n.is_empty()) {
+            has_notes = true;
+            tracks.push((name.clone(), notes));
+        }
+
+        // the first track of most files only has the song name and the tempo map
+        if i == 0 && !has_notes {
+            title = name;
+        }
+    }
+
+    for (_, notes) in &mut tracks {
+        monophonic(notes);
+    }
+
+    // every line gets the same bars, long enough for the longest of them
+    let end = tracks
+        .iter()
+        .flat_map(|(_, notes)| notes.last())
+        .map(|n| {
+            grid.snap(n.end, grid.straight)
+                .max(grid.snap(n.start, grid.straight) + grid.straight)
+        })
+        .max()
+        .unwrap_or(0);
+    time_signatures.sort_by_key(|(tick, _)| *tick);
+    key_signatures.sort_by_key(|(tick, _)| *tick);
+    let bars = bar_spans(&time_signatures, &key_signatures, end, &grid);
+
+    let mut lines = tracks
+        .into_iter()
+        .map(|(name, notes)| {
+            let (staff, ties) = build_staff(&notes, &bars, &grid)?;
+            let line = Line {
+                name,
+                ..Line::new(LineType::Single(staff))
+            };
+            Ok((line, ties))
+        })
+        .collect::<Result<Vec<_>, MidiError>>()?;
+
+    // tempo markings are written over the first line, in crotchets per minute
+    tempos.sort_by_key(|(tick, _)| *tick);
+    if let Some((
+        Line {
+            line: LineType::Single(staff),
+            ..
+        },
+        _,
+    )) = lines.first_mut()
+    {
+        let mut last = None;
+        for (bar, span) in staff.bars.iter_mut().zip(&bars) {
+            let bpm = active(&tempos, span.start, &grid);
+            if bpm != last {
+                bar.header.tempo =
+                    bpm.and_then(|bpm| Tempo::new(NoteSizeUnit::Crotchet.into(), bpm).ok());
+                last = bpm;
+            }
+        }
+    }
+
+    // every line had its ties put on the first one
+    let mut spans = Vec::new();
+    for (i, (_, ties)) in lines.iter().enumerate() {
+        spans.extend(ties.iter().map(|tie| {
+            let mut tie = *tie;
+            tie.start.line = i;
+            tie.end.line = i;
+            tie
+        }));
+    }
+
+    Ok(MusicSheet {
+        spans,
+        ..MusicSheet::new(
+            title.unwrap_or_default(),
+            String::new(),
+            lines.into_iter().map(|(line, _)| line).collect(),
+        )
+    })
+}
+
+/// Lines hold a single melody, so only the highest of the notes starting
+/// together is kept, and every note is cut when the next one starts.
+fn monophonic(notes: &mut Vec<RawNote>) {
+    notes.sort_by_key(|n| (n.start, Reverse(n.key)));
+    notes.dedup_by_key(|n| n.start);
+
+    for i in 1..notes.len() {
+        notes[i - 1].end = notes[i - 1].end.min(notes[i].start);
+    }
+}
+
+/// Splits the line in bars, time and key signatures take effect on the next barline
+fn bar_spans(
+    time_signatures: &[(u64, TimeSignature)],
+    key_signatures: &[(u64, KeySignature)],
+    end: u64,
+    grid: &Grid,
+) -> Vec<BarSpan> {
+    let mut bars = Vec::new();
+    let mut ts = None;
+    let mut key = None;
+    let mut start = 0;
+
+    while start < end || bars.is_empty() {
+        let current = active(time_signatures, start, grid).unwrap_or_default();
+        let changed = ts != Some(current);
+        ts = Some(current);
+
+        // C major is only written when it replaces another key
+        let current_key = active(key_signatures, start, grid);
+        let key_changed = current_key != key;
+        key = current_key;
+
+        let end = start + grid.bar_length(&current);
+        bars.push(BarSpan {
+            start,
+            end,
+            time_signature: changed.then_some(current),
+            key_signature: key.filter(|_| key_changed),
+        });
+        start = end;
+    }
+
+    bars
+}
+
+/// The last of the changes that happened before the barline at `start`
+fn active<T: Copy>(changes: &[(u64, T)], start: u64, grid: &Grid) -> Option<T> {
+    changes
+        .iter()
+        .rev()
+        .find(|(tick, _)| grid.snap(*tick, grid.straight) <= start)
+        .map(|(_, value)| *value)
+}
+
+/// Writes the notes in bars, returning them with the ties between them, on line 0
+fn build_staff(
+    notes: &[RawNote],
+    bars: &[BarSpan],
+    grid: &Grid,
+) -> Result<(SingleLine, Vec<Span>), MidiError> {
+    let (segments, triplets) = quantize(notes, bars, grid);
+
+    let mut staff = SingleLine {
+        bars: Vec::with_capacity(bars.len()),
+    };
+    let mut segments = segments.into_iter().peekable();
+    let mut key = KeySignature::default();
+    let mut ties = Vec::new();
+
+    for span in bars {
+        // cut the notes at the barline and fill the gaps with rests
+        let mut pieces = Vec::new();
+        let mut cursor = span.start;
+        while let Some(segment) = segments.peek_mut() {
+            if segment.start >= span.end {
+                break;
+            }
+            if segment.start > cursor {
+                pieces.push(Segment {
+                    start: cursor,
+                    end: segment.start,
+                    key: None,
+                    tied: false,
+                });
+            }
+            let end = segment.end.min(span.end);
+            let tied = segment.end > span.end && segment.key.is_some();
+            pieces.push(Segment {
+                end,
+                tied,
+                ..*segment
+            });
+            cursor = end;
+
+            // notes that cross the barline go on in the next bar, tied to this one
+            if segment.end > span.end {
+                segment.start = span.end;
+                break;
+            }
+            segments.next();
+        }
+        if cursor < span.end {
+            pieces.push(Segment {
+                start: cursor,
+                end: span.end,
+                key: None,
+                tied: false,
+            });
+        }
+
+        let mut header = BarHeader {
+            time_signature: span.time_signature,
+            key_signature: span.key_signature,
+            ..Default::default()
+        };
+        if let Some(k) = span.key_signature {
+            key = k;
+        }
+        if span.start == 0 {
+            header.clef = Some(pick_clef(notes));
+        }
+
+        let (notes, tied) = bar_notes(&pieces, &triplets, &key, grid)?;
+        let b = staff.bars.len();
+        let position = |bar, index| NotePosition {
+            line: 0,
+            staff: 0,
+            bar,
+            voice: 0,
+            index,
+        };
+        for i in tied {
+            let end = if i + 1 < notes.len() {
+                position(b, i + 1)
+            } else {
+                position(b + 1, 0)
+            };
+            ties.push(Span::tie(position(b, i), end));
+        }
+        staff.bars.push(Bar::new(header, notes));
+    }
+
+    Ok((staff, ties))
+}
+
+/// Snaps the notes to the grid, returning them with the start of every beat played as a triplet.
+///
+/// A beat becomes a triplet when exactly three notes start in it, closer to
+/// the triplet grid than to the straight one.
+fn quantize(notes: &[RawNote], bars: &[BarSpan], grid: &Grid) -> (Vec<Segment>, Vec<u64>) {
+    let mut positions: Vec<(u64, u64)> = notes
+        .iter()
+        .map(|n| {
+            let start = grid.snap(n.start, grid.straight);
+            // short notes still last at least one step
+            let end = grid.snap(n.end, grid.straight).max(start + grid.straight);
+            (start, end)
+        })
+        .collect();
+
+    let beats = bars.iter().flat_map(|bar| {
+        (bar.start..bar.end)
+            .step_by(grid.quarter as usize)
+            .filter(move |beat| beat + grid.quarter <= bar.end)
+    });
+
+    let mut triplets = Vec::new();
+    for beat in beats {
+        let Some(first) = notes
+            .iter()
+            .position(|n| grid.snap(n.start, grid.triplet) >= beat)
+        else {
+            break;
+        };
+        let Some(group) = notes.get(first..first + TRIPLET) else {
+            break;
+        };
+
+        // notes are held until the next one, so only where they start matters
+        let fits = group
+            .iter()
+            .enumerate()
+            .all(|(i, n)| grid.snap(n.start, grid.triplet) == beat + i as u64 * grid.triplet)
+            && positions
+                .get(first + TRIPLET)
+                .is_none_or(|(start, _)| *start >= beat + grid.quarter);
+        let error = |step| -> f64 { group.iter().map(|n| grid.error(n.start, step)).sum() };
+
+        if fits && error(grid.triplet) < error(grid.straight) {
+            for (i, position) in positions[first..first + TRIPLET].iter_mut().enumerate() {
+                let start = beat + i as u64 * grid.triplet;
+                *position = (start, start + grid.triplet);
+            }
+            triplets.push(beat);
+        }
+    }
+
+    let mut segments = Vec::with_capacity(notes.len());
+    let mut cursor = 0;
+    for i in 0..notes.len() {
+        let (start, mut end) = positions[i];
+        if let Some((next, _)) = positions.get(i + 1) {
+            end = end.min(*next);
+        }
+        let start = start.max(cursor);
+        // notes that snapped onto the next one are dropped
+        if end <= start {
+            continue;
+        }
+
+        segments.push(Segment {
+            start,
+            end,
+            key: Some(notes[i].key),
+            tied: false,
+        });
+        cursor = end;
+    }
+
+    (segments, triplets)
+}
+
+/// Writes the pieces of a bar as notes, returning them with the indices of the
+/// notes tied to the one after them, or to the start of the next bar for the last one
+fn bar_notes(
+    pieces: &[Segment],
+    triplets: &[u64],
+    signature: &KeySignature,
+    grid: &Grid,
+) -> Result<(Vec<Note>, Vec<usize>), MidiError> {
+    let mut notes = Vec::new();
+    let mut tied = Vec::new();
+    let mut i = 0;
+
+    while i < pieces.len() {
+        let piece = pieces[i];
+
+        if triplets.contains(&piece.start) {
+            if let Some(group) = pieces.get(i..i + TRIPLET) {
+                let mut tuplet = Vec::with_capacity(TRIPLET);
+                for p in group {
+                    if let Some(key) = p.key {
+                        tuplet.push(Note::Note(NoteInfo::new(
+                            tone(key, signature)?,
+                            NoteSizeUnit::Quaver.into(),
+                        )));
+                    }
+                }
+                // only full triplets of notes, the ratio can't be wrong
+                if let Ok(tuplet) =
+                    Tuplet::new(TRIPLET as u8, 2, NoteSizeUnit::Quaver.into(), tuplet)
+                {
+                    notes.push(Note::Tuplet(tuplet));
+                    i += TRIPLET;
+                    continue;
+                }
+            }
+        }
+
+        // lengths that aren't a single note are written as notes tied together
+        let sizes = split_length(piece.end - piece.start, grid);
+        for (j, size) in sizes.iter().enumerate() {
+            notes.push(match piece.key {
+                Some(key) => Note::Note(NoteInfo::new(tone(key, signature)?, *size)),
+                None => Note::Rest(*size),
+            });
+            if piece.key.is_some() && (j + 1 < sizes.len() || piece.tied) {
+                tied.push(notes.len() - 1);
+            }
+        }
+        i += 1;
+    }
+
+    Ok((notes, tied))
+}
+
+/// Splits a length in the fewest note sizes, longest first
+fn split_length(length: u64, grid: &Grid) -> Vec<NoteSize> {
+    // lengths are on the straight grid here, so they always have sizes
+    Duration::new(length, grid.whole())
+        .to_note_sizes()
+        .unwrap_or_default()
+}
+
+/// Lines that mostly play from middle C up are written in treble clef, the others in bass clef
+fn pick_clef(notes: &[RawNote]) -> Clef {
+    let sum: u64 = notes.iter().map(|n| u64::from(n.key)).sum();
+    let average = sum / notes.len().max(1) as u64;
+
+    if average >= TREBLE_THRESHOLD {
+        Clef::Treble
+    } else {
+        Clef::Bass
+    }
+}
+
+/// Black keys are spelled with flats in keys with flats, and with sharps otherwise
+fn tone(key: u8, signature: &KeySignature) -> Result<Tone, MidiError> {
+    let tone = key
+        .checked_sub(MIDI_C0)
+        .and_then(|semitones| Tone::from_semitones_since_c0(u32::from(semitones)))
+        .ok_or(MidiError::NoteOutOfRange(key))?;
+
+    if signature.flats() == 0 || tone.modifiers != Some(ToneModifiers::Sharp) {
+        return Ok(tone);
+    }
+
+    // the sharp of a letter is the flat of the next one, and B has no sharp so it never wraps
+    let next = match tone.octave {
+        Octave::C => Octave::D,
+        Octave::D => Octave::E,
+        Octave::F => Octave::G,
+        Octave::G => Octave::A,
+        _ => Octave::B,
+    };
+    Ok(Tone::with_modifiers_unchecked(
+        next,
+        tone.position,
+        ToneModifiers::Flat,
+    ))
+}
@@ -0,0 +1,181 @@
+mod export;
+mod import;
+
+use rustsheet::{
+    notes::{NoteError, Tone},
+    tempo::{NoteSizeUnit, TimeSignature},
+    MusicSheet,
+};
+use thiserror::Error;
+
+#[derive(Default)]
+pub struct MusicSheetMidiExporter {}
+
+impl MusicSheetMidiExporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Converts the sheet to a Standard MIDI File (format 1).
+    ///
+    /// The first track holds the title, tempo and time signatures, then every line
+    /// gets its own track and channel. Tied notes are played as a single note,
+    /// staccato and marcato notes are cut short, accented ones are louder and
+    /// fermatas slow the tempo down to hold the music for twice as long.
+    ///
+    /// Repeats, endings and jumps are played out, in the
+    /// [performance order](rustsheet::bar::SingleLine::performance_order) of the first line.
+    /// The tempo follows the markings of the first line, and accelerandos and ritardandos
+    /// change it every quaver, up to the marking at their end or by a quarter without one.
+    ///
+    /// Dynamics set the velocity of the notes of their staff from where they're written,
+    /// and hairpins change it gradually, note by note, until the dynamic at their end,
+    /// or by about one level when there's none.
+    ///
+    /// Lyrics are lyric events on the track of their line, when the notes they're sung
+    /// on start, with a hyphen after the syllables that don't end a word. Repeated bars sing
+    /// their verses in turn, and the others the first verse they have.
+    ///
+    /// Transposing instruments are played at [concert pitch](MusicSheet::to_concert_pitch).
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Bar, BarHeader, Line, LineType, SingleLine},
+    ///     notes::{Note, NoteInfo},
+    ///     MusicSheet,
+    /// };
+    /// use rustsheet_midi::{MidiConfig, MusicSheetMidiExporter};
+    /// use rustsheet_utils::tones;
+    ///
+    /// fn main() {
+    ///     let bar = Bar::new(
+    ///         BarHeader::sane_default(),
+    ///         vec![Note::Note(NoteInfo {
+    ///             tone: tones::C3,
+    ///             ..Default::default()
+    ///         })],
+    ///     );
+    ///     let lines = vec![Line::new(LineType::Single(SingleLine { bars: vec![bar] }))];
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+    ///
+    ///     let exporter = MusicSheetMidiExporter::new();
+    ///     let midi = exporter.export_sheet(&sheet, &MidiConfig::default()).unwrap();
+    ///
+    ///     // every MIDI file starts with the header chunk
+    ///     assert_eq!(&midi[..4], b"MThd");
+    /// }
+    /// ```
+    pub fn export_sheet(
+        &self,
+        sheet: &MusicSheet,
+        config: &MidiConfig,
+    ) -> Result<Vec<u8>, MidiError> {
+        export::export_sheet(sheet, config)
+    }
+}
+
+#[derive(Default)]
+pub struct MusicSheetMidiImporter {}
+
+impl MusicSheetMidiImporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Reads a Standard MIDI File into a sheet.
+    ///
+    /// Every track (and every channel of a single track file) becomes a line named
+    /// after the track, in treble or bass clef depending on its average pitch.
+    /// The name of a first track without notes becomes the title of the sheet.
+    ///
+    /// Lines only hold one note at a time, so chords are reduced to their highest note.
+    /// Notes are snapped to [`MidiConfig::grid`], or to quaver triplets where they fit
+    /// better, and split in bars following the time signatures of the file.
+    /// Notes that cross a barline, or that can't be written as a single note, are
+    /// split in notes tied together.
+    /// The tempo at every barline where it changed becomes a marking on the first line.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Bar, BarHeader, Line, LineType, SingleLine},
+    ///     notes::{Note, NoteInfo},
+    ///     tempo::NoteSizeUnit,
+    ///     MusicSheet,
+    /// };
+    /// use rustsheet_midi::{MidiConfig, MusicSheetMidiExporter, MusicSheetMidiImporter};
+    /// use rustsheet_utils::tones;
+    ///
+    /// fn main() {
+    ///     let bar = Bar::new(
+    ///         BarHeader::sane_default(),
+    ///         vec![Note::Note(NoteInfo::new(tones::C3, NoteSizeUnit::Minim.into())); 2],
+    ///     );
+    ///     let lines = vec![Line::new(LineType::Single(SingleLine { bars: vec![bar] }))];
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+    ///
+    ///     let config = MidiConfig::default();
+    ///     let midi = MusicSheetMidiExporter::new().export_sheet(&sheet, &config).unwrap();
+    ///     let imported = MusicSheetMidiImporter::new().import_sheet(&midi, &config).unwrap();
+    ///
+    ///     assert_eq!(imported.title, "Example");
+    ///     let LineType::Single(staff) = &imported.lines[0].line else {
+    ///         panic!("MIDI lines are imported on a single staff");
+    ///     };
+    ///     assert_eq!(staff.bars.len(), 1);
+    ///     assert_eq!(staff.bars[0].voices[0].len(), 2);
+    /// }
+    /// ```
+    pub fn import_sheet(&self, data: &[u8], config: &MidiConfig) -> Result<MusicSheet, MidiError> {
+        import::import_sheet(data, config)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MidiConfig {
+    /// Resolution of the file, in ticks per quarter note
+    pub ticks_per_quarter: u16,
+    /// Quarter notes per minute before the first tempo marking
+    pub bpm: u32,
+    /// Velocity of the notes before the first dynamic, from 1 to 127
+    pub velocity: u8,
+    /// Length of grace notes, in ticks
+    pub grace_ticks: u32,
+    /// Shortest note that imported notes are snapped to, at most a crotchet
+    pub grid: NoteSizeUnit,
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            ticks_per_quarter: 480,
+            bpm: 120,
+            velocity: 80,
+            grace_ticks: 60,
+            grid: NoteSizeUnit::SemiQuaver,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MidiError {
+    #[error("Tone {0} is out of the MIDI range")]
+    ToneOutOfRange(Tone),
+    #[error("Couldn't transpose the sheet to concert pitch: {0}")]
+    ConcertPitch(NoteError),
+    #[error("Time signature {0} can't be represented in MIDI")]
+    UnsupportedTimeSignature(TimeSignature),
+    #[error("Too many lines, MIDI only has 16 channels")]
+    TooManyLines,
+    #[error("MIDI note {0} is out of the range of tones")]
+    NoteOutOfRange(u8),
+    #[error("Invalid time signature {0}/2^{1}")]
+    InvalidTimeSignature(u8, u8),
+    #[error("Invalid key signature with {0} fifths")]
+    InvalidKeySignature(i8),
+    #[error("Only files timed in ticks per quarter note are supported")]
+    UnsupportedTiming,
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(&'static str),
+    #[error("Couldn't write the MIDI file")]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't parse the MIDI file")]
+    Parse(#[from] midly::Error),
+}
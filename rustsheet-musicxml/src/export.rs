@@ -0,0 +1,976 @@
+use std::fmt::{Display, Write};
+
+use rustsheet::{
+    bar::{Bar, BarHeader, LineType, NotePosition, SingleLine, MAX_VOICES},
+    key::KeySignature,
+    lyrics::Lyric,
+    notes::{Articulation, Note, NoteInfo, Ornament, Tone, Tuplet},
+    repeat::{Marker, Volta},
+    span::{Span, SpanKind},
+    tempo::{Duration, NoteSize, NoteSizeModifiers, Tempo, TimeSignature},
+    transpose::Transposition,
+    MusicSheet,
+};
+
+use crate::{mapping, MusicXmlError};
+
+const DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#;
+const DOCTYPE: &str = r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#;
+const VERSION: &str = "4.0";
+const INDENT: &str = "  ";
+
+/// Just enough of an XML writer for MusicXML, elements only hold either text or other elements
+struct Writer {
+    out: String,
+    open: Vec<&'static str>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self {
+            out: format!("{}\n{}\n", DECLARATION, DOCTYPE),
+            open: Vec::new(),
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        for _ in &self.open {
+            self.out.push_str(INDENT);
+        }
+        self.out.push('<');
+        self.out.push_str(name);
+        for (key, value) in attributes {
+            // writing to a string can't fail
+            let _ = write!(self.out, " {}=\"{}\"", key, escape(value));
+        }
+    }
+
+    fn open(&mut self, name: &'static str, attributes: &[(&str, &str)]) {
+        self.start_tag(name, attributes);
+        self.out.push_str(">\n");
+        self.open.push(name);
+    }
+
+    fn close(&mut self) {
+        if let Some(name) = self.open.pop() {
+            for _ in &self.open {
+                self.out.push_str(INDENT);
+            }
+            let _ = writeln!(self.out, "</{}>", name);
+        }
+    }
+
+    fn empty(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.start_tag(name, attributes);
+        self.out.push_str("/>\n");
+    }
+
+    fn text(&mut self, name: &str, attributes: &[(&str, &str)], text: impl Display) {
+        self.start_tag(name, attributes);
+        let _ = writeln!(self.out, ">{}</{}>", escape(&text.to_string()), name);
+    }
+
+    fn finish(mut self) -> String {
+        while !self.open.is_empty() {
+            self.close();
+        }
+
+        self.out
+    }
+}
+
+/// A `<note>` element, tuplets and grace notes are written one note at a time
+struct XmlNote<'a> {
+    /// Rests don't have a tone
+    tone: Option<Tone>,
+    size: NoteSize,
+    grace: bool,
+    /// Whether the note is played with the one before it
+    chord: bool,
+    /// Actual and normal notes of all the tuplets the note is in, multiplied together
+    tuplet: Option<(u64, u64)>,
+    /// The tuplets that start on the note and their number, from the outermost
+    starts: Vec<(usize, &'a Tuplet)>,
+    /// Numbers of the tuplets that end on the note, from the innermost
+    stops: Vec<usize>,
+    /// Whether the tone is held from the note before
+    tie_stop: bool,
+    /// Whether the tone goes on into the next note
+    tie_start: bool,
+    /// Numbers of the slurs that end on the note
+    slur_stops: Vec<usize>,
+    /// Numbers of the slurs that start on the note
+    slur_starts: Vec<usize>,
+    articulations: &'a [Articulation],
+    ornament: Option<Ornament>,
+    /// Syllables sung on the note, every verse of it
+    lyrics: Vec<&'a Lyric>,
+}
+
+impl<'a> XmlNote<'a> {
+    fn new(tone: Option<Tone>, size: NoteSize) -> Self {
+        Self {
+            tone,
+            size,
+            grace: false,
+            chord: false,
+            tuplet: None,
+            starts: Vec::new(),
+            stops: Vec::new(),
+            tie_stop: false,
+            tie_start: false,
+            slur_stops: Vec::new(),
+            slur_starts: Vec::new(),
+            articulations: &[],
+            ornament: None,
+            lyrics: Vec::new(),
+        }
+    }
+
+    /// A note with its articulations and ornament
+    fn marked(info: &'a NoteInfo) -> Self {
+        Self {
+            articulations: &info.articulations,
+            ornament: info.ornament,
+            ..Self::new(Some(info.tone), info.size)
+        }
+    }
+}
+
+/// The ties, slurs, hairpins, tempo changes, dynamics and lyrics of the part being written
+struct PartSpans<'a> {
+    sheet: &'a MusicSheet,
+    /// Every slur of the part with its number, the lowest one not taken by another open slur
+    slurs: Vec<(Span, usize)>,
+    /// Every hairpin of the part, numbered like the slurs
+    wedges: Vec<(Span, usize)>,
+    /// Every accelerando and ritardando of the part, numbered like the slurs
+    tempo_changes: Vec<(Span, usize)>,
+}
+
+impl<'a> PartSpans<'a> {
+    fn new(sheet: &'a MusicSheet, line: usize) -> Self {
+        let spans = |kinds: &[SpanKind]| {
+            sheet
+                .spans
+                .iter()
+                .filter(|s| s.start.line == line && kinds.contains(&s.kind))
+                .copied()
+                .collect()
+        };
+
+        Self {
+            sheet,
+            slurs: number_spans(spans(&[SpanKind::Slur])),
+            wedges: number_spans(spans(&[SpanKind::Crescendo, SpanKind::Diminuendo])),
+            tempo_changes: number_spans(spans(&[SpanKind::Accelerando, SpanKind::Ritardando])),
+        }
+    }
+
+    /// Marks the elements of the note at `position` with its ties, slurs and lyrics
+    fn mark(&self, position: NotePosition, elements: &mut [XmlNote<'a>]) {
+        let from = self.sheet.tied_from_previous(position);
+        let into = self.sheet.tied_into_next(position);
+        for element in elements.iter_mut().filter(|e| !e.grace) {
+            if let Some(tone) = element.tone {
+                element.tie_stop = from.contains(&tone);
+                element.tie_start = into.contains(&tone);
+            }
+        }
+
+        // slurs start on the grace note if there's one, and end on the last note
+        for (span, number) in &self.slurs {
+            if span.start == position {
+                if let Some(first) = elements.first_mut() {
+                    first.slur_starts.push(*number);
+                }
+            }
+            if span.end == position {
+                if let Some(last) = elements.iter_mut().rev().find(|e| !e.chord) {
+                    last.slur_stops.push(*number);
+                }
+            }
+        }
+
+        // lyrics go on the note itself, not its grace note
+        if let Some(sung) = elements.iter_mut().find(|e| !e.grace) {
+            sung.lyrics = self
+                .sheet
+                .lyrics
+                .iter()
+                .filter(|lyric| lyric.position == position)
+                .collect();
+            sung.lyrics.sort_by_key(|lyric| lyric.verse);
+        }
+    }
+
+    /// Writes the hairpins and tempo changes that end or start on the note at `position`,
+    /// and its dynamics, as `<direction>` elements to put right before it
+    fn write_directions(
+        &self,
+        w: &mut Writer,
+        position: NotePosition,
+        staff: Option<usize>,
+        voice: usize,
+    ) {
+        // each of `types` writes the content of a `<direction-type>`
+        let direction = |w: &mut Writer, placement: &str, types: &[&dyn Fn(&mut Writer)]| {
+            w.open("direction", &[("placement", placement)]);
+            for write in types {
+                w.open("direction-type", &[]);
+                write(w);
+                w.close();
+            }
+            w.text("voice", &[], voice);
+            write_staff(w, staff);
+            w.close();
+        };
+
+        for (_, number) in self.wedges.iter().filter(|(span, _)| span.end == position) {
+            let number = number.to_string();
+            direction(
+                w,
+                "below",
+                &[&|w| w.empty("wedge", &[("type", "stop"), ("number", &number)])],
+            );
+        }
+        for (span, number) in self
+            .wedges
+            .iter()
+            .filter(|(span, _)| span.start == position)
+        {
+            let kind = if span.kind == SpanKind::Crescendo {
+                "crescendo"
+            } else {
+                "diminuendo"
+            };
+            let number = number.to_string();
+            direction(
+                w,
+                "below",
+                &[&|w| w.empty("wedge", &[("type", kind), ("number", &number)])],
+            );
+        }
+
+        // tempo changes are written as words followed by a dashed line
+        for (_, number) in self
+            .tempo_changes
+            .iter()
+            .filter(|(span, _)| span.end == position)
+        {
+            let number = number.to_string();
+            direction(
+                w,
+                "above",
+                &[&|w| w.empty("dashes", &[("type", "stop"), ("number", &number)])],
+            );
+        }
+        for (span, number) in self
+            .tempo_changes
+            .iter()
+            .filter(|(span, _)| span.start == position)
+        {
+            let words = if span.kind == SpanKind::Ritardando {
+                "rit."
+            } else {
+                "accel."
+            };
+            let number = number.to_string();
+            direction(
+                w,
+                "above",
+                &[&|w| w.text("words", &[], words), &|w| {
+                    w.empty("dashes", &[("type", "start"), ("number", &number)])
+                }],
+            );
+        }
+        for mark in self
+            .sheet
+            .dynamics
+            .iter()
+            .filter(|m| m.position == position)
+        {
+            direction(
+                w,
+                "below",
+                &[&|w| {
+                    w.open("dynamics", &[]);
+                    w.empty(mark.dynamic.symbol(), &[]);
+                    w.close();
+                }],
+            );
+        }
+    }
+}
+
+/// Numbers slurs or hairpins, each with the lowest number not taken by an open one
+fn number_spans(mut spans: Vec<Span>) -> Vec<(Span, usize)> {
+    // where the note is written in the part, measure by measure
+    let order = |p: NotePosition| (p.bar, p.staff, p.voice, p.index);
+    spans.sort_by_key(|s| order(s.start));
+
+    let mut numbered: Vec<(Span, usize)> = Vec::with_capacity(spans.len());
+    for span in spans {
+        // the ones that end where this one starts are closed first
+        let number = (1..)
+            .find(|n| {
+                !numbered
+                    .iter()
+                    .any(|(other, m)| m == n && order(other.end) > order(span.start))
+            })
+            .unwrap();
+        numbered.push((span, number));
+    }
+
+    numbered
+}
+
+pub fn export_sheet(sheet: &MusicSheet) -> Result<String, MusicXmlError> {
+    // parts are written as the players read them, with how they sound in <transpose>
+    let sheet = &sheet
+        .to_written_pitch()
+        .map_err(MusicXmlError::WrittenPitch)?;
+    // every length has to be a whole number of divisions
+    let divisions = divisions(sheet);
+
+    let mut w = Writer::new();
+    w.open("score-partwise", &[("version", VERSION)]);
+
+    w.open("work", &[]);
+    w.text("work-title", &[], &sheet.title);
+    w.close();
+    if let Some(subtitle) = &sheet.subtitle {
+        w.text("movement-title", &[], subtitle);
+    }
+    w.open("identification", &[]);
+    w.text("creator", &[("type", "composer")], &sheet.author);
+    w.close();
+
+    w.open("part-list", &[]);
+    for (i, line) in sheet.lines.iter().enumerate() {
+        w.open("score-part", &[("id", &part_id(i))]);
+        w.text("part-name", &[], line.name.as_deref().unwrap_or_default());
+        w.close();
+    }
+    w.close();
+
+    for (i, line) in sheet.lines.iter().enumerate() {
+        w.open("part", &[("id", &part_id(i))]);
+
+        let staves = staves(&line.line);
+        let spans = PartSpans::new(sheet, i);
+        let measures = staves.iter().map(|s| s.bars.len()).max().unwrap_or(0);
+        let mut time_signatures = vec![TimeSignature::default(); staves.len()];
+
+        for m in 0..measures {
+            w.open("measure", &[("number", &(m + 1).to_string())]);
+
+            let bars: Vec<Option<&Bar>> = staves.iter().map(|s| s.bars.get(m)).collect();
+            for (ts, bar) in time_signatures.iter_mut().zip(&bars) {
+                if let Some(signature) = bar.and_then(|b| b.header.time_signature) {
+                    *ts = signature;
+                }
+            }
+
+            // repeats, endings and jumps are read from the upper staff
+            let header = |m: usize| staves[0].bars.get(m).map(|b| &b.header);
+            let upper = header(m).cloned().unwrap_or_default();
+            let previous_volta = m
+                .checked_sub(1)
+                .and_then(header)
+                .and_then(|h| h.volta.as_ref());
+            let next_volta = header(m + 1).and_then(|h| h.volta.as_ref());
+            let direction_staff = (staves.len() > 1).then_some(1);
+
+            write_left_barline(&mut w, &upper, previous_volta);
+            let transposition = line.transposition.filter(|_| m == 0);
+            write_attributes(&mut w, m == 0, &bars, divisions, transposition)?;
+            if let Some(tempo) = &upper.tempo {
+                write_tempo(&mut w, tempo, direction_staff);
+            }
+            write_markers(&mut w, &upper, direction_staff, false);
+
+            let mut previous = Duration::ZERO;
+            for (s, bar) in bars.iter().enumerate() {
+                // go back to the start of the measure for the next staff
+                if s > 0 {
+                    w.open("backup", &[]);
+                    w.text("duration", &[], mapping::in_divisions(previous, divisions));
+                    w.close();
+                }
+
+                let staff = (staves.len() > 1).then_some(s + 1);
+                let position = NotePosition {
+                    line: i,
+                    staff: s,
+                    bar: m,
+                    voice: 0,
+                    index: 0,
+                };
+                previous = write_bar(
+                    &mut w,
+                    *bar,
+                    &time_signatures[s],
+                    staff,
+                    divisions,
+                    &spans,
+                    position,
+                )?;
+            }
+
+            write_markers(&mut w, &upper, direction_staff, true);
+            write_right_barline(&mut w, &upper, next_volta);
+            w.close();
+        }
+
+        w.close();
+    }
+
+    Ok(w.finish())
+}
+
+fn write_attributes(
+    w: &mut Writer,
+    first: bool,
+    bars: &[Option<&Bar>],
+    divisions: u64,
+    transposition: Option<Transposition>,
+) -> Result<(), MusicXmlError> {
+    let headers: Vec<_> = bars.iter().map(|b| b.map(|b| &b.header)).collect();
+    let upper = headers
+        .first()
+        .copied()
+        .flatten()
+        .cloned()
+        .unwrap_or_default();
+
+    // the key signature is always written at the start, then only when it changes
+    let key = upper
+        .key_signature
+        .or_else(|| first.then(KeySignature::default));
+    let clefs: Vec<_> = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(s, h)| h.and_then(|h| h.clef).map(|c| (s, c)))
+        .collect();
+
+    if !first && key.is_none() && upper.time_signature.is_none() && clefs.is_empty() {
+        return Ok(());
+    }
+
+    w.open("attributes", &[]);
+    if first {
+        w.text("divisions", &[], divisions);
+    }
+    if let Some(key) = key {
+        w.open("key", &[]);
+        w.text("fifths", &[], key.fifths());
+        w.text("mode", &[], key.mode());
+        w.close();
+    }
+    if let Some(ts) = upper.time_signature {
+        w.open("time", &[]);
+        w.text("beats", &[], ts.num);
+        w.text("beat-type", &[], ts.den);
+        w.close();
+    }
+    if first && bars.len() > 1 {
+        w.text("staves", &[], bars.len());
+    }
+    for (s, clef) in clefs {
+        let (sign, line) = mapping::clef_sign(&clef).ok_or(MusicXmlError::UnsupportedClef(clef))?;
+        let number = (s + 1).to_string();
+        let attributes: &[(&str, &str)] = if bars.len() > 1 {
+            &[("number", &number)]
+        } else {
+            &[]
+        };
+        w.open("clef", attributes);
+        w.text("sign", &[], sign);
+        w.text("line", &[], line);
+        w.close();
+    }
+    if let Some(transposition) = transposition {
+        // within an octave, and the octaves apart
+        let (steps, semitones) = transposition.interval();
+        let octaves = steps / 7;
+        w.open("transpose", &[]);
+        w.text("diatonic", &[], steps - 7 * octaves);
+        w.text("chromatic", &[], semitones - 12 * octaves);
+        if octaves != 0 {
+            w.text("octave-change", &[], octaves);
+        }
+        w.close();
+    }
+    w.close();
+
+    Ok(())
+}
+
+/// Writes the start of a repeat and of an ending, unless the ending goes on from
+/// the previous measure
+fn write_left_barline(w: &mut Writer, header: &BarHeader, previous_volta: Option<&Volta>) {
+    let volta = header.volta.as_ref().filter(|v| Some(*v) != previous_volta);
+    if !header.repeat_start && volta.is_none() {
+        return;
+    }
+
+    w.open("barline", &[("location", "left")]);
+    if header.repeat_start {
+        w.text("bar-style", &[], "heavy-light");
+    }
+    if let Some(volta) = volta {
+        w.text(
+            "ending",
+            &[("number", &ending_number(volta)), ("type", "start")],
+            volta,
+        );
+    }
+    if header.repeat_start {
+        w.empty("repeat", &[("direction", "forward")]);
+    }
+    w.close();
+}
+
+/// Writes the end of a repeat and of an ending, unless the ending goes on in
+/// the next measure. Endings that go back to the start of the repeat are closed.
+fn write_right_barline(w: &mut Writer, header: &BarHeader, next_volta: Option<&Volta>) {
+    let volta = header.volta.as_ref().filter(|v| Some(*v) != next_volta);
+    if header.repeat_end.is_none() && volta.is_none() {
+        return;
+    }
+
+    w.open("barline", &[("location", "right")]);
+    if header.repeat_end.is_some() {
+        w.text("bar-style", &[], "light-heavy");
+    }
+    if let Some(volta) = volta {
+        let kind = if header.repeat_end.is_some() {
+            "stop"
+        } else {
+            "discontinue"
+        };
+        w.empty(
+            "ending",
+            &[("number", &ending_number(volta)), ("type", kind)],
+        );
+    }
+    if let Some(times) = header.repeat_end {
+        let times = times.to_string();
+        w.empty("repeat", &[("direction", "backward"), ("times", &times)]);
+    }
+    w.close();
+}
+
+/// The passes of an ending, like `1, 2`
+fn ending_number(volta: &Volta) -> String {
+    let passes: Vec<String> = volta.passes.iter().map(u8::to_string).collect();
+    passes.join(", ")
+}
+
+/// Writes the segno and coda signs of the measure at its start, or its fine,
+/// "To Coda" and jump at its `end`, each with the `<sound>` that plays it
+fn write_markers(w: &mut Writer, header: &BarHeader, staff: Option<usize>, end: bool) {
+    let direction = |w: &mut Writer, write: &dyn Fn(&mut Writer), sound: (&str, &str)| {
+        w.open("direction", &[("placement", "above")]);
+        w.open("direction-type", &[]);
+        write(w);
+        w.close();
+        write_staff(w, staff);
+        w.empty("sound", &[sound]);
+        w.close();
+    };
+
+    for marker in &header.markers {
+        match (marker, end) {
+            (Marker::Segno, false) => {
+                direction(w, &|w| w.empty("segno", &[]), ("segno", "segno"));
+            }
+            (Marker::Coda, false) => direction(w, &|w| w.empty("coda", &[]), ("coda", "coda")),
+            (Marker::Fine, true) => {
+                direction(w, &|w| w.text("words", &[], "Fine"), ("fine", "yes"));
+            }
+            (Marker::ToCoda, true) => {
+                direction(w, &|w| w.text("words", &[], "To Coda"), ("tocoda", "coda"));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(jump) = header.jump.filter(|_| end) {
+        let sound = if jump.to_segno() {
+            ("dalsegno", "segno")
+        } else {
+            ("dacapo", "yes")
+        };
+        direction(w, &|w| w.text("words", &[], jump.text()), sound);
+    }
+}
+
+/// Writes a tempo marking as its words and a metronome mark over the staff,
+/// with the tempo it's played at in crotchets per minute
+fn write_tempo(w: &mut Writer, tempo: &Tempo, staff: Option<usize>) {
+    w.open("direction", &[("placement", "above")]);
+    if let Some(text) = &tempo.text {
+        w.open("direction-type", &[]);
+        w.text("words", &[], text);
+        w.close();
+    }
+    w.open("direction-type", &[]);
+    w.open("metronome", &[]);
+    w.text("beat-unit", &[], mapping::type_name(tempo.beat.unit));
+    for _ in 0..mapping::dots(tempo.beat.modifiers) {
+        w.empty("beat-unit-dot", &[]);
+    }
+    w.text("per-minute", &[], tempo.bpm);
+    w.close();
+    w.close();
+    write_staff(w, staff);
+    w.empty(
+        "sound",
+        &[("tempo", &format!("{}", tempo.quarters_per_minute()))],
+    );
+    w.close();
+}
+
+/// Writes the notes of one staff in the measure, voice after voice,
+/// returning how long the last one is
+fn write_bar(
+    w: &mut Writer,
+    bar: Option<&Bar>,
+    ts: &TimeSignature,
+    staff: Option<usize>,
+    divisions: u64,
+    spans: &PartSpans,
+    position: NotePosition,
+) -> Result<Duration, MusicXmlError> {
+    // every staff has its own voice numbers
+    let first_voice = staff.map_or(0, |s| (s - 1) * MAX_VOICES) + 1;
+    let voices: Vec<(usize, &[Note])> = bar.map(|b| b.used_voices().collect()).unwrap_or_default();
+
+    // empty bars get a whole measure rest, so that the staves stay in time
+    if voices.is_empty() {
+        let length = ts.bar_duration();
+        w.open("note", &[]);
+        w.empty("rest", &[("measure", "yes")]);
+        w.text("duration", &[], mapping::in_divisions(length, divisions));
+        w.text("voice", &[], first_voice);
+        write_staff(w, staff);
+        w.close();
+
+        return Ok(length);
+    }
+
+    let mut previous = Duration::ZERO;
+    for (i, (v, notes)) in voices.into_iter().enumerate() {
+        // go back to the start of the measure for the next voice
+        if i > 0 {
+            w.open("backup", &[]);
+            w.text("duration", &[], mapping::in_divisions(previous, divisions));
+            w.close();
+        }
+
+        for (index, note) in notes.iter().enumerate() {
+            let mut elements = Vec::new();
+            xml_notes(std::slice::from_ref(note), None, 0, &mut elements);
+            let position = NotePosition {
+                voice: v,
+                index,
+                ..position
+            };
+            spans.mark(position, &mut elements);
+            spans.write_directions(w, position, staff, first_voice + v);
+            for element in &elements {
+                write_note(w, element, staff, first_voice + v, divisions)?;
+            }
+        }
+        previous = notes.iter().flat_map(mapping::note_durations).sum();
+    }
+
+    Ok(previous)
+}
+
+/// Takes the notes apart into `<note>` elements, `depth` tuplets deep
+fn xml_notes<'a>(
+    notes: &'a [Note],
+    tuplet: Option<(u64, u64)>,
+    depth: usize,
+    elements: &mut Vec<XmlNote<'a>>,
+) {
+    for note in notes {
+        match note {
+            Note::Note(info) => elements.push(XmlNote {
+                tuplet,
+                ..XmlNote::marked(info)
+            }),
+            Note::Chord(chord) => {
+                for (i, tone) in chord.tones().iter().enumerate() {
+                    elements.push(XmlNote {
+                        chord: i > 0,
+                        tuplet,
+                        ..XmlNote::new(Some(*tone), chord.size)
+                    });
+                }
+            }
+            Note::Tuplet(inner) => {
+                let (actual, normal) = tuplet.unwrap_or((1, 1));
+                let ratio = (
+                    actual * u64::from(inner.actual()),
+                    normal * u64::from(inner.normal()),
+                );
+                let first = elements.len();
+                xml_notes(inner.notes(), Some(ratio), depth + 1, elements);
+
+                // brackets go from the first note that takes time to the last one,
+                // the first note of a chord holds them for the others
+                let number = depth + 1;
+                if let Some(start) = elements[first..].iter_mut().find(|n| !n.grace) {
+                    start.starts.insert(0, (number, inner));
+                }
+                if let Some(stop) = elements[first..].iter_mut().rev().find(|n| !n.chord) {
+                    stop.stops.push(number);
+                }
+            }
+            Note::WithGrace { grace, note } => {
+                elements.push(XmlNote {
+                    grace: true,
+                    ..XmlNote::new(Some(*grace), NoteSize::default())
+                });
+                elements.push(XmlNote {
+                    tuplet,
+                    ..XmlNote::marked(note)
+                });
+            }
+            Note::Rest(size) => elements.push(XmlNote {
+                tuplet,
+                ..XmlNote::new(None, *size)
+            }),
+        }
+    }
+}
+
+fn write_note(
+    w: &mut Writer,
+    note: &XmlNote,
+    staff: Option<usize>,
+    voice: usize,
+    divisions: u64,
+) -> Result<(), MusicXmlError> {
+    w.open("note", &[]);
+
+    if note.grace {
+        w.empty("grace", &[("slash", "yes")]);
+    }
+    if note.chord {
+        w.empty("chord", &[]);
+    }
+
+    match note.tone {
+        Some(tone) => {
+            if tone.position > mapping::MAX_OCTAVE {
+                return Err(MusicXmlError::ToneOutOfRange(tone));
+            }
+
+            w.open("pitch", &[]);
+            w.text("step", &[], tone.octave);
+            let alter = mapping::alter(&tone);
+            if alter != 0 {
+                w.text("alter", &[], alter);
+            }
+            w.text("octave", &[], tone.position);
+            w.close();
+        }
+        None => w.empty("rest", &[]),
+    }
+
+    // grace notes take no time
+    if !note.grace {
+        let (actual, normal) = note.tuplet.unwrap_or((1, 1));
+        let length = note.size.duration().scale(normal, actual);
+        w.text("duration", &[], mapping::in_divisions(length, divisions));
+    }
+    if note.tie_stop {
+        w.empty("tie", &[("type", "stop")]);
+    }
+    if note.tie_start {
+        w.empty("tie", &[("type", "start")]);
+    }
+
+    w.text("voice", &[], voice);
+    if note.grace {
+        w.text("type", &[], "eighth");
+    } else {
+        w.text("type", &[], mapping::type_name(note.size.unit));
+        for _ in 0..mapping::dots(note.size.modifiers) {
+            w.empty("dot", &[]);
+        }
+    }
+
+    if let Some(modifiers) = note.tone.and_then(|t| t.modifiers) {
+        w.text("accidental", &[], mapping::accidental_name(modifiers));
+    }
+
+    if let Some((actual, normal)) = note.tuplet {
+        w.open("time-modification", &[]);
+        w.text("actual-notes", &[], actual);
+        w.text("normal-notes", &[], normal);
+        w.close();
+    }
+
+    write_staff(w, staff);
+
+    let fermata = matches!(note.size.modifiers, Some(NoteSizeModifiers::Crown))
+        || note.articulations.contains(&Articulation::Fermata);
+    let articulations: Vec<&str> = note
+        .articulations
+        .iter()
+        .filter_map(|a| mapping::articulation_name(*a))
+        .collect();
+    let notations = !note.starts.is_empty()
+        || !note.stops.is_empty()
+        || note.tie_stop
+        || note.tie_start
+        || !note.slur_stops.is_empty()
+        || !note.slur_starts.is_empty()
+        || !articulations.is_empty()
+        || note.ornament.is_some()
+        || fermata;
+    if notations {
+        w.open("notations", &[]);
+        if note.tie_stop {
+            w.empty("tied", &[("type", "stop")]);
+        }
+        if note.tie_start {
+            w.empty("tied", &[("type", "start")]);
+        }
+        for number in &note.slur_stops {
+            w.empty("slur", &[("type", "stop"), ("number", &number.to_string())]);
+        }
+        for number in &note.slur_starts {
+            w.empty(
+                "slur",
+                &[("type", "start"), ("number", &number.to_string())],
+            );
+        }
+        for (number, tuplet) in &note.starts {
+            let number = number.to_string();
+            w.open(
+                "tuplet",
+                &[("type", "start"), ("number", &number), ("bracket", "yes")],
+            );
+            write_tuplet_portion(w, "tuplet-actual", tuplet.actual(), &tuplet.unit());
+            write_tuplet_portion(w, "tuplet-normal", tuplet.normal(), &tuplet.unit());
+            w.close();
+        }
+        for number in &note.stops {
+            w.empty(
+                "tuplet",
+                &[("type", "stop"), ("number", &number.to_string())],
+            );
+        }
+        if !articulations.is_empty() {
+            w.open("articulations", &[]);
+            for name in articulations {
+                w.empty(name, &[]);
+            }
+            w.close();
+        }
+        if let Some(ornament) = note.ornament {
+            w.open("ornaments", &[]);
+            w.empty(mapping::ornament_name(ornament), &[]);
+            w.close();
+        }
+        if fermata {
+            w.empty("fermata", &[("type", "upright")]);
+        }
+        w.close();
+    }
+
+    for lyric in &note.lyrics {
+        w.open(
+            "lyric",
+            &[("number", &(u16::from(lyric.verse) + 1).to_string())],
+        );
+        w.text("syllabic", &[], mapping::syllabic_name(lyric.syllabic));
+        w.text("text", &[], &lyric.text);
+        if lyric.extend {
+            w.empty("extend", &[]);
+        }
+        w.close();
+    }
+
+    w.close();
+
+    Ok(())
+}
+
+/// Writes `<tuplet-actual>` or `<tuplet-normal>`, with the number of units and their size
+fn write_tuplet_portion(w: &mut Writer, name: &'static str, count: u8, unit: &NoteSize) {
+    w.open(name, &[]);
+    w.text("tuplet-number", &[], count);
+    w.text("tuplet-type", &[], mapping::type_name(unit.unit));
+    for _ in 0..mapping::dots(unit.modifiers) {
+        w.empty("tuplet-dot", &[]);
+    }
+    w.close();
+}
+
+fn write_staff(w: &mut Writer, staff: Option<usize>) {
+    if let Some(staff) = staff {
+        w.text("staff", &[], staff);
+    }
+}
+
+fn part_id(line: usize) -> String {
+    format!("P{}", line + 1)
+}
+
+fn staves(line: &LineType) -> Vec<&SingleLine> {
+    match line {
+        LineType::Single(staff) => vec![staff],
+        LineType::Double(upper, lower) => vec![upper, lower],
+    }
+}
+
+/// The fewest divisions of a quarter note that every note and bar of the sheet
+/// lasts a whole number of
+fn divisions(sheet: &MusicSheet) -> u64 {
+    let mut divisions = 1;
+    let mut add = |duration: Duration| {
+        let den = duration.scale(4, 1).den();
+        divisions = divisions / gcd(divisions, den) * den;
+    };
+
+    for staff in sheet.lines.iter().flat_map(|l| staves(&l.line)) {
+        for bar in &staff.bars {
+            if let Some(ts) = bar.header.time_signature {
+                add(ts.bar_duration());
+            }
+            for duration in bar
+                .voices
+                .iter()
+                .flatten()
+                .flat_map(mapping::note_durations)
+            {
+                add(duration);
+            }
+        }
+    }
+
+    divisions
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
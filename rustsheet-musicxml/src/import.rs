@@ -0,0 +1,1183 @@
+use std::str::FromStr;
+
+use roxmltree::{Document, Node, ParsingOptions};
+use rustsheet::{
+    bar::{Bar, BarHeader, Line, LineType, NotePosition, SingleLine, MAX_VOICES},
+    dynamics::{Dynamic, DynamicMark},
+    key::{KeySignature, Mode},
+    lyrics::{Lyric, Syllabic},
+    notes::{Articulation, Chord, Note, NoteInfo, Octave, Ornament, Tone, ToneModifiers, Tuplet},
+    repeat::{Jump, Marker, Volta},
+    span::{Span, SpanKind},
+    tempo::{Duration, NoteSize, NoteSizeModifiers, NoteSizeUnit, Tempo, TimeSignature},
+    transpose::Transposition,
+    MusicSheet,
+};
+
+use crate::{mapping, MusicXmlError};
+
+/// Where in the document the measure being read is, for errors
+struct Context<'a> {
+    part: &'a str,
+    measure: &'a str,
+}
+
+impl Context<'_> {
+    fn unsupported(&self, construct: &'static str) -> MusicXmlError {
+        MusicXmlError::Unsupported {
+            part: self.part.to_string(),
+            measure: self.measure.to_string(),
+            construct,
+        }
+    }
+}
+
+/// A `<note>` element, before grace notes and tuplets are grouped
+struct Item {
+    /// Rests don't have a tone
+    tone: Option<Tone>,
+    /// The other tones of a chord, from the `<note>` elements marked with `<chord/>`
+    chord: Vec<Tone>,
+    size: NoteSize,
+    grace: bool,
+    /// Actual and normal notes of all the tuplets the note is in, multiplied together
+    tuplet: Option<(u64, u64)>,
+    /// Tuplets whose bracket starts on the note, from the outermost
+    starts: Vec<TupletStart>,
+    /// How many tuplet brackets end on the note
+    stops: usize,
+    marks: Marks,
+    /// With the fermata, which only notes can hold, chords and rests get a crown instead
+    articulations: Vec<Articulation>,
+    ornament: Option<Ornament>,
+}
+
+/// The ties, slurs, hairpins, tempo changes, dynamics and lyrics on a note, until the whole
+/// part is read and they can be matched up
+#[derive(Default)]
+struct Marks {
+    /// Whether the note is tied to the next one
+    tie: bool,
+    /// Numbers of the slurs that end on the note
+    slur_stops: Vec<String>,
+    /// Numbers of the slurs that start on the note
+    slur_starts: Vec<String>,
+    /// Numbers of the hairpins that end on the note
+    wedge_stops: Vec<String>,
+    /// Numbers and kinds of the hairpins that start on the note
+    wedge_starts: Vec<(String, SpanKind)>,
+    /// Numbers of the dashed lines of tempo changes that end on the note
+    dashes_stops: Vec<String>,
+    /// Numbers and kinds of the tempo changes that start on the note
+    dashes_starts: Vec<(String, SpanKind)>,
+    dynamics: Vec<Dynamic>,
+    /// Verse, text, place in the word and extender of every syllable sung on the note
+    lyrics: Vec<(u8, String, Syllabic, bool)>,
+}
+
+impl Marks {
+    fn extend(&mut self, other: Marks) {
+        self.tie |= other.tie;
+        self.slur_stops.extend(other.slur_stops);
+        self.slur_starts.extend(other.slur_starts);
+        self.wedge_stops.extend(other.wedge_stops);
+        self.wedge_starts.extend(other.wedge_starts);
+        self.dashes_stops.extend(other.dashes_stops);
+        self.dashes_starts.extend(other.dashes_starts);
+        self.dynamics.extend(other.dynamics);
+        self.lyrics.extend(other.lyrics);
+    }
+}
+
+/// The ratio and unit of a tuplet bracket, when they're written out
+#[derive(Debug, Clone, Copy, Default)]
+struct TupletStart {
+    ratio: Option<(u8, u8)>,
+    unit: Option<NoteSize>,
+}
+
+/// A tuplet being read, until its bracket ends
+struct OpenTuplet {
+    ratio: (u8, u8),
+    unit: Option<NoteSize>,
+    /// Notes with a `<time-modification>` and no bracket, the tuplet ends after `actual` notes
+    implicit: bool,
+    notes: Vec<Note>,
+}
+
+pub fn import_sheet(xml: &str) -> Result<MusicSheet, MusicXmlError> {
+    // most files come with a doctype, which is only read and never resolved
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let doc = Document::parse_with_options(xml, options)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("score-partwise") {
+        return Err(MusicXmlError::NotPartwise(
+            root.tag_name().name().to_string(),
+        ));
+    }
+
+    let work_title = child(root, "work").and_then(|w| text(w, "work-title"));
+    let movement_title = text(root, "movement-title");
+    let (title, subtitle) = match work_title {
+        Some(title) => (title, movement_title),
+        None => (movement_title.unwrap_or_default(), None),
+    };
+
+    // the composer, or whoever is credited first
+    let author = child(root, "identification")
+        .and_then(|i| {
+            let creators = i.children().filter(|c| c.has_tag_name("creator"));
+            creators
+                .clone()
+                .find(|c| c.attribute("type") == Some("composer"))
+                .or_else(|| creators.clone().next())
+        })
+        .and_then(|c| c.text())
+        .map(|t| t.trim().to_string())
+        .unwrap_or_default();
+
+    let part_list = child(root, "part-list").ok_or(MusicXmlError::MissingElement("part-list"))?;
+    let names: Vec<(&str, Option<String>)> = part_list
+        .children()
+        .filter(|c| c.has_tag_name("score-part"))
+        .map(|p| {
+            let name = text(p, "part-name").filter(|n| !n.is_empty());
+            (p.attribute("id").unwrap_or_default(), name)
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut spans = Vec::new();
+    let mut dynamics = Vec::new();
+    let mut lyrics = Vec::new();
+    for part in root.children().filter(|c| c.has_tag_name("part")) {
+        let id = part.attribute("id").unwrap_or_default();
+        let name = names
+            .iter()
+            .find(|(part_id, _)| *part_id == id)
+            .map(|(_, name)| name.clone())
+            .ok_or_else(|| MusicXmlError::UnknownPart(id.to_string()))?;
+
+        let (line, (part_spans, part_dynamics, part_lyrics)) = read_part(part, id)?;
+        spans.extend(part_spans.into_iter().map(|mut span| {
+            span.start.line = lines.len();
+            span.end.line = lines.len();
+            span
+        }));
+        dynamics.extend(part_dynamics.into_iter().map(|mut mark| {
+            mark.position.line = lines.len();
+            mark
+        }));
+        lyrics.extend(part_lyrics.into_iter().map(|mut lyric| {
+            lyric.position.line = lines.len();
+            lyric
+        }));
+        lines.push(Line {
+            name,
+            transposition: read_transpose(part)?,
+            ..Line::new(line)
+        });
+    }
+
+    let mut sheet = MusicSheet {
+        title,
+        subtitle,
+        author,
+        lines,
+        spans: Vec::new(),
+        dynamics,
+        lyrics: Vec::new(),
+    };
+    // ties and slurs that the sheet can't hold, like ties into tuplets, are left out,
+    // and so are syllables on notes inside tuplets
+    sheet.spans = spans
+        .into_iter()
+        .filter(|span| span.check(&sheet).is_ok())
+        .collect();
+    sheet.lyrics = lyrics
+        .into_iter()
+        .filter(|lyric| {
+            matches!(
+                sheet.note(lyric.position),
+                Some(Note::Note(_) | Note::Chord(_) | Note::WithGrace { .. })
+            )
+        })
+        .collect();
+
+    Ok(sheet)
+}
+
+/// Reads the staves of a part, with the ties, slurs, hairpins, dynamics and lyrics on its notes
+/// on line 0
+fn read_part(part: Node, id: &str) -> Result<(LineType, PartMarks), MusicXmlError> {
+    let mut divisions: u64 = 1;
+    let mut staff_count = 1;
+    let mut staves = [
+        SingleLine { bars: Vec::new() },
+        SingleLine { bars: Vec::new() },
+    ];
+    let mut marked: Vec<(NotePosition, Marks)> = Vec::new();
+    // directions go on the next note of their staff, even in a later measure
+    let mut directions: [Marks; 2] = Default::default();
+    // the ending being read, until the barline that stops it
+    let mut ending: Option<Volta> = None;
+
+    for measure in part.children().filter(|c| c.has_tag_name("measure")) {
+        let ctx = Context {
+            part: id,
+            measure: measure.attribute("number").unwrap_or("?"),
+        };
+        let mut headers: [BarHeader; 2] = Default::default();
+        // the notes of every voice of each staff, in the order the voices first appear
+        let mut voices: [Vec<(String, Vec<Item>)>; 2] = Default::default();
+        let mut started = false;
+        let mut ending_stops = false;
+
+        for element in measure.children().filter(Node::is_element) {
+            match element.tag_name().name() {
+                "attributes" => {
+                    if let Some(d) = number::<u64>(element, "divisions")? {
+                        if d == 0 {
+                            return Err(MusicXmlError::InvalidValue("divisions", d.to_string()));
+                        }
+                        divisions = d;
+                    }
+
+                    let changes = ["key", "time", "clef", "staves"]
+                        .iter()
+                        .any(|name| child(element, name).is_some());
+                    if started && changes {
+                        return Err(ctx.unsupported("changes in the middle of a measure"));
+                    }
+
+                    if let Some(count) = number::<usize>(element, "staves")? {
+                        if count > 2 {
+                            return Err(ctx.unsupported("more than two staves"));
+                        }
+                        staff_count = count.max(1);
+                    }
+                    read_attributes(element, &mut headers, &ctx)?;
+                }
+                "note" => {
+                    started = true;
+                    let staff = staff_index(element, staff_count)?;
+                    let items = voice_items(&mut voices[staff], element, &ctx)?;
+
+                    let chord = child(element, "chord").is_some();
+                    match read_note(element, divisions, &ctx)? {
+                        // the notes of a chord come one after the other, all but the first marked
+                        Some(item) if chord => {
+                            if item.grace {
+                                return Err(ctx.unsupported("grace chords"));
+                            }
+                            let tone = item
+                                .tone
+                                .ok_or_else(|| ctx.unsupported("rests in chords"))?;
+                            let previous = items
+                                .last_mut()
+                                .filter(|p| p.tone.is_some() && !p.grace)
+                                .ok_or_else(|| ctx.unsupported("chords without a first note"))?;
+                            if previous.size.duration() != item.size.duration()
+                                || previous.tuplet != item.tuplet
+                            {
+                                return Err(
+                                    ctx.unsupported("chords of notes with different lengths")
+                                );
+                            }
+                            previous.chord.push(tone);
+                            previous.starts.extend(item.starts);
+                            previous.stops += item.stops;
+                            previous.marks.extend(item.marks);
+                            for articulation in item.articulations {
+                                if !previous.articulations.contains(&articulation) {
+                                    previous.articulations.push(articulation);
+                                }
+                            }
+                        }
+                        Some(mut item) => {
+                            item.marks.extend(std::mem::take(&mut directions[staff]));
+                            items.push(item);
+                        }
+                        None => {}
+                    }
+                }
+                "barline" => ending_stops |= read_barline(element, &mut headers, &mut ending),
+                "direction" => {
+                    let staff = staff_index(element, staff_count)?;
+                    read_direction(element, &mut directions[staff], &mut headers[staff]);
+                }
+                "forward" => {
+                    // skipped time is the same as a rest
+                    let staff = staff_index(element, staff_count)?;
+                    let items = voice_items(&mut voices[staff], element, &ctx)?;
+                    let duration = number::<u64>(element, "duration")?
+                        .ok_or(MusicXmlError::MissingElement("duration"))?;
+                    let size = size_from_duration(duration, divisions, (1, 1))
+                        .ok_or_else(|| ctx.unsupported("durations that aren't a single note"))?;
+                    items.push(Item {
+                        tone: None,
+                        chord: Vec::new(),
+                        size,
+                        grace: false,
+                        tuplet: None,
+                        starts: Vec::new(),
+                        stops: 0,
+                        marks: std::mem::take(&mut directions[staff]),
+                        articulations: Vec::new(),
+                        ornament: None,
+                    });
+                }
+                // staves and voices are told apart by their <staff> and <voice> elements,
+                // so going back is implied, and everything else (barline styles, layout)
+                // isn't part of the sheet
+                _ => {}
+            }
+        }
+
+        for header in &mut headers {
+            header.volta = ending.clone();
+        }
+        if ending_stops {
+            ending = None;
+        }
+
+        for (s, (staff_voices, header)) in voices.into_iter().zip(headers).enumerate() {
+            if s < staff_count {
+                let bar = staves[s].bars.len();
+                let mut voices = Vec::with_capacity(staff_voices.len());
+                for (voice, (_, items)) in staff_voices.into_iter().enumerate() {
+                    let (notes, marks) = group_notes(items, &ctx)?;
+                    marked.extend(marks.into_iter().enumerate().map(|(index, marks)| {
+                        let position = NotePosition {
+                            line: 0,
+                            staff: s,
+                            bar,
+                            voice,
+                            index,
+                        };
+                        (position, marks)
+                    }));
+                    voices.push(notes);
+                }
+                staves[s].bars.push(Bar {
+                    header,
+                    voices,
+                    chord_symbols: Vec::new(),
+                });
+            }
+        }
+    }
+
+    let [upper, lower] = staves;
+    let line = if staff_count == 2 {
+        LineType::Double(upper, lower)
+    } else {
+        LineType::Single(upper)
+    };
+
+    let marks = match_spans(&line, marked);
+    Ok((line, marks))
+}
+
+/// Adds the repeats of a `<barline>` to the bars of both staves, and starts the ending
+/// it opens, returning whether it stops the ending instead
+fn read_barline(element: Node, headers: &mut [BarHeader; 2], ending: &mut Option<Volta>) -> bool {
+    let mut stops = false;
+    for child in element.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "repeat" => {
+                for header in headers.iter_mut() {
+                    match child.attribute("direction") {
+                        Some("forward") => header.repeat_start = true,
+                        Some("backward") => {
+                            let times = child.attribute("times").and_then(|t| t.parse().ok());
+                            header.repeat_end = Some(times.unwrap_or(2).max(2));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // endings with numbers the sheet can't hold are left out
+            "ending" => match child.attribute("type") {
+                Some("start") => {
+                    let passes = child
+                        .attribute("number")
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|pass| pass.trim().parse().ok())
+                        .collect::<Option<Vec<u8>>>();
+                    *ending = passes.and_then(|p| Volta::new(p).ok());
+                }
+                Some("stop" | "discontinue") => stops = true,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    stops
+}
+
+/// Adds the dynamics, hairpins and tempo changes of a `<direction>` to the marks
+/// of the next note, and its tempo marking, jump and markers to the bar
+fn read_direction(element: Node, marks: &mut Marks, header: &mut BarHeader) {
+    let types: Vec<Node> = element
+        .children()
+        .filter(|c| c.has_tag_name("direction-type"))
+        .flat_map(|t| t.children().filter(Node::is_element))
+        .collect();
+
+    // words are either a jump or a marker, the name of the tempo,
+    // or say how the dashed line after them changes it
+    let mut words: Vec<String> = Vec::new();
+    let all_words = types
+        .iter()
+        .filter(|d| d.has_tag_name("words"))
+        .filter_map(|d| d.text())
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    for word in all_words {
+        if let Ok(jump) = Jump::from_str(word) {
+            header.jump = Some(jump);
+        } else if word.eq_ignore_ascii_case("fine") {
+            header.markers.push(Marker::Fine);
+        } else if word.eq_ignore_ascii_case("to coda") {
+            header.markers.push(Marker::ToCoda);
+        } else {
+            words.push(word.to_string());
+        }
+    }
+    let change = words.iter().find_map(|w| {
+        let w = w.to_lowercase();
+        if w.starts_with("rit") || w.starts_with("rall") {
+            Some(SpanKind::Ritardando)
+        } else if w.starts_with("accel") {
+            Some(SpanKind::Accelerando)
+        } else {
+            None
+        }
+    });
+
+    let mut tempo = None;
+    for direction in types {
+        match direction.tag_name().name() {
+            // markings the sheet doesn't have, like sf or rfz, are left out
+            "dynamics" => marks.dynamics.extend(
+                direction
+                    .children()
+                    .filter_map(|d| Dynamic::from_str(d.tag_name().name()).ok()),
+            ),
+            "wedge" => {
+                let number = direction.attribute("number").unwrap_or("1").to_string();
+                match direction.attribute("type") {
+                    Some("crescendo") => marks.wedge_starts.push((number, SpanKind::Crescendo)),
+                    Some("diminuendo") => marks.wedge_starts.push((number, SpanKind::Diminuendo)),
+                    Some("stop") => marks.wedge_stops.push(number),
+                    _ => {}
+                }
+            }
+            // dashes without words are just lines, and are left out
+            "dashes" => {
+                let number = direction.attribute("number").unwrap_or("1").to_string();
+                match (direction.attribute("type"), change) {
+                    (Some("start"), Some(kind)) => marks.dashes_starts.push((number, kind)),
+                    (Some("stop"), _) => marks.dashes_stops.push(number),
+                    _ => {}
+                }
+            }
+            "metronome" => {
+                let beat = text(direction, "beat-unit")
+                    .and_then(|unit| mapping::unit_from_type(&unit))
+                    .zip(mapping::modifiers_from_dots(
+                        direction
+                            .children()
+                            .filter(|c| c.has_tag_name("beat-unit-dot"))
+                            .count(),
+                    ))
+                    .map(|(unit, modifiers)| NoteSize { unit, modifiers });
+                let bpm = text(direction, "per-minute").and_then(|b| b.trim().parse().ok());
+                if let (Some(beat), Some(bpm)) = (beat, bpm) {
+                    tempo = Tempo::new(beat, bpm).ok();
+                }
+            }
+            "segno" => header.markers.push(Marker::Segno),
+            "coda" => header.markers.push(Marker::Coda),
+            _ => {}
+        }
+    }
+
+    // jumps and markers without words are still played
+    let sound = child(element, "sound");
+    let has = |name| sound.and_then(|s| s.attribute(name)).is_some();
+    if header.jump.is_none() {
+        if has("dalsegno") {
+            header.jump = Some(Jump::DalSegno);
+        } else if sound.and_then(|s| s.attribute("dacapo")) == Some("yes") {
+            header.jump = Some(Jump::DaCapo);
+        }
+    }
+    for (name, marker) in [("fine", Marker::Fine), ("tocoda", Marker::ToCoda)] {
+        if has(name) && !header.markers.contains(&marker) {
+            header.markers.push(marker);
+        }
+    }
+
+    // without a metronome mark, the tempo it's played at is still a marking
+    if tempo.is_none() {
+        tempo = sound
+            .and_then(|sound| sound.attribute("tempo"))
+            .and_then(|t| t.trim().parse::<f64>().ok())
+            .filter(|t| *t >= 0.5 && *t < 65535.5)
+            .and_then(|t| Tempo::new(NoteSizeUnit::Crotchet.into(), t.round() as u16).ok());
+    }
+
+    if let Some(tempo) = tempo {
+        header.tempo = Some(Tempo {
+            text: (change.is_none() && !words.is_empty()).then(|| words.join(" ")),
+            ..tempo
+        });
+    }
+}
+
+/// The spans, dynamics and lyrics of a part
+type PartMarks = (Vec<Span>, Vec<DynamicMark>, Vec<Lyric>);
+
+/// Turns ties into spans to the note after them, pairs up the starts and ends of slurs,
+/// hairpins and tempo changes, and places the dynamics and lyrics
+fn match_spans(line: &LineType, marked: Vec<(NotePosition, Marks)>) -> PartMarks {
+    let mut spans = Vec::new();
+    let mut dynamics = Vec::new();
+    let mut lyrics = Vec::new();
+    let mut open: Vec<(String, NotePosition)> = Vec::new();
+    let mut wedges: Vec<(String, SpanKind, NotePosition)> = Vec::new();
+    let mut dashes: Vec<(String, SpanKind, NotePosition)> = Vec::new();
+
+    for (position, marks) in marked {
+        if marks.tie {
+            let voice_len = line
+                .staves()
+                .get(position.staff)
+                .and_then(|staff| staff.bars.get(position.bar))
+                .map_or(0, |bar| bar.voice(position.voice).len());
+            let next = if position.index + 1 < voice_len {
+                NotePosition {
+                    index: position.index + 1,
+                    ..position
+                }
+            } else {
+                NotePosition {
+                    bar: position.bar + 1,
+                    index: 0,
+                    ..position
+                }
+            };
+            spans.push(Span::tie(position, next));
+        }
+
+        // a slur can end on the note the next one with its number starts on
+        for number in marks.slur_stops {
+            if let Some(i) = open.iter().position(|(n, _)| *n == number) {
+                let (_, start) = open.remove(i);
+                spans.push(Span::slur(start, position));
+            }
+        }
+        for number in marks.slur_starts {
+            open.retain(|(n, _)| *n != number);
+            open.push((number, position));
+        }
+
+        // hairpins end on the note their stop comes before
+        for number in marks.wedge_stops {
+            if let Some(i) = wedges.iter().position(|(n, _, _)| *n == number) {
+                let (_, kind, start) = wedges.remove(i);
+                spans.push(Span {
+                    kind,
+                    start,
+                    end: position,
+                });
+            }
+        }
+        for (number, kind) in marks.wedge_starts {
+            wedges.retain(|(n, _, _)| *n != number);
+            wedges.push((number, kind, position));
+        }
+
+        // and so do tempo changes
+        for number in marks.dashes_stops {
+            if let Some(i) = dashes.iter().position(|(n, _, _)| *n == number) {
+                let (_, kind, start) = dashes.remove(i);
+                spans.push(Span {
+                    kind,
+                    start,
+                    end: position,
+                });
+            }
+        }
+        for (number, kind) in marks.dashes_starts {
+            dashes.retain(|(n, _, _)| *n != number);
+            dashes.push((number, kind, position));
+        }
+
+        dynamics.extend(
+            marks
+                .dynamics
+                .into_iter()
+                .map(|dynamic| DynamicMark::new(position, dynamic)),
+        );
+        lyrics.extend(
+            marks
+                .lyrics
+                .into_iter()
+                .map(|(verse, text, syllabic, extend)| Lyric {
+                    position,
+                    verse,
+                    text,
+                    syllabic,
+                    extend,
+                }),
+        );
+    }
+
+    (spans, dynamics, lyrics)
+}
+
+/// How the part sounds, from its first `<transpose>`
+fn read_transpose(part: Node) -> Result<Option<Transposition>, MusicXmlError> {
+    let Some(transpose) = part.descendants().find(|n| n.has_tag_name("transpose")) else {
+        return Ok(None);
+    };
+    let octaves = number::<i32>(transpose, "octave-change")?.unwrap_or(0);
+    let semitones = number::<i32>(transpose, "chromatic")?
+        .ok_or(MusicXmlError::MissingElement("chromatic"))?
+        + 12 * octaves;
+
+    Ok(Some(match number::<i32>(transpose, "diatonic")? {
+        Some(steps) => Transposition::diatonic(steps + 7 * octaves, semitones),
+        None => Transposition::Chromatic(semitones),
+    }))
+}
+
+fn read_attributes(
+    element: Node,
+    headers: &mut [BarHeader; 2],
+    ctx: &Context,
+) -> Result<(), MusicXmlError> {
+    if let Some(key) = child(element, "key") {
+        let fifths = number::<i8>(key, "fifths")?
+            .ok_or_else(|| ctx.unsupported("non-traditional key signatures"))?;
+        let mode = match text(key, "mode") {
+            Some(mode) => {
+                mapping::mode_from_name(&mode).ok_or(MusicXmlError::InvalidValue("mode", mode))?
+            }
+            None => Mode::Major,
+        };
+        let key = KeySignature::new(fifths, mode)
+            .map_err(|_| MusicXmlError::InvalidValue("fifths", fifths.to_string()))?;
+        for header in headers.iter_mut() {
+            header.key_signature = Some(key);
+        }
+    }
+
+    if let Some(time) = child(element, "time") {
+        if child(time, "senza-misura").is_some() {
+            return Err(ctx.unsupported("unmeasured time"));
+        }
+
+        let beats = text(time, "beats").ok_or(MusicXmlError::MissingElement("beats"))?;
+        let beat_type =
+            text(time, "beat-type").ok_or(MusicXmlError::MissingElement("beat-type"))?;
+        if beats.contains('+') || beat_type.contains('+') {
+            return Err(ctx.unsupported("composite time signatures"));
+        }
+
+        let invalid = || MusicXmlError::InvalidValue("time", format!("{}/{}", beats, beat_type));
+        let num = u8::from_str(&beats).map_err(|_| invalid())?;
+        let den = u8::from_str(&beat_type).map_err(|_| invalid())?;
+        let ts = TimeSignature::new(num, den).map_err(|_| invalid())?;
+        for header in headers.iter_mut() {
+            header.time_signature = Some(ts);
+        }
+    }
+
+    for clef in element.children().filter(|c| c.has_tag_name("clef")) {
+        let staff = clef
+            .attribute("number")
+            .map(|n| {
+                usize::from_str(n)
+                    .ok()
+                    .filter(|n| (1..=headers.len()).contains(n))
+                    .ok_or_else(|| MusicXmlError::InvalidValue("clef", n.to_string()))
+            })
+            .transpose()?
+            .unwrap_or(1);
+
+        if number::<i8>(clef, "clef-octave-change")?.is_some_and(|o| o != 0) {
+            return Err(ctx.unsupported("octave clefs"));
+        }
+
+        let sign = text(clef, "sign").ok_or(MusicXmlError::MissingElement("sign"))?;
+        let line = number::<u8>(clef, "line")?.unwrap_or(match sign.as_str() {
+            "G" => 2,
+            "F" => 4,
+            _ => 3,
+        });
+        let clef =
+            mapping::clef_from_sign(&sign, line).ok_or_else(|| ctx.unsupported("this clef"))?;
+        headers[staff - 1].clef = Some(clef);
+    }
+
+    Ok(())
+}
+
+fn read_note(element: Node, divisions: u64, ctx: &Context) -> Result<Option<Item>, MusicXmlError> {
+    if child(element, "unpitched").is_some() {
+        return Err(ctx.unsupported("unpitched notes"));
+    }
+    if child(element, "cue").is_some() {
+        return Err(ctx.unsupported("cue notes"));
+    }
+
+    let grace = child(element, "grace").is_some();
+    let rest = child(element, "rest");
+    let tone = match child(element, "pitch") {
+        Some(pitch) => Some(read_pitch(pitch, element, ctx)?),
+        None if rest.is_some() => None,
+        None => return Err(MusicXmlError::MissingElement("pitch")),
+    };
+
+    let tuplet = child(element, "time-modification")
+        .map(|t| -> Result<_, MusicXmlError> {
+            let actual = number::<u64>(t, "actual-notes")?
+                .ok_or(MusicXmlError::MissingElement("actual-notes"))?;
+            let normal = number::<u64>(t, "normal-notes")?
+                .ok_or(MusicXmlError::MissingElement("normal-notes"))?;
+            if actual == 0 || normal == 0 {
+                return Err(MusicXmlError::InvalidValue(
+                    "time-modification",
+                    format!("{}:{}", actual, normal),
+                ));
+            }
+            Ok((actual, normal))
+        })
+        .transpose()?
+        .filter(|(actual, normal)| actual != normal);
+
+    let notations = child(element, "notations");
+    let mut starts = Vec::new();
+    let mut stops = 0;
+    let brackets = notations
+        .into_iter()
+        .flat_map(|n| n.children().filter(|c| c.has_tag_name("tuplet")));
+    for bracket in brackets {
+        match bracket.attribute("type") {
+            Some("start") => starts.push(read_tuplet_start(bracket, ctx)?),
+            Some("stop") => stops += 1,
+            _ => {}
+        }
+    }
+    let mut articulations: Vec<Articulation> = notations
+        .and_then(|n| child(n, "articulations"))
+        .into_iter()
+        .flat_map(|a| a.children())
+        .filter_map(|a| mapping::articulation_from_name(a.tag_name().name()))
+        .collect();
+    if notations.and_then(|n| child(n, "fermata")).is_some() {
+        articulations.push(Articulation::Fermata);
+    }
+    let ornament = notations
+        .and_then(|n| child(n, "ornaments"))
+        .into_iter()
+        .flat_map(|o| o.children())
+        .find_map(|o| mapping::ornament_from_name(o.tag_name().name()));
+
+    // ties are written both as sound (<tie>) and notation (<tied>), either is enough
+    let tie_start = |node: Node| node.attribute("type") == Some("start");
+    let tie = element
+        .children()
+        .filter(|c| c.has_tag_name("tie"))
+        .chain(
+            notations
+                .into_iter()
+                .flat_map(|n| n.children().filter(|c| c.has_tag_name("tied"))),
+        )
+        .any(tie_start);
+    let mut marks = Marks {
+        tie,
+        lyrics: element
+            .children()
+            .filter(|c| c.has_tag_name("lyric"))
+            .filter_map(read_lyric)
+            .collect(),
+        ..Marks::default()
+    };
+    let slurs = notations
+        .into_iter()
+        .flat_map(|n| n.children().filter(|c| c.has_tag_name("slur")));
+    for slur in slurs {
+        let number = slur.attribute("number").unwrap_or("1").to_string();
+        match slur.attribute("type") {
+            Some("start") => marks.slur_starts.push(number),
+            Some("stop") => marks.slur_stops.push(number),
+            _ => {}
+        }
+    }
+
+    let duration = number::<u64>(element, "duration")?;
+    let size = match text(element, "type") {
+        Some(name) => {
+            let unit =
+                mapping::unit_from_type(&name).ok_or(MusicXmlError::InvalidValue("type", name))?;
+            let dots = element.children().filter(|c| c.has_tag_name("dot")).count();
+            let modifiers = mapping::modifiers_from_dots(dots)
+                .ok_or_else(|| ctx.unsupported("more than three dots"))?;
+            NoteSize { unit, modifiers }
+        }
+        None if grace => NoteSize::default(),
+        None => {
+            let duration = duration.ok_or(MusicXmlError::MissingElement("duration"))?;
+            match size_from_duration(duration, divisions, tuplet.unwrap_or((1, 1))) {
+                Some(size) => size,
+                // whole measure rests in odd time signatures are left as an empty bar
+                None if rest.is_some_and(|r| r.attribute("measure") == Some("yes")) => {
+                    return Ok(None)
+                }
+                None => return Err(ctx.unsupported("durations that aren't a single note")),
+            }
+        }
+    };
+
+    Ok(Some(Item {
+        tone,
+        chord: Vec::new(),
+        size,
+        grace,
+        tuplet,
+        starts,
+        stops,
+        marks,
+        articulations,
+        ornament,
+    }))
+}
+
+/// Reads a syllable, with the verse from its number counted from 1, and the words
+/// of an elision joined with a tie
+fn read_lyric(lyric: Node) -> Option<(u8, String, Syllabic, bool)> {
+    let verse = lyric
+        .attribute("number")
+        .and_then(|n| n.parse::<u8>().ok())
+        .map_or(0, |n| n.saturating_sub(1));
+    let texts: Vec<&str> = lyric
+        .children()
+        .filter(|c| c.has_tag_name("text"))
+        .filter_map(|t| t.text())
+        .collect();
+    if texts.is_empty() {
+        return None;
+    }
+    let syllabic = text(lyric, "syllabic")
+        .and_then(|name| mapping::syllabic_from_name(&name))
+        .unwrap_or_default();
+    // an extender without a type starts, stops only end the line drawn from an earlier note
+    let extend = child(lyric, "extend").is_some_and(|e| e.attribute("type") != Some("stop"));
+
+    Some((verse, texts.join("\u{203f}"), syllabic, extend))
+}
+
+fn read_tuplet_start(bracket: Node, ctx: &Context) -> Result<TupletStart, MusicXmlError> {
+    let portion = |name| -> Result<Option<(u8, Option<NoteSize>)>, MusicXmlError> {
+        let Some(portion) = child(bracket, name) else {
+            return Ok(None);
+        };
+        let Some(count) = number::<u8>(portion, "tuplet-number")? else {
+            return Ok(None);
+        };
+        let unit = text(portion, "tuplet-type")
+            .map(|name| {
+                let unit = mapping::unit_from_type(&name)
+                    .ok_or(MusicXmlError::InvalidValue("tuplet-type", name))?;
+                let dots = portion
+                    .children()
+                    .filter(|c| c.has_tag_name("tuplet-dot"))
+                    .count();
+                let modifiers = mapping::modifiers_from_dots(dots)
+                    .ok_or_else(|| ctx.unsupported("more than three dots"))?;
+                Ok::<_, MusicXmlError>(NoteSize { unit, modifiers })
+            })
+            .transpose()?;
+        Ok(Some((count, unit)))
+    };
+
+    let (Some((actual, unit)), Some((normal, normal_unit))) =
+        (portion("tuplet-actual")?, portion("tuplet-normal")?)
+    else {
+        return Ok(TupletStart::default());
+    };
+
+    // the normal notes can be counted in another unit, like three quavers in the time of a crotchet
+    let normal = match (unit, normal_unit) {
+        (Some(unit), Some(normal_unit)) => {
+            let normal = normal_unit.duration().scale(normal.into(), 1);
+            let count = normal.scale(unit.duration().den(), unit.duration().num());
+            match u8::try_from(count.num()) {
+                Ok(normal) if count.den() == 1 => normal,
+                _ => return Err(ctx.unsupported("tuplets with this ratio")),
+            }
+        }
+        _ => normal,
+    };
+
+    Ok(TupletStart {
+        ratio: Some((actual, normal)),
+        unit,
+    })
+}
+
+fn read_pitch(pitch: Node, note: Node, ctx: &Context) -> Result<Tone, MusicXmlError> {
+    let step = text(pitch, "step").ok_or(MusicXmlError::MissingElement("step"))?;
+    let octave = step
+        .chars()
+        .next()
+        .filter(|_| step.len() == 1)
+        .and_then(|c| Octave::try_from(c).ok())
+        .ok_or_else(|| MusicXmlError::InvalidValue("step", step.clone()))?;
+    let position = number::<u8>(pitch, "octave")?.ok_or(MusicXmlError::MissingElement("octave"))?;
+
+    let alter = number::<f32>(pitch, "alter")?.unwrap_or(0.0);
+    let modifiers = if alter == 1.0 {
+        Some(ToneModifiers::Sharp)
+    } else if alter == -1.0 {
+        Some(ToneModifiers::Flat)
+    } else if alter == 0.0 {
+        // naturals are only kept when they're written out
+        (text(note, "accidental").as_deref() == Some("natural")).then_some(ToneModifiers::Natural)
+    } else {
+        return Err(ctx.unsupported("alterations other than a sharp or a flat"));
+    };
+
+    Ok(match modifiers {
+        Some(modifiers) => Tone::with_modifiers(octave, position, modifiers).ok_or_else(|| {
+            MusicXmlError::InvalidValue("pitch", format!("{}{}", octave, position))
+        })?,
+        None => Tone::new(octave, position),
+    })
+}
+
+/// Attaches grace notes to the note after them and gathers tuplets,
+/// returning the notes with the ties and slurs on each of them
+fn group_notes(items: Vec<Item>, ctx: &Context) -> Result<(Vec<Note>, Vec<Marks>), MusicXmlError> {
+    let mut notes = Vec::with_capacity(items.len());
+    let mut marks = Vec::with_capacity(items.len());
+    let mut grace = None;
+    // slurs on grace notes and inside tuplets go on the note or tuplet they're part of,
+    // ties there can't be kept
+    let mut pending = Marks::default();
+    let mut open: Vec<OpenTuplet> = Vec::new();
+
+    for item in items {
+        // notes with a time modification and no bracket are in a tuplet all the same
+        let implicit =
+            item.tuplet.is_some() && item.starts.is_empty() && open.is_empty() && !item.grace;
+        let starts = if implicit {
+            vec![TupletStart::default()]
+        } else {
+            item.starts
+        };
+        let lone = open.is_empty() && starts.len() == 1;
+
+        for start in starts {
+            let too_large = || ctx.unsupported("tuplets this large");
+            let ratio = match (start.ratio, item.tuplet) {
+                (Some(ratio), _) => ratio,
+                // without the ratio of the bracket, a lone tuplet has the ratio of its notes
+                (None, Some((actual, normal))) if lone => (
+                    u8::try_from(actual).map_err(|_| too_large())?,
+                    u8::try_from(normal).map_err(|_| too_large())?,
+                ),
+                (None, Some(_)) => {
+                    return Err(ctx.unsupported("nested tuplets without their ratio"))
+                }
+                (None, None) => return Err(ctx.unsupported("tuplets without a time modification")),
+            };
+            open.push(OpenTuplet {
+                ratio,
+                unit: start.unit,
+                implicit,
+                notes: Vec::new(),
+            });
+        }
+
+        if item.grace {
+            if grace.is_some() {
+                return Err(ctx.unsupported("several grace notes in a row"));
+            }
+            if item.stops > 0 {
+                return Err(ctx.unsupported("tuplets ending on a grace note"));
+            }
+            grace = item.tone;
+            pending.extend(Marks {
+                tie: false,
+                ..item.marks
+            });
+            continue;
+        }
+        if item.tuplet.is_none() && !open.is_empty() {
+            return Err(ctx.unsupported("unfinished tuplets"));
+        }
+
+        // chords and rests only have room for either dots or a fermata
+        let fermata = item.articulations.contains(&Articulation::Fermata);
+        let crowned = if fermata && item.size.modifiers.is_none() {
+            NoteSize {
+                modifiers: Some(NoteSizeModifiers::Crown),
+                ..item.size
+            }
+        } else {
+            item.size
+        };
+        let note = match (item.tone, grace.take()) {
+            (Some(_), Some(_)) if !item.chord.is_empty() => {
+                return Err(ctx.unsupported("grace notes before chords"))
+            }
+            (Some(tone), Some(grace)) => Note::WithGrace {
+                grace,
+                note: NoteInfo {
+                    tone,
+                    size: item.size,
+                    articulations: item.articulations,
+                    ornament: item.ornament,
+                },
+            },
+            (Some(tone), None) if !item.chord.is_empty() => {
+                let tones = std::iter::once(tone).chain(item.chord);
+                // there's always at least the first tone
+                Note::Chord(Chord::new(tones, crowned).unwrap())
+            }
+            (Some(tone), None) => Note::Note(NoteInfo {
+                tone,
+                size: item.size,
+                articulations: item.articulations,
+                ornament: item.ornament,
+            }),
+            (None, None) => Note::Rest(crowned),
+            (None, Some(_)) => return Err(ctx.unsupported("grace notes before rests")),
+        };
+        match open.last_mut() {
+            Some(tuplet) => {
+                tuplet.notes.push(note);
+                pending.extend(Marks {
+                    tie: false,
+                    ..item.marks
+                });
+            }
+            None => {
+                notes.push(note);
+                pending.extend(item.marks);
+                marks.push(std::mem::take(&mut pending));
+            }
+        }
+
+        let mut stops = item.stops;
+        if let Some(tuplet) = open.last() {
+            if tuplet.implicit && tuplet.notes.len() == tuplet.ratio.0.into() {
+                stops = stops.max(1);
+            }
+        }
+        for _ in 0..stops {
+            let tuplet = open
+                .pop()
+                .ok_or_else(|| ctx.unsupported("tuplets that end before they start"))?;
+            let (actual, normal) = tuplet.ratio;
+            let tuplet = match tuplet.unit {
+                Some(unit) => Tuplet::new(actual, normal, unit, tuplet.notes),
+                None => Tuplet::with_ratio(actual, normal, tuplet.notes),
+            }
+            .map_err(|_| ctx.unsupported("tuplets that don't add up to their ratio"))?;
+
+            match open.last_mut() {
+                Some(outer) => outer.notes.push(Note::Tuplet(tuplet)),
+                None => {
+                    notes.push(Note::Tuplet(tuplet));
+                    marks.push(std::mem::take(&mut pending));
+                }
+            }
+        }
+    }
+
+    if grace.is_some() {
+        return Err(ctx.unsupported("grace notes at the end of a measure"));
+    }
+    if !open.is_empty() {
+        return Err(ctx.unsupported("tuplets across barlines"));
+    }
+
+    Ok((notes, marks))
+}
+
+/// Finds the note size that lasts `duration` divisions, inside a tuplet of the given ratio
+fn size_from_duration(
+    duration: u64,
+    divisions: u64,
+    (actual, normal): (u64, u64),
+) -> Option<NoteSize> {
+    let length = Duration::new(duration * actual, 4 * divisions * normal);
+
+    NoteSizeUnit::ALL
+        .iter()
+        .flat_map(|unit| {
+            (0..=3).filter_map(|dots| {
+                mapping::modifiers_from_dots(dots).map(|modifiers| NoteSize {
+                    unit: *unit,
+                    modifiers,
+                })
+            })
+        })
+        .find(|size| size.duration() == length)
+}
+
+/// The notes read so far in the voice of the element, starting it if it's new
+fn voice_items<'a>(
+    voices: &'a mut Vec<(String, Vec<Item>)>,
+    element: Node,
+    ctx: &Context,
+) -> Result<&'a mut Vec<Item>, MusicXmlError> {
+    let voice = text(element, "voice").unwrap_or_else(|| "1".to_string());
+    let index = match voices.iter().position(|(v, _)| *v == voice) {
+        Some(index) => index,
+        None if voices.len() == MAX_VOICES => {
+            return Err(ctx.unsupported("more than four voices on a staff"))
+        }
+        None => {
+            voices.push((voice, Vec::new()));
+            voices.len() - 1
+        }
+    };
+
+    Ok(&mut voices[index].1)
+}
+
+fn staff_index(element: Node, staff_count: usize) -> Result<usize, MusicXmlError> {
+    let staff = number::<usize>(element, "staff")?.unwrap_or(1);
+    if staff == 0 || staff > staff_count {
+        return Err(MusicXmlError::InvalidValue("staff", staff.to_string()));
+    }
+
+    Ok(staff - 1)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name).map(|c| c.text().unwrap_or_default().trim().to_string())
+}
+
+fn number<T: FromStr>(node: Node, name: &'static str) -> Result<Option<T>, MusicXmlError> {
+    text(node, name)
+        .map(|t| T::from_str(&t).map_err(|_| MusicXmlError::InvalidValue(name, t)))
+        .transpose()
+}
@@ -0,0 +1,129 @@
+mod export;
+mod import;
+mod mapping;
+
+use rustsheet::{
+    bar::Clef,
+    notes::{NoteError, Tone},
+    MusicSheet,
+};
+use thiserror::Error;
+
+#[derive(Default)]
+pub struct MusicSheetMusicXmlExporter {}
+
+impl MusicSheetMusicXmlExporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Writes the sheet as a partwise MusicXML document.
+    ///
+    /// The title goes in `<work-title>`, the subtitle in `<movement-title>` and the author
+    /// is the composer. Every line becomes a `<part>`, with two staves for double lines.
+    /// Ties are written on every tone the tied notes share, and slurs are numbered within
+    /// their part. Dynamics and hairpins are `<direction>` elements before the note they
+    /// go under, hairpins ending before the note they reach, and so are accelerandos and
+    /// ritardandos, as words and dashes over it. Tempo markings start their measure with
+    /// a metronome mark.
+    /// Repeats and endings of the upper staff are `<barline>` elements, and its segnos,
+    /// codas, fines and jumps directions with the `<sound>` that plays them.
+    /// Lyrics are `<lyric>` elements of the note they're sung on, numbered by verse from 1.
+    /// Transposing instruments are written as the players read them, with a `<transpose>`
+    /// saying how they sound.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Bar, BarHeader, Line, LineType, SingleLine},
+    ///     notes::{Note, NoteInfo},
+    ///     tempo::NoteSizeUnit,
+    ///     MusicSheet,
+    /// };
+    /// use rustsheet_musicxml::{MusicSheetMusicXmlExporter, MusicSheetMusicXmlImporter};
+    /// use rustsheet_utils::tones;
+    ///
+    /// fn main() {
+    ///     let bar = Bar::new(
+    ///         BarHeader::sane_default(),
+    ///         vec![Note::Note(NoteInfo::new(tones::C3, NoteSizeUnit::SemiBreve.into()))],
+    ///     );
+    ///     let bass = Line::new_named(
+    ///         "Bass".to_string(),
+    ///         LineType::Single(SingleLine { bars: vec![bar] }),
+    ///     );
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), vec![bass]);
+    ///
+    ///     let xml = MusicSheetMusicXmlExporter::new().export_sheet(&sheet).unwrap();
+    ///     assert!(xml.contains("<part-name>Bass</part-name>"));
+    ///
+    ///     // and back
+    ///     let imported = MusicSheetMusicXmlImporter::new().import_sheet(&xml).unwrap();
+    ///     assert_eq!(imported.title, "Example");
+    ///     assert_eq!(imported.author, "Someone");
+    ///     assert_eq!(imported.lines[0].name.as_deref(), Some("Bass"));
+    /// }
+    /// ```
+    pub fn export_sheet(&self, sheet: &MusicSheet) -> Result<String, MusicXmlError> {
+        export::export_sheet(sheet)
+    }
+}
+
+#[derive(Default)]
+pub struct MusicSheetMusicXmlImporter {}
+
+impl MusicSheetMusicXmlImporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Reads a partwise MusicXML document into a sheet.
+    ///
+    /// Parts with two staves become double lines, the voices of each staff become the
+    /// voices of its bars, in the order they first appear, and notes marked with `<chord/>`
+    /// are gathered into chords. Constructs the sheet can't hold yet, like more than four
+    /// voices on a staff, are reported as [`MusicXmlError::Unsupported`] with the part and
+    /// measure they were found in, while purely visual elements (beams, stems, layout)
+    /// are skipped.
+    /// Ties (from either `<tie>` or `<tied>`) and numbered slurs become the spans of the
+    /// sheet, leaving out the ones it can't hold, like ties into a tuplet.
+    /// Articulations and ornaments are kept on single notes, and fermatas on chords and
+    /// rests become crowns.
+    /// Dynamics and wedges in `<direction>` elements go on the next note of their staff,
+    /// markings the sheet doesn't have, like `<sf>`, are left out, and so do dashes
+    /// after words starting with "rit", "rall" or "accel", as tempo changes.
+    /// Metronome marks, or the tempo of a `<sound>` without one, become tempo markings
+    /// of their measure, with the words around them.
+    /// Repeats and endings in `<barline>` elements go on the bars of every staff, segnos,
+    /// codas and jumps, as words like "D.S. al Coda" or as a `<sound>`, on their measure.
+    /// `<lyric>` elements become the syllables of their note, in the verse of their number,
+    /// with the words of an elision joined by a tie, and leaving out the ones in tuplets.
+    /// The first `<transpose>` of a part makes it a transposing instrument, written as it's read.
+    pub fn import_sheet(&self, xml: &str) -> Result<MusicSheet, MusicXmlError> {
+        import::import_sheet(xml)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MusicXmlError {
+    #[error("Couldn't parse the XML document")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Expected a <score-partwise> document, found <{0}>")]
+    NotPartwise(String),
+    #[error("Missing <{0}> element")]
+    MissingElement(&'static str),
+    #[error("Invalid value {1:?} for <{0}>")]
+    InvalidValue(&'static str, String),
+    #[error("Part {0:?} isn't declared in the part list")]
+    UnknownPart(String),
+    #[error("Measure {measure} of part {part:?}: {construct} can't be represented")]
+    Unsupported {
+        part: String,
+        measure: String,
+        construct: &'static str,
+    },
+    #[error("Tone {0} is out of the MusicXML range")]
+    ToneOutOfRange(Tone),
+    #[error("Couldn't transpose the sheet to written pitch: {0}")]
+    WrittenPitch(NoteError),
+    #[error("Clef {0:?} has no MusicXML equivalent")]
+    UnsupportedClef(Clef),
+}
@@ -0,0 +1,245 @@
+//! Conversions between the sheet types and their MusicXML spelling,
+//! shared by the importer and the exporter.
+
+use rustsheet::{
+    bar::Clef,
+    key::Mode,
+    lyrics::Syllabic,
+    notes::{Articulation, Note, Octave, Ornament, Tone, ToneModifiers},
+    tempo::{Duration, NoteSizeModifiers, NoteSizeUnit},
+};
+
+/// Highest octave number MusicXML accepts
+pub const MAX_OCTAVE: u8 = 9;
+
+/// Clefs that can stand for [`Clef::None`], with the pitch of their sign
+const CLEF_SIGNS: [(&str, u8); 10] = [
+    ("G", 2),
+    ("F", 4),
+    ("C", 3),
+    ("C", 4),
+    ("G", 1),
+    ("F", 3),
+    ("F", 5),
+    ("C", 1),
+    ("C", 2),
+    ("C", 5),
+];
+
+pub fn type_name(unit: NoteSizeUnit) -> &'static str {
+    match unit {
+        NoteSizeUnit::Large => "maxima",
+        NoteSizeUnit::Long => "long",
+        NoteSizeUnit::Breve => "breve",
+        NoteSizeUnit::SemiBreve => "whole",
+        NoteSizeUnit::Minim => "half",
+        NoteSizeUnit::Crotchet => "quarter",
+        NoteSizeUnit::Quaver => "eighth",
+        NoteSizeUnit::SemiQuaver => "16th",
+        NoteSizeUnit::DemiSemiQuaver => "32nd",
+        NoteSizeUnit::HemiDemiSemiQuaver => "64th",
+        NoteSizeUnit::SemiHemiDemiSemiQuaver => "128th",
+        NoteSizeUnit::DemiSemiHemiDemiSemiQuaver => "256th",
+    }
+}
+
+pub fn unit_from_type(name: &str) -> Option<NoteSizeUnit> {
+    Some(match name {
+        "maxima" => NoteSizeUnit::Large,
+        "long" => NoteSizeUnit::Long,
+        "breve" => NoteSizeUnit::Breve,
+        "whole" => NoteSizeUnit::SemiBreve,
+        "half" => NoteSizeUnit::Minim,
+        "quarter" => NoteSizeUnit::Crotchet,
+        "eighth" => NoteSizeUnit::Quaver,
+        "16th" => NoteSizeUnit::SemiQuaver,
+        "32nd" => NoteSizeUnit::DemiSemiQuaver,
+        "64th" => NoteSizeUnit::HemiDemiSemiQuaver,
+        "128th" => NoteSizeUnit::SemiHemiDemiSemiQuaver,
+        "256th" => NoteSizeUnit::DemiSemiHemiDemiSemiQuaver,
+        _ => return None,
+    })
+}
+
+/// The element of an articulation inside `<articulations>`, fermatas are written on their own
+pub fn articulation_name(articulation: Articulation) -> Option<&'static str> {
+    Some(match articulation {
+        Articulation::Staccato => "staccato",
+        Articulation::Staccatissimo => "staccatissimo",
+        Articulation::Tenuto => "tenuto",
+        Articulation::Accent => "accent",
+        Articulation::Marcato => "strong-accent",
+        Articulation::Fermata => return None,
+    })
+}
+
+pub fn articulation_from_name(name: &str) -> Option<Articulation> {
+    Some(match name {
+        "staccato" => Articulation::Staccato,
+        "staccatissimo" => Articulation::Staccatissimo,
+        "tenuto" => Articulation::Tenuto,
+        "accent" => Articulation::Accent,
+        "strong-accent" => Articulation::Marcato,
+        _ => return None,
+    })
+}
+
+/// The element of an ornament inside `<ornaments>`
+pub fn ornament_name(ornament: Ornament) -> &'static str {
+    match ornament {
+        Ornament::Trill => "trill-mark",
+        Ornament::Mordent => "mordent",
+        Ornament::Turn => "turn",
+    }
+}
+
+pub fn ornament_from_name(name: &str) -> Option<Ornament> {
+    Some(match name {
+        "trill-mark" => Ornament::Trill,
+        "mordent" => Ornament::Mordent,
+        "turn" => Ornament::Turn,
+        _ => return None,
+    })
+}
+
+pub fn syllabic_name(syllabic: Syllabic) -> &'static str {
+    match syllabic {
+        Syllabic::Single => "single",
+        Syllabic::Begin => "begin",
+        Syllabic::Middle => "middle",
+        Syllabic::End => "end",
+    }
+}
+
+pub fn syllabic_from_name(name: &str) -> Option<Syllabic> {
+    Some(match name {
+        "single" => Syllabic::Single,
+        "begin" => Syllabic::Begin,
+        "middle" => Syllabic::Middle,
+        "end" => Syllabic::End,
+        _ => return None,
+    })
+}
+
+pub fn dots(modifiers: Option<NoteSizeModifiers>) -> usize {
+    match modifiers {
+        Some(NoteSizeModifiers::Dot) => 1,
+        Some(NoteSizeModifiers::DoubleDot) => 2,
+        Some(NoteSizeModifiers::TripleDot) => 3,
+        _ => 0,
+    }
+}
+
+pub fn modifiers_from_dots(dots: usize) -> Option<Option<NoteSizeModifiers>> {
+    match dots {
+        0 => Some(None),
+        1 => Some(Some(NoteSizeModifiers::Dot)),
+        2 => Some(Some(NoteSizeModifiers::DoubleDot)),
+        3 => Some(Some(NoteSizeModifiers::TripleDot)),
+        _ => None,
+    }
+}
+
+/// A duration in `divisions` of a quarter note
+pub fn in_divisions(duration: Duration, divisions: u64) -> u64 {
+    duration.num() * 4 * divisions / duration.den()
+}
+
+/// How long every note that takes time lasts when played, tuplets taken apart and grace notes left out
+pub fn note_durations(note: &Note) -> Vec<Duration> {
+    let mut durations = Vec::new();
+    push_durations(note, (1, 1), &mut durations);
+    durations
+}
+
+fn push_durations(note: &Note, (num, den): (u64, u64), durations: &mut Vec<Duration>) {
+    match note {
+        Note::Note(info) | Note::WithGrace { note: info, .. } => {
+            durations.push(info.size.duration().scale(num, den))
+        }
+        Note::Chord(chord) => durations.push(chord.size.duration().scale(num, den)),
+        Note::Tuplet(tuplet) => {
+            let scale = (
+                num * u64::from(tuplet.normal()),
+                den * u64::from(tuplet.actual()),
+            );
+            for note in tuplet.notes() {
+                push_durations(note, scale, durations);
+            }
+        }
+        Note::Rest(size) => durations.push(size.duration().scale(num, den)),
+    }
+}
+
+/// The alteration in semitones, as written in `<alter>`
+pub fn alter(tone: &Tone) -> i8 {
+    match tone.modifiers {
+        Some(ToneModifiers::Sharp) => 1,
+        Some(ToneModifiers::Flat) => -1,
+        _ => 0,
+    }
+}
+
+pub fn accidental_name(modifiers: ToneModifiers) -> &'static str {
+    match modifiers {
+        ToneModifiers::Sharp => "sharp",
+        ToneModifiers::Flat => "flat",
+        ToneModifiers::Natural => "natural",
+    }
+}
+
+/// MusicXML has no mode for key signatures with a non-standard tonic, they're read as major
+pub fn mode_from_name(name: &str) -> Option<Mode> {
+    match name {
+        "none" => Some(Mode::Major),
+        _ => name.parse().ok(),
+    }
+}
+
+pub fn clef_sign(clef: &Clef) -> Option<(&'static str, u8)> {
+    match clef {
+        Clef::Treble => Some(("G", 2)),
+        Clef::Bass => Some(("F", 4)),
+        Clef::Tenor => Some(("C", 4)),
+        Clef::None { center } => CLEF_SIGNS
+            .into_iter()
+            .find(|(sign, line)| sign_center(sign, *line).as_ref() == Some(center)),
+    }
+}
+
+pub fn clef_from_sign(sign: &str, line: u8) -> Option<Clef> {
+    match (sign, line) {
+        ("G", 2) => Some(Clef::Treble),
+        ("F", 4) => Some(Clef::Bass),
+        ("C", 4) => Some(Clef::Tenor),
+        (sign, line) => sign_center(sign, line).map(|center| Clef::None { center }),
+    }
+}
+
+/// The tone on the middle line of a staff with this clef
+fn sign_center(sign: &str, line: u8) -> Option<Tone> {
+    let pitch = match sign {
+        "G" => Tone::new(Octave::G, 4),
+        "F" => Tone::new(Octave::F, 3),
+        "C" => Tone::new(Octave::C, 4),
+        _ => return None,
+    };
+    if !(1..=5).contains(&line) {
+        return None;
+    }
+
+    // every line is two steps away from the next one
+    move_steps(pitch, (3 - i32::from(line)) * 2)
+}
+
+fn move_steps(tone: Tone, steps: i32) -> Option<Tone> {
+    let index = tone.get_steps_since_c0() as i32 + steps;
+    if index < 0 {
+        return None;
+    }
+
+    Some(Tone::new(
+        Octave::from_index(index),
+        u8::try_from(index / 7).ok()?,
+    ))
+}
@@ -1,38 +1,12 @@
 use std::io::Write;
 
-use rustsheet::{
-    bar::{Bar, BarHeader, Line, SingleLine},
-    notes::{Note, NoteInfo, Octave, Tone},
-    tempo::{NoteSize, NoteSizeUnit},
-    MusicSheet,
-};
+use rustsheet::notation;
 use rustsheet_svg::{MusicSheetSVGRenderer, RendererConfig};
-use rustsheet_utils::{scales::MajorScale, tones};
 
 /// This example renders the line shown in the README
 fn main() {
-    // create a single line of 3 bars, 4/4 bass clef with 1 beat large notes (G2 to C3)
-    let bar = Bar {
-        header: BarHeader::sane_default(),
-        notes: vec![
-            Note::Note(NoteInfo {
-                tone: tones::G2,
-                ..Default::default()
-            }),
-            Note::Note(NoteInfo {
-                tone: tones::A2,
-                ..Default::default()
-            }),
-            Note::Note(NoteInfo {
-                tone: tones::B2,
-                ..Default::default()
-            }),
-            Note::Note(NoteInfo {
-                tone: tones::C3,
-                ..Default::default()
-            }),
-        ],
-    };
+    // create a single 4/4 bar in bass clef with 1 beat large notes (G2 to C3)
+    let bar = notation::parse_bar("clef=bass time=4/4 G2:4 A2:4 B2:4 C3:4").unwrap();
 
     let renderer = MusicSheetSVGRenderer::new();
 
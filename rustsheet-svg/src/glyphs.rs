@@ -0,0 +1,115 @@
+use svg::node::{element::Group, Text};
+
+/// An SVG asset from the `svgs` folder, together with the bounding box of its visible content.
+///
+/// The assets come from different sources, so their canvas sizes don't mean much,
+/// only the bounding box is used to place them.
+pub struct Glyph {
+    svg: &'static str,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Glyph {
+    /// Places the glyph with the top left corner of its content at (x, y), scaled to the given height.
+    pub fn place(&self, x: f32, y: f32, height: f32) -> Group {
+        let scale = height / self.height;
+        Group::new()
+            .set(
+                "transform",
+                format!(
+                    "translate({} {}) scale({}) translate({} {})",
+                    x, y, scale, -self.x, -self.y
+                ),
+            )
+            // the svg crate doesn't escape text nodes, so this embeds the asset as is
+            .add(Text::new(self.svg))
+    }
+
+    /// The width of the glyph once scaled to the given height.
+    pub fn width(&self, height: f32) -> f32 {
+        self.width * height / self.height
+    }
+}
+
+pub const TREBLE_CLEF: Glyph = Glyph {
+    svg: include_str!("../../svgs/clef/treble-clef.svg"),
+    x: 0.0,
+    y: 0.0,
+    width: 15.19,
+    height: 40.77,
+};
+
+pub const BASS_CLEF: Glyph = Glyph {
+    svg: include_str!("../../svgs/clef/bass-clef.svg"),
+    x: 0.04,
+    y: 0.0,
+    width: 17.28,
+    height: 19.96,
+};
+
+pub const TENOR_CLEF: Glyph = Glyph {
+    svg: include_str!("../../svgs/clef/tenor-clef.svg"),
+    x: 0.0,
+    y: 0.0,
+    width: 16.56,
+    height: 25.0,
+};
+
+pub const SEMIMINIMA_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/semiminima-rest.svg"),
+    x: 0.0,
+    y: 0.0,
+    width: 26.22,
+    height: 74.74,
+};
+
+pub const QUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/quaver-rest.svg"),
+    x: 0.5,
+    y: 0.5,
+    width: 9.03,
+    height: 16.67,
+};
+
+pub const SEMIQUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/semiquaver-rest.svg"),
+    x: 0.5,
+    y: 0.5,
+    width: 11.66,
+    height: 25.65,
+};
+
+pub const DEMISEMIQUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/demisemiquaver-rest.svg"),
+    x: 1.0,
+    y: 1.0,
+    width: 13.58,
+    height: 34.6,
+};
+
+pub const HEMIDEMISEMIQUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/hemidemisemiquaver-rest.svg"),
+    x: 0.5,
+    y: 0.5,
+    width: 14.89,
+    height: 43.57,
+};
+
+pub const SEMIHEMIDEMISEMIQUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/semihemidemisemiquaver-rest.svg"),
+    x: 1.0,
+    y: 1.0,
+    width: 17.06,
+    height: 52.6,
+};
+
+pub const DEMISEMIHEMIDEMISEMIQUAVER_REST: Glyph = Glyph {
+    svg: include_str!("../../svgs/notes/demisemihemidemisemiquaver-rest.svg"),
+    x: 73.02,
+    y: 10.02,
+    width: 58.76,
+    height: 160.03,
+};
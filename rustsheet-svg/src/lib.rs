@@ -1,12 +1,14 @@
+mod glyphs;
 mod line;
 
+use line::LineMarks;
 use rustsheet::{
     bar::{Bar, LineType},
     MusicSheet,
 };
 use svg::{
     node::{
-        element::{Line, Rectangle, Text as TextElement},
+        element::{Group, Rectangle, Text as TextElement},
         Text,
     },
     Document,
@@ -17,6 +19,19 @@ const FONT_SIZE: &str = "font-size";
 const TEXT_ANCHOR: &str = "text-anchor";
 
 const MARGIN: usize = 10;
+/// Room left above and below a staff for notes on ledger lines
+const STAFF_PADDING: usize = 40;
+
+const PAGE_MARGIN: usize = 60;
+const TITLE_SIZE: usize = 48;
+const SUBTITLE_SIZE: usize = 36;
+const AUTHOR_SIZE: usize = 20;
+const HEADER_SPACING: usize = 10;
+const LABEL_SIZE: usize = 100;
+const LABEL_FONT_SIZE: usize = 16;
+const SYSTEM_SPACING: usize = 10;
+
+#[derive(Default)]
 pub struct MusicSheetSVGRenderer {}
 
 impl MusicSheetSVGRenderer {
@@ -24,15 +39,135 @@ impl MusicSheetSVGRenderer {
         Self {}
     }
 
-    pub fn render_sheet(&self, sheet: &MusicSheet, config: &RendererConfig) -> Vec<u8> {
-        let title = h1_centered(&sheet.title, 100);
-        let subtitle = sheet.subtitle.as_ref().map(|s| h2_centered(s, 150));
-        let author = Text::new(&sheet.author);
-        let mut doc = Document::new().set(VIEWBOX, (0, 0, 500, 1000)).add(title);
+    /// Engraves the whole sheet, returning one SVG document per page, so a sheet that
+    /// doesn't fit on a single page has to be written to one file per page. Pages are
+    /// as big as the paper, unless a system alone doesn't fit in it.
+    ///
+    /// The first page starts with the title, subtitle and author, then every line
+    /// follows as one or more systems, labeled with its name. Ties and slurs are
+    /// drawn over the notes they connect, even across systems, tempo markings and
+    /// changes over the staff they're on, and dynamics and hairpins under it.
+    /// Repeat barlines, the brackets of endings, segnos, codas and jumps go with their bars.
+    /// Lyrics go under the dynamics, a row for each verse, with the notes spaced out
+    /// so that their syllables don't run into each other. Chord symbols go over the
+    /// notes they start with, above the highest ones of the bar.
+    pub fn render_sheet(&self, sheet: &MusicSheet, config: &RendererConfig) -> Vec<Vec<u8>> {
+        let (width, height) = config.paper_size.dimensions();
+        let center = width / 2;
+        let right = width.saturating_sub(PAGE_MARGIN);
+        let bottom = height.saturating_sub(PAGE_MARGIN);
 
-        if let Some(t) = subtitle {
-            doc = doc.add(t);
+        let mut pages = Vec::new();
+        let mut doc = page(config);
+        // pages grow past the paper size for systems that can't fit in it
+        let mut size = (width, height);
+        let mut y = PAGE_MARGIN;
+
+        // header
+        y += TITLE_SIZE;
+        doc = doc.add(h1_centered(&sheet.title, center, y));
+        if let Some(subtitle) = &sheet.subtitle {
+            y += SUBTITLE_SIZE + HEADER_SPACING;
+            doc = doc.add(h2_centered(subtitle, center, y));
         }
+        y += AUTHOR_SIZE + HEADER_SPACING;
+        doc = doc.add(
+            TextElement::new()
+                .add(Text::new(escape(&sheet.author)))
+                .set(FONT_SIZE, AUTHOR_SIZE)
+                .set("x", right)
+                .set("y", y)
+                .set(TEXT_ANCHOR, "end"),
+        );
+        y += HEADER_SPACING;
+
+        // only indent the lines if at least one of them has a label
+        let indent = if sheet.lines.iter().any(|l| l.name.is_some()) {
+            LABEL_SIZE
+        } else {
+            0
+        };
+
+        for (l, line) in sheet.lines.iter().enumerate() {
+            let systems = line::layout_line(
+                &line.line,
+                PAGE_MARGIN,
+                indent,
+                right,
+                &LineMarks::of(sheet, l),
+                config,
+            );
+
+            for (i, system) in systems.into_iter().enumerate() {
+                // move on to the next page if the system doesn't fit in this one
+                if y + system.height > bottom && y > PAGE_MARGIN {
+                    pages.push(doc.set(VIEWBOX, (0, 0, size.0, size.1)));
+                    doc = page(config);
+                    size = (width, height);
+                    y = PAGE_MARGIN;
+                }
+                size.0 = size.0.max(system.width + PAGE_MARGIN);
+                size.1 = size.1.max(y + system.height + PAGE_MARGIN);
+
+                let mut g = Group::new()
+                    .set("transform", format!("translate(0 {})", y))
+                    .add(system.group);
+
+                if let (0, Some(name)) = (i, &line.name) {
+                    g = g.add(
+                        TextElement::new()
+                            .add(Text::new(escape(name)))
+                            .set(FONT_SIZE, LABEL_FONT_SIZE)
+                            .set("x", PAGE_MARGIN + indent - HEADER_SPACING)
+                            .set("y", system.height / 2)
+                            .set("dominant-baseline", "middle")
+                            .set(TEXT_ANCHOR, "end"),
+                    );
+                }
+
+                doc = doc.add(g);
+                y += system.height + SYSTEM_SPACING;
+            }
+        }
+        pages.push(doc.set(VIEWBOX, (0, 0, size.0, size.1)));
+
+        pages
+            .into_iter()
+            .map(|doc| {
+                let mut s = Vec::new();
+
+                svg::write(&mut s, &doc).unwrap();
+
+                s
+            })
+            .collect()
+    }
+
+    /// Lays out a line as wide as the paper, wrapping it onto as many systems as it needs
+    pub fn render_line(&self, line: &LineType, config: &RendererConfig) -> Vec<u8> {
+        let (width, _) = config.paper_size.dimensions();
+        let systems = line::layout_line(
+            line,
+            MARGIN,
+            0,
+            width.saturating_sub(MARGIN),
+            &LineMarks::default(),
+            config,
+        );
+
+        let mut doc = Document::new().add(bg_color(config.background_color));
+        let mut y = MARGIN;
+        let mut right = width;
+        for system in systems {
+            right = right.max(system.width + MARGIN);
+            doc = doc.add(
+                Group::new()
+                    .set("transform", format!("translate(0 {})", y))
+                    .add(system.group),
+            );
+            y += system.height;
+        }
+        doc = doc.set(VIEWBOX, (0, 0, right, y + MARGIN));
 
         let mut s = Vec::new();
 
@@ -41,12 +176,28 @@ impl MusicSheetSVGRenderer {
         s
     }
 
-    pub fn render_line(&self, line: &LineType, config: &RendererConfig) {}
-
     pub fn render_bar(&self, bar: &Bar, config: &RendererConfig) -> Vec<u8> {
-        let (rendered, bar_size) = line::generate_bar(MARGIN, MARGIN, true, bar, config);
+        let widths = line::slot_widths(bar, &[]);
+        let bar_size = line::bar_width(bar, &widths, true);
+        let (rendered, bar_size) = line::generate_bar(
+            (MARGIN, MARGIN + STAFF_PADDING),
+            bar_size,
+            true,
+            bar,
+            &widths,
+            &mut Default::default(),
+            config,
+        );
         let doc = Document::new()
-            .set(VIEWBOX, (0, 0, bar_size.0 + MARGIN, bar_size.1 + MARGIN))
+            .set(
+                VIEWBOX,
+                (
+                    0,
+                    0,
+                    bar_size.0 + MARGIN,
+                    bar_size.1 + STAFF_PADDING + MARGIN,
+                ),
+            )
             .add(bg_color(config.background_color))
             .add(rendered);
         let mut s = Vec::new();
@@ -62,7 +213,38 @@ pub struct RendererConfig<'a> {
     pub background_color: &'a str,
     pub octave_colors: [&'a str; 7],
     pub line_help_colors: [&'a str; 8],
+    /// Highlights the bars that don't add up to their time signature
     pub error_checking: bool,
+    /// The size of the pages of a rendered sheet, lines wrap onto a new system when
+    /// they would get wider than it
+    pub paper_size: PaperSize,
+}
+
+/// Paper sizes for rendering sheets, in SVG units.
+///
+/// A staff is 40 units high, so the standard sizes use 5 units per millimeter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PaperSize {
+    /// 210 x 297 mm
+    #[default]
+    A4,
+    /// 8.5 x 11 in
+    Letter,
+    Custom {
+        width: usize,
+        height: usize,
+    },
+}
+
+impl PaperSize {
+    /// Width and height of the page
+    pub fn dimensions(&self) -> (usize, usize) {
+        match self {
+            PaperSize::A4 => (1050, 1485),
+            PaperSize::Letter => (1080, 1397),
+            PaperSize::Custom { width, height } => (*width, *height),
+        }
+    }
 }
 
 impl Default for RendererConfig<'static> {
@@ -72,6 +254,7 @@ impl Default for RendererConfig<'static> {
             octave_colors: ["black"; 7],
             line_help_colors: ["#00000000"; 8],
             error_checking: false,
+            paper_size: PaperSize::A4,
         }
     }
 }
@@ -83,6 +266,7 @@ impl<'a> RendererConfig<'a> {
             octave_colors: ["black"; 7],
             line_help_colors: ["#00000000"; 8],
             error_checking: false,
+            paper_size: PaperSize::A4,
         }
     }
 }
@@ -96,24 +280,157 @@ fn bg_color(color: &str) -> Rectangle {
         .set("fill", color)
 }
 
-fn h1_centered(text: &str, height: u32) -> TextElement {
+fn page(config: &RendererConfig) -> Document {
+    Document::new().add(bg_color(config.background_color))
+}
+
+/// The svg crate writes text nodes as they are, so escape the markup characters
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn h1_centered(text: &str, x: usize, y: usize) -> TextElement {
     TextElement::new()
-        .add(Text::new(text))
-        .set(FONT_SIZE, 48)
-        .set("x", 250)
-        .set("y", height)
+        .add(Text::new(escape(text)))
+        .set(FONT_SIZE, TITLE_SIZE)
+        .set("x", x)
+        .set("y", y)
         .set(TEXT_ANCHOR, "middle")
 }
 
-fn h2_centered(text: &str, height: u32) -> TextElement {
+fn h2_centered(text: &str, x: usize, y: usize) -> TextElement {
     TextElement::new()
-        .add(Text::new(text))
-        .set(FONT_SIZE, 36)
-        .set("x", 250)
-        .set("y", height)
+        .add(Text::new(escape(text)))
+        .set(FONT_SIZE, SUBTITLE_SIZE)
+        .set("x", x)
+        .set("y", y)
         .set(TEXT_ANCHOR, "middle")
 }
 
 /*fn music_line(x: u32, y: u32) -> [Line; 5] {
     Line::new().set("x1", x).set("y1")
 }*/
+
+#[cfg(test)]
+mod tests {
+    use rustsheet::{
+        bar::{Line, LineType},
+        notation, MusicSheet,
+    };
+
+    use super::*;
+
+    /// The tuplet numbers drawn, as their text and where they're centered
+    fn tuplet_numbers(svg: &[u8]) -> Vec<(String, f32)> {
+        let svg = std::str::from_utf8(svg).unwrap();
+        svg.split("<text ")
+            .skip(1)
+            .filter(|text| text.contains(r#"font-style="italic""#))
+            .map(|text| {
+                let x = text.split(r#" x=""#).nth(1).unwrap();
+                let x = x[..x.find('"').unwrap()].parse().unwrap();
+                let number = text[text.find('>').unwrap() + 1..text.find('<').unwrap()].trim();
+                (number.to_string(), x)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renders_nested_tuplets() {
+        let input = "(3 (3 C4:16 D4 E4) F4:8 G4:8) D4:4 E4:2";
+        let renderer = MusicSheetSVGRenderer::new();
+        let config = RendererConfig::default();
+
+        // the inner triplet is centered on its second note, the outer one on its third
+        let svg = renderer.render_bar(&notation::parse_bar(input).unwrap(), &config);
+        let numbers = tuplet_numbers(&svg);
+        assert_eq!(numbers.len(), 2);
+        assert!(numbers.iter().all(|(number, _)| number == "3"));
+        assert!(numbers[0].1 < numbers[1].1);
+
+        let line = LineType::Single(notation::parse_line(input).unwrap());
+        assert_eq!(
+            tuplet_numbers(&renderer.render_line(&line, &config)).len(),
+            2
+        );
+
+        let sheet = MusicSheet::new(
+            "Tuplets".to_string(),
+            "Nobody".to_string(),
+            vec![Line::new(line)],
+        );
+        let pages = renderer.render_sheet(&sheet, &config);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(tuplet_numbers(&pages[0]).len(), 2);
+    }
+
+    /// The value of an attribute, on the first element it's set on
+    fn attribute(svg: &str, name: &str) -> f32 {
+        let value = svg.split(&format!(r#" {name}=""#)).nth(1).unwrap();
+        value[..value.find('"').unwrap()].parse().unwrap()
+    }
+
+    /// The width and height of the page
+    fn view_box(svg: &str) -> (f32, f32) {
+        let view_box = svg.split(r#"viewBox=""#).nth(1).unwrap();
+        let values: Vec<f32> = view_box[..view_box.find('"').unwrap()]
+            .split(' ')
+            .map(|v| v.parse().unwrap())
+            .collect();
+        (values[2], values[3])
+    }
+
+    /// The centers of the noteheads drawn
+    fn noteheads(svg: &str) -> Vec<(f32, f32)> {
+        svg.split("<ellipse")
+            .skip(1)
+            .map(|e| (attribute(e, "cx"), attribute(e, "cy")))
+            .collect()
+    }
+
+    #[test]
+    fn renders_on_paper_smaller_than_the_margins() {
+        let line = LineType::Single(notation::parse_line("C4:4 D4:4 E4:4 F4:4 |").unwrap());
+        let config = RendererConfig {
+            paper_size: PaperSize::Custom {
+                width: 5,
+                height: 5,
+            },
+            ..Default::default()
+        };
+        let renderer = MusicSheetSVGRenderer::new();
+
+        // the line is drawn lower by the margin
+        let svg = String::from_utf8(renderer.render_line(&line, &config)).unwrap();
+        let (width, height) = view_box(&svg);
+        let notes = noteheads(&svg);
+        assert_eq!(notes.len(), 4);
+        assert!(notes
+            .iter()
+            .all(|&(x, y)| x < width && y + (MARGIN as f32) < height));
+
+        let sheet = MusicSheet::new(
+            "Small".to_string(),
+            "Nobody".to_string(),
+            vec![Line::new(line)],
+        );
+        let pages: Vec<String> = renderer
+            .render_sheet(&sheet, &config)
+            .into_iter()
+            .map(|page| String::from_utf8(page).unwrap())
+            .collect();
+        // the header takes the first page, the system doesn't fit after it
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("Small"));
+        assert!(noteheads(&pages[0]).is_empty());
+
+        let (width, height) = view_box(&pages[1]);
+        let notes = noteheads(&pages[1]);
+        assert_eq!(notes.len(), 4);
+        assert!(notes
+            .iter()
+            .all(|&(x, y)| x < width && y + (PAGE_MARGIN as f32) < height));
+    }
+}
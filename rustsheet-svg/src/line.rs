@@ -1,10 +1,24 @@
-use rustsheet::bar::Bar;
+use rustsheet::{
+    bar::{Bar, Clef, LineType, NotePosition, SingleLine},
+    dynamics::{Dynamic, DynamicMark},
+    harmony::ChordSymbol,
+    key::{AccidentalState, KeySignature},
+    lyrics::Lyric,
+    notes::{Articulation, Note, NoteInfo, Octave, Ornament, Tone, ToneModifiers, Tuplet},
+    repeat::{Marker, Volta},
+    span::{Span, SpanKind},
+    tempo::{Duration, NoteSize, NoteSizeModifiers, NoteSizeUnit, Tempo, TimeSignature},
+    MusicSheet,
+};
 use svg::{
-    node::element::{Circle, Group, Line},
+    node::{
+        element::{Circle, Ellipse, Group, Line, Path, Rectangle, Text as TextElement},
+        Text,
+    },
     Node,
 };
 
-use crate::RendererConfig;
+use crate::{escape, glyphs, RendererConfig, STAFF_PADDING};
 
 const VERTICAL_NOTE_MARGIN: usize = 5;
 const LINES_MARGIN: usize = 2 * VERTICAL_NOTE_MARGIN;
@@ -12,41 +26,1070 @@ const LINES_THICKNESS: usize = 1;
 const STROKE_COLOR: &str = "black";
 const STROKE: &str = "stroke";
 const STROKE_WIDTH: &str = "stroke-width";
+const FILL: &str = "fill";
+const ERROR_COLOR: &str = "red";
 
 const NOTES_MARGIN: usize = 25;
 const MIN_SIZE_BAR: usize = 50;
 const NOTE_SIZE: usize = 25;
 const CLEF_SIZE: usize = 40;
 const TIME_SIGNATURE_SIZE: usize = 25;
+const KEY_ACCIDENTAL_SIZE: usize = 10;
 const END_BAR_MARGIN: usize = 5;
+const STAFF_SPACING: usize = 60;
+const BRACE_SIZE: usize = 15;
 
-pub fn generate_bar(
+const NOTEHEAD_RX: f32 = 6.0;
+const NOTEHEAD_RY: f32 = 4.5;
+const STEM_LENGTH: f32 = 35.0;
+const FLAG_SPACING: f32 = 7.0;
+const GRACE_SCALE: f32 = 0.6;
+const GRACE_OFFSET: f32 = 18.0;
+const TUPLET_SPACING: f32 = 8.0;
+const TUPLET_HOOK: f32 = 5.0;
+const TUPLET_NUMBER_SIZE: f32 = 12.0;
+const VOICE_REST_OFFSET: f32 = 2.0 * LINES_MARGIN as f32;
+const TIE_HEIGHT: f32 = 6.0;
+const SLUR_HEIGHT: f32 = 10.0;
+const SLUR_CLEARANCE: f32 = 6.0;
+const CURVE_THICKNESS: f32 = 1.5;
+const MARK_SPACING: f32 = 8.0;
+/// How far under the staff dynamics and hairpins go
+const DYNAMICS_OFFSET: f32 = 22.0;
+const DYNAMICS_SIZE: f32 = 16.0;
+/// Half the height of the open end of a hairpin
+const HAIRPIN_OPENING: f32 = 5.0;
+/// How far over the staff tempo markings and tempo changes go
+const TEMPO_OFFSET: f32 = 20.0;
+const TEMPO_SIZE: f32 = 14.0;
+/// Roughly how wide a letter of the tempo words is, for what goes after them
+const TEMPO_LETTER_WIDTH: f32 = 0.55 * TEMPO_SIZE;
+/// Room taken by the thick line, the thin line and the dots of a repeat barline
+const REPEAT_WIDTH: usize = 12;
+const REPEAT_DOT_RADIUS: f32 = 1.8;
+/// How far over the staff the bracket of an ending goes, and how far its ends go down
+const VOLTA_OFFSET: f32 = 32.0;
+const VOLTA_HOOK: f32 = 10.0;
+const VOLTA_SIZE: f32 = 12.0;
+/// Size of the segno and coda signs, over the start of their bar
+const SIGN_SIZE: f32 = 9.0;
+/// How far under the dynamics the first verse of lyrics goes, and each next one under it
+const LYRICS_OFFSET: f32 = 22.0;
+const LYRICS_SPACING: usize = 18;
+const LYRICS_SIZE: f32 = 14.0;
+/// About how wide a letter of the lyrics is, to keep syllables from running into each other
+const LYRICS_LETTER_WIDTH: f32 = 0.5 * LYRICS_SIZE;
+/// Room left between two syllables, or around the hyphen between them
+const LYRICS_GAP: f32 = 8.0;
+const HYPHEN_WIDTH: f32 = 5.0;
+
+const CHORD_SYMBOL_OFFSET: f32 = 10.0;
+const CHORD_SYMBOL_SIZE: f32 = 15.0;
+/// About how wide a letter of a chord symbol is
+const CHORD_SYMBOL_LETTER_WIDTH: f32 = 0.6 * CHORD_SYMBOL_SIZE;
+
+/// State carried from one bar to the next while laying out a line
+#[derive(Debug, Clone, Default)]
+pub struct LineState {
+    /// The last clef that was set, notes are placed relative to it
+    pub clef: Clef,
+    /// The last key signature that was set
+    pub key: KeySignature,
+    /// The last time signature that was set, to check the bars against
+    pub time_signature: TimeSignature,
+    /// The ending the last bar was in, its bracket goes on over the next bars of the ending
+    pub volta: Option<Volta>,
+}
+
+/// A row of staves, laid out to fit the page width.
+///
+/// The group is drawn with its top left corner at the origin.
+pub struct System {
+    pub group: Group,
+    /// How far right the system goes, past the page width if a bar alone doesn't fit in it
+    pub width: usize,
+    pub height: usize,
+}
+
+/// What goes over and under the notes of a line, pointing at them by position
+#[derive(Default)]
+pub struct LineMarks<'a> {
+    pub spans: Vec<&'a Span>,
+    pub dynamics: Vec<&'a DynamicMark>,
+    pub lyrics: Vec<&'a Lyric>,
+}
+
+impl<'a> LineMarks<'a> {
+    /// The spans starting on a line of the sheet, and its dynamics and lyrics
+    pub fn of(sheet: &'a MusicSheet, line: usize) -> Self {
+        Self {
+            spans: sheet
+                .spans
+                .iter()
+                .filter(|s| s.start.line == line)
+                .collect(),
+            dynamics: sheet
+                .dynamics
+                .iter()
+                .filter(|m| m.position.line == line)
+                .collect(),
+            lyrics: sheet
+                .lyrics
+                .iter()
+                .filter(|l| l.position.line == line)
+                .collect(),
+        }
+    }
+}
+
+/// Where a note was drawn, for the ties, slurs, hairpins, dynamics and lyrics that go to it
+#[derive(Debug, Clone)]
+struct Anchor {
+    /// Center of the first and the last head, apart for tuplets
+    x: (f32, f32),
+    /// Height of the head of each tone, from the lowest, none for rests and tuplets
+    heads: Vec<(Tone, f32)>,
+    /// Highest and lowest head
+    top: f32,
+    bottom: f32,
+    stem_down: bool,
+}
+
+/// The anchors of every note of a bar, voice by voice
+type BarAnchors = Vec<Vec<Anchor>>;
+
+/// The ends of a tie or a slur, and how far up (negative) or down it bends
+type Curve = ((f32, f32), (f32, f32), f32);
+
+/// The horizontal size a bar needs to fit everything in it, with slots as wide as `widths`
+pub fn bar_width(bar: &Bar, widths: &[usize], end: bool) -> usize {
+    let mut bar_size = MIN_SIZE_BAR + header_width(bar);
+    bar_size += widths.iter().sum::<usize>();
+
+    if end {
+        bar_size += END_BAR_MARGIN;
+    }
+    if bar.header.repeat_end.is_some() {
+        bar_size += REPEAT_WIDTH;
+    }
+
+    bar_size
+}
+
+/// How wide each slot of a bar is: a note with a margin on each side, or as wide as the
+/// longest syllable of `lyrics` sung in it or the chord symbol over it, so that
+/// neighbouring ones never touch
+pub fn slot_widths(bar: &Bar, lyrics: &[&Lyric]) -> Vec<usize> {
+    let onsets = bar_onsets(bar);
+    let default = (2 * NOTES_MARGIN) + NOTE_SIZE;
+    let mut widths = vec![default; onsets.len()];
+
+    for mark in &bar.chord_symbols {
+        let slot = onsets.partition_point(|onset| *onset < mark.at);
+        // the symbol starts at the left of the notehead and runs over the half of the next slot
+        let text = chord_symbol_text(&mark.symbol).chars().count() as f32;
+        let needed =
+            2.0 * (text * CHORD_SYMBOL_LETTER_WIDTH - NOTEHEAD_RX + LYRICS_GAP) - default as f32;
+        if let Some(width) = widths.get_mut(slot) {
+            *width = (*width).max(needed.ceil() as usize);
+        }
+    }
+
+    for lyric in lyrics {
+        let notes = bar.voice(lyric.position.voice);
+        let Some(before) = notes.get(..lyric.position.index) else {
+            continue;
+        };
+        let pos = before
+            .iter()
+            .fold(Duration::ZERO, |pos, note| pos + note.duration());
+        let slot = onsets.partition_point(|onset| *onset < pos);
+        let mut needed = lyric_width(&lyric.text) + LYRICS_GAP;
+        if lyric.syllabic.continues() {
+            needed += HYPHEN_WIDTH + LYRICS_GAP;
+        }
+        if let Some(width) = widths.get_mut(slot) {
+            *width = (*width).max(needed.ceil() as usize);
+        }
+    }
+
+    widths
+}
+
+/// The room the clef, key and time signature of a bar take before the notes
+fn header_width(bar: &Bar) -> usize {
+    let mut width = 0;
+    if bar.header.clef.is_some() {
+        width += CLEF_SIZE + NOTES_MARGIN;
+    }
+    if let Some(key) = bar.header.key_signature {
+        width += key_signature_width(&key);
+    }
+    if bar.header.time_signature.is_some() {
+        width += TIME_SIGNATURE_SIZE + NOTES_MARGIN;
+    }
+    if bar.header.repeat_start {
+        width += REPEAT_WIDTH;
+    }
+
+    width
+}
+
+/// Splits a line into systems that fit in `width`, starting each staff at `x`.
+/// The first system is moved right by `indent`, to leave room for a label.
+///
+/// Every bar is as wide as the widest of its staves, so that barlines
+/// line up on double lines. The ties and slurs of `marks` are drawn over the notes,
+/// its tempo changes over the staves and its hairpins and dynamics under them,
+/// split where the line goes on to the next system. Its lyrics go under the dynamics,
+/// a row for each verse, and make room for themselves by widening the slots they're in.
+pub fn layout_line(
+    line: &LineType,
     x: usize,
-    y: usize,
-    end: bool,
-    bar: &Bar,
+    indent: usize,
+    width: usize,
+    marks: &LineMarks,
     config: &RendererConfig,
-) -> (Group, (usize, usize)) {
-    // calculate bar length
-    let mut bar_size = MIN_SIZE_BAR;
-    if bar.header.clef.is_some() {
-        bar_size += CLEF_SIZE;
-        bar_size += NOTES_MARGIN;
+) -> Vec<System> {
+    let staves: Vec<&SingleLine> = match line {
+        LineType::Single(single) => vec![single],
+        LineType::Double(upper, lower) => vec![upper, lower],
+    };
+    let bar_count = staves.iter().map(|s| s.bars.len()).max().unwrap_or(0);
+    let staff_height = 4 * LINES_MARGIN;
+    // every staff leaves room for the verses of its lyrics under it
+    let rooms: Vec<usize> = (0..staves.len())
+        .map(|s| {
+            let verses = marks
+                .lyrics
+                .iter()
+                .filter(|l| l.position.staff == s)
+                .map(|l| usize::from(l.verse) + 1)
+                .max()
+                .unwrap_or(0);
+            verses * LYRICS_SPACING
+        })
+        .collect();
+    let last = staves.len() - 1;
+    let system_height = staff_top(last, &rooms) + staff_height + rooms[last] + STAFF_PADDING;
+
+    let empty = Bar::default();
+    let mut states = vec![LineState::default(); staves.len()];
+    let mut groups = Vec::new();
+    // where each note was drawn, by staff and bar, the system of every bar
+    // and where the notes of each system start and end
+    let mut anchors: Vec<Vec<BarAnchors>> = vec![Vec::new(); staves.len()];
+    let mut bar_systems = Vec::with_capacity(bar_count);
+    let mut bounds = Vec::new();
+    let mut index = 0;
+
+    while index < bar_count {
+        let first = index;
+        let system_x = if first == 0 { x + indent } else { x };
+        let staves_x = if staves.len() > 1 {
+            system_x + BRACE_SIZE
+        } else {
+            system_x
+        };
+        let mut cursor = staves_x;
+        let mut notes_x = cursor;
+        let mut g = Group::new();
+
+        while index < bar_count {
+            let end = index == bar_count - 1;
+            let bars: Vec<Bar> = staves
+                .iter()
+                .zip(&states)
+                .map(|(staff, state)| {
+                    let mut bar = staff.bars.get(index).unwrap_or(&empty).clone();
+                    // systems after the first one remind which clef and key are in use
+                    if index == first && index != 0 {
+                        bar.header.clef = bar.header.clef.or(Some(state.clef));
+                        bar.header.key_signature = bar.header.key_signature.or(Some(state.key));
+                    }
+                    bar
+                })
+                .collect();
+            let widths: Vec<Vec<usize>> = bars
+                .iter()
+                .enumerate()
+                .map(|(s, bar)| {
+                    let lyrics: Vec<&Lyric> = marks
+                        .lyrics
+                        .iter()
+                        .filter(|l| l.position.staff == s && l.position.bar == index)
+                        .copied()
+                        .collect();
+                    slot_widths(bar, &lyrics)
+                })
+                .collect();
+            let bar_size = bars
+                .iter()
+                .zip(&widths)
+                .map(|(b, w)| bar_width(b, w, end))
+                .max()
+                .unwrap_or(0);
+
+            // always put at least one bar in a system, even if it doesn't fit
+            if index != first && cursor + bar_size > width {
+                break;
+            }
+
+            for (i, (bar, state)) in bars.iter().zip(states.iter_mut()).enumerate() {
+                let staff_y = staff_top(i, &rooms);
+                let (rendered, _) = generate_bar(
+                    (cursor, staff_y),
+                    bar_size,
+                    end,
+                    bar,
+                    &widths[i],
+                    state,
+                    config,
+                );
+                g.append(rendered);
+                anchors[i].push(bar_anchors(
+                    (cursor, staff_y),
+                    bar,
+                    &widths[i],
+                    state.clef,
+                    config,
+                ));
+                if index == first {
+                    notes_x = notes_x.max(cursor + NOTES_MARGIN / 2 + header_width(bar));
+                }
+            }
+
+            bar_systems.push(groups.len());
+            cursor += bar_size;
+            index += 1;
+        }
+
+        if staves.len() > 1 {
+            let top = STAFF_PADDING;
+            let bottom = staff_top(last, &rooms) + staff_height;
+            g.append(draw_brace(
+                system_x as f32,
+                top as f32,
+                (bottom - top) as f32,
+            ));
+            // the starting line goes through all the staves
+            g.append(
+                Line::new()
+                    .set("x1", staves_x)
+                    .set("x2", staves_x)
+                    .set("y1", top)
+                    .set("y2", bottom)
+                    .set(STROKE, STROKE_COLOR)
+                    .set(STROKE_WIDTH, LINES_THICKNESS),
+            );
+        }
+
+        bounds.push((notes_x as f32, cursor as f32));
+        groups.push(g);
     }
 
-    if bar.header.time_signature.is_some() {
-        bar_size += TIME_SIGNATURE_SIZE;
-        bar_size += NOTES_MARGIN;
+    for span in &marks.spans {
+        draw_span(span, &anchors, &bar_systems, &bounds, &rooms, &mut groups);
+    }
+    for mark in &marks.dynamics {
+        if let Some((anchor, &system)) = find_anchor(&anchors, &bar_systems, mark.position) {
+            let y = dynamics_y(mark.position.staff, &rooms).max(anchor.bottom + DYNAMICS_SIZE);
+            groups[system].append(draw_dynamic(anchor.x.0, y, mark.dynamic));
+        }
+    }
+    for lyric in &marks.lyrics {
+        let next = next_syllable(lyric, &marks.lyrics);
+        draw_lyric(
+            lyric,
+            next,
+            &anchors,
+            &bar_systems,
+            &bounds,
+            &rooms,
+            &mut groups,
+        );
     }
-    // margin left, note, margin right
-    bar_size += bar.notes.len() * ((2 * NOTES_MARGIN) + NOTE_SIZE);
 
-    if end {
-        bar_size += END_BAR_MARGIN;
+    groups
+        .into_iter()
+        .zip(bounds)
+        .map(|(group, (_, right))| System {
+            group,
+            width: right as usize,
+            height: system_height,
+        })
+        .collect()
+}
+
+/// Top line of a staff of a system, counted from the top one, with the `rooms` left
+/// for lyrics under every staff
+fn staff_top(staff: usize, rooms: &[usize]) -> usize {
+    let lyrics: usize = rooms.iter().take(staff).sum();
+    STAFF_PADDING + staff * (4 * LINES_MARGIN + STAFF_SPACING) + lyrics
+}
+
+/// The middle of the dynamics and hairpins under a staff
+fn dynamics_y(staff: usize, rooms: &[usize]) -> f32 {
+    (staff_top(staff, rooms) + 4 * LINES_MARGIN) as f32 + DYNAMICS_OFFSET
+}
+
+/// The baseline of a verse of the lyrics under a staff
+fn lyrics_y(staff: usize, verse: u8, rooms: &[usize]) -> f32 {
+    dynamics_y(staff, rooms) + LYRICS_OFFSET + f32::from(verse) * LYRICS_SPACING as f32
+}
+
+/// About how wide a syllable is drawn
+fn lyric_width(text: &str) -> f32 {
+    text.chars().count() as f32 * LYRICS_LETTER_WIDTH
+}
+
+/// The syllable sung after `lyric` in the same verse of its voice, if there's one
+fn next_syllable<'a>(lyric: &Lyric, lyrics: &[&'a Lyric]) -> Option<&'a Lyric> {
+    let p = lyric.position;
+    lyrics
+        .iter()
+        .filter(|other| {
+            let o = other.position;
+            other.verse == lyric.verse
+                && (o.staff, o.voice) == (p.staff, p.voice)
+                && (o.bar, o.index) > (p.bar, p.index)
+        })
+        .min_by_key(|other| (other.position.bar, other.position.index))
+        .copied()
+}
+
+/// Draws a syllable centered under its note, in the row of its verse, then the hyphen
+/// to the `next` one of its word, or the extender line up to the last note it's held
+/// over before the next syllable or a rest, going on over the systems in between
+fn draw_lyric(
+    lyric: &Lyric,
+    next: Option<&Lyric>,
+    anchors: &[Vec<BarAnchors>],
+    bar_systems: &[usize],
+    bounds: &[(f32, f32)],
+    rooms: &[usize],
+    groups: &mut [Group],
+) {
+    let p = lyric.position;
+    let Some((anchor, &system)) = find_anchor(anchors, bar_systems, p) else {
+        return;
+    };
+    let y = lyrics_y(p.staff, lyric.verse, rooms);
+    let x = anchor.x.0;
+    let end = x + lyric_width(&lyric.text) / 2.0;
+    groups[system].append(
+        TextElement::new()
+            .add(Text::new(escape(&lyric.text)))
+            .set("x", x)
+            .set("y", y)
+            .set("font-size", LYRICS_SIZE)
+            .set("font-family", "serif")
+            .set("text-anchor", "middle")
+            .set(FILL, STROKE_COLOR),
+    );
+
+    let rule = |x1: f32, x2: f32, y: f32| {
+        Line::new()
+            .set("x1", x1)
+            .set("x2", x2)
+            .set("y1", y)
+            .set("y2", y)
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, LINES_THICKNESS)
+    };
+
+    if lyric.syllabic.continues() {
+        // halfway to the next syllable, or right after this one when it's on the next system
+        let to = next
+            .and_then(|n| Some((n, find_anchor(anchors, bar_systems, n.position)?)))
+            .filter(|(_, (_, &s))| s == system)
+            .map_or(end + LYRICS_GAP + HYPHEN_WIDTH, |(n, (a, _))| {
+                a.x.0 - lyric_width(&n.text) / 2.0
+            });
+        let center = (end + to) / 2.0;
+        let hyphen_y = y - LYRICS_SIZE / 3.0;
+        groups[system].append(rule(
+            center - HYPHEN_WIDTH / 2.0,
+            center + HYPHEN_WIDTH / 2.0,
+            hyphen_y,
+        ));
+        return;
+    }
+    if !lyric.extend {
+        return;
+    }
+
+    // the last note the syllable is held on
+    let mut held = None;
+    let until = next.map(|n| (n.position.bar, n.position.index));
+    'notes: for (b, bar) in anchors[p.staff].iter().enumerate().skip(p.bar) {
+        for (i, note) in bar.get(p.voice).into_iter().flatten().enumerate() {
+            if (b, i) <= (p.bar, p.index) {
+                continue;
+            }
+            if until.is_some_and(|until| (b, i) >= until) || note.heads.is_empty() {
+                break 'notes;
+            }
+            held = Some((note.x.1 + NOTEHEAD_RX, bar_systems[b]));
+        }
+    }
+    let Some((to, last)) = held else {
+        return;
+    };
+
+    for (s, &(left, right)) in bounds.iter().enumerate().take(last + 1).skip(system) {
+        let from = if s == system { end + 2.0 } else { left };
+        let to = if s == last { to } else { right };
+        if to > from {
+            groups[s].append(rule(from, to, y + 2.0));
+        }
+    }
+}
+
+/// Where the note at a position was drawn, and in which system
+fn find_anchor<'a>(
+    anchors: &'a [Vec<BarAnchors>],
+    bar_systems: &'a [usize],
+    p: NotePosition,
+) -> Option<(&'a Anchor, &'a usize)> {
+    anchors
+        .get(p.staff)?
+        .get(p.bar)?
+        .get(p.voice)?
+        .get(p.index)
+        .zip(bar_systems.get(p.bar))
+}
+
+/// Draws a tie, a slur, a hairpin or a tempo change, with one piece in the group of
+/// each system it goes through.
+///
+/// Ties go from every tone the notes share, away from the stem, or with the highest
+/// one up and the others down on chords. Slurs go over the notes if the first one has
+/// its stem down, and under them otherwise.
+fn draw_span(
+    span: &Span,
+    anchors: &[Vec<BarAnchors>],
+    bar_systems: &[usize],
+    bounds: &[(f32, f32)],
+    rooms: &[usize],
+    groups: &mut [Group],
+) {
+    let anchor = |p| find_anchor(anchors, bar_systems, p);
+    let (Some((start, &first)), Some((end, &last))) = (anchor(span.start), anchor(span.end)) else {
+        return;
+    };
+
+    let curves: Vec<Curve> = match span.kind {
+        SpanKind::Tie => start
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (tone, y1))| {
+                let (_, y2) = end.heads.iter().find(|(other, _)| other == tone)?;
+                let up = if start.heads.len() == 1 {
+                    start.stem_down
+                } else {
+                    i == start.heads.len() - 1
+                };
+                let side = if up { -1.0 } else { 1.0 };
+                let offset = side * (NOTEHEAD_RY + 1.0);
+                Some((
+                    (start.x.1 + NOTEHEAD_RX / 2.0, y1 + offset),
+                    (end.x.0 - NOTEHEAD_RX / 2.0, y2 + offset),
+                    side * TIE_HEIGHT,
+                ))
+            })
+            .collect(),
+        SpanKind::Slur => {
+            let y = |anchor: &Anchor| {
+                if start.stem_down {
+                    anchor.top - SLUR_CLEARANCE
+                } else {
+                    anchor.bottom + SLUR_CLEARANCE
+                }
+            };
+            let side = if start.stem_down { -1.0 } else { 1.0 };
+            vec![((start.x.0, y(start)), (end.x.1, y(end)), side * SLUR_HEIGHT)]
+        }
+        SpanKind::Crescendo | SpanKind::Diminuendo => {
+            let y = dynamics_y(span.start.staff, rooms)
+                .max(start.bottom.max(end.bottom) + DYNAMICS_SIZE);
+            let x1 = start.x.0 + 2.0 * NOTEHEAD_RX;
+            let x2 = end.x.0 - 2.0 * NOTEHEAD_RX;
+            for (system, path) in draw_hairpin(span.kind, (x1, x2, y), (first, last), bounds) {
+                groups[system].append(path);
+            }
+            return;
+        }
+        SpanKind::Accelerando | SpanKind::Ritardando => {
+            let y = staff_top(span.start.staff, rooms) as f32 - TEMPO_OFFSET;
+            let x1 = start.x.0 - NOTEHEAD_RX;
+            let x2 = end.x.0 - NOTEHEAD_RX;
+            for (system, g) in draw_tempo_change(span.kind, (x1, x2, y), (first, last), bounds) {
+                groups[system].append(g);
+            }
+            return;
+        }
+    };
+
+    for ((x1, y1), (x2, y2), height) in curves {
+        let systems = bounds.iter().enumerate().take(last + 1).skip(first);
+        for (system, &(left, right)) in systems {
+            // across a system break, the curve goes on to the end of the system
+            // and comes back at the start of the next one
+            let from = if system == first {
+                (x1, y1)
+            } else {
+                (left, y2)
+            };
+            let to = if system == last {
+                (x2, y2)
+            } else {
+                (right, y1)
+            };
+            groups[system].append(draw_curve(from, to, height));
+        }
+    }
+}
+
+/// Draws a hairpin from `x1` to `x2` centered on `y`, opening for a crescendo and
+/// closing for a diminuendo.
+///
+/// Across a system break, each piece opens (or closes) its share of the way, so the
+/// next one starts as open as the previous one ended.
+fn draw_hairpin(
+    kind: SpanKind,
+    (x1, x2, y): (f32, f32, f32),
+    (first, last): (usize, usize),
+    bounds: &[(f32, f32)],
+) -> Vec<(usize, Path)> {
+    let pieces = (last - first + 1) as f32;
+    let opening = |done: f32| {
+        let open = if kind == SpanKind::Crescendo {
+            done
+        } else {
+            1.0 - done
+        };
+        HAIRPIN_OPENING * open
+    };
+
+    bounds
+        .iter()
+        .enumerate()
+        .take(last + 1)
+        .skip(first)
+        .map(|(system, &(left, right))| {
+            let from = if system == first { x1 } else { left };
+            let to = if system == last { x2 } else { right };
+            let piece = (system - first) as f32;
+            let (o1, o2) = (opening(piece / pieces), opening((piece + 1.0) / pieces));
+            let path = Path::new()
+                .set(
+                    "d",
+                    format!(
+                        "M {} {} L {} {} M {} {} L {} {}",
+                        to,
+                        y - o2,
+                        from,
+                        y - o1,
+                        from,
+                        y + o1,
+                        to,
+                        y + o2,
+                    ),
+                )
+                .set(FILL, "none")
+                .set(STROKE, STROKE_COLOR)
+                .set(STROKE_WIDTH, LINES_THICKNESS);
+            (system, path)
+        })
+        .collect()
+}
+
+/// Draws "accel." or "rit." from `x1`, on the baseline `y`, and a dashed line after it
+/// up to `x2`, going on over the systems in between
+fn draw_tempo_change(
+    kind: SpanKind,
+    (x1, x2, y): (f32, f32, f32),
+    (first, last): (usize, usize),
+    bounds: &[(f32, f32)],
+) -> Vec<(usize, Group)> {
+    let text = if kind == SpanKind::Ritardando {
+        "rit."
+    } else {
+        "accel."
+    };
+
+    bounds
+        .iter()
+        .enumerate()
+        .take(last + 1)
+        .skip(first)
+        .map(|(system, &(left, right))| {
+            let mut g = Group::new();
+            let mut from = left;
+            if system == first {
+                g.append(
+                    TextElement::new()
+                        .add(Text::new(text))
+                        .set("x", x1)
+                        .set("y", y)
+                        .set("font-size", TEMPO_SIZE)
+                        .set("font-family", "serif")
+                        .set("font-style", "italic")
+                        .set(FILL, STROKE_COLOR),
+                );
+                from = x1 + (text.len() as f32 + 1.0) * TEMPO_LETTER_WIDTH;
+            }
+            let to = if system == last { x2 } else { right };
+            if to > from {
+                g.append(
+                    Line::new()
+                        .set("x1", from)
+                        .set("x2", to)
+                        .set("y1", y - TEMPO_SIZE / 4.0)
+                        .set("y2", y - TEMPO_SIZE / 4.0)
+                        .set(STROKE, STROKE_COLOR)
+                        .set(STROKE_WIDTH, LINES_THICKNESS)
+                        .set("stroke-dasharray", "4 3"),
+                );
+            }
+            (system, g)
+        })
+        .collect()
+}
+
+/// Draws a tempo marking from `x`, over the staff whose top line is at `y`:
+/// its words, then the note that gets the beat and how many there are in a minute
+fn draw_tempo(x: f32, y: f32, tempo: &Tempo, config: &RendererConfig) -> Group {
+    let base = y - TEMPO_OFFSET;
+    let text = |content: &str, x: f32, weight: &str| {
+        TextElement::new()
+            .add(Text::new(escape(content)))
+            .set("x", x)
+            .set("y", base)
+            .set("font-size", TEMPO_SIZE)
+            .set("font-family", "serif")
+            .set("font-weight", weight)
+            .set(FILL, STROKE_COLOR)
+    };
+
+    let mut g = Group::new();
+    let mut cx = x;
+    if let Some(words) = &tempo.text {
+        g.append(text(words, cx, "bold"));
+        cx += (words.chars().count() as f32 + 1.0) * TEMPO_LETTER_WIDTH;
+    }
+
+    // a small note in black, with its head just over the baseline, where a B4 would be
+    let plain = RendererConfig {
+        octave_colors: [STROKE_COLOR; 7],
+        ..*config
+    };
+    let head = base - NOTEHEAD_RY * GRACE_SCALE;
+    cx += NOTEHEAD_RX * GRACE_SCALE;
+    g.append(draw_chord(
+        cx,
+        head - (2 * LINES_MARGIN) as f32,
+        &[(Tone::new(Octave::B, 4), None)],
+        &tempo.beat,
+        &Clef::Treble,
+        Stem::Grace,
+        &plain,
+    ));
+    let dots = match tempo.beat.modifiers {
+        Some(NoteSizeModifiers::Dot) => 1.0,
+        Some(NoteSizeModifiers::DoubleDot) => 2.0,
+        Some(NoteSizeModifiers::TripleDot) => 3.0,
+        _ => 0.0,
+    };
+    cx += NOTEHEAD_RX * GRACE_SCALE + (dots + 1.0) * TEMPO_LETTER_WIDTH;
+    g.append(text(&format!("= {}", tempo.bpm), cx, "normal"));
+
+    g
+}
+
+/// How a chord symbol is printed, with the signs for sharps and flats
+fn chord_symbol_text(symbol: &ChordSymbol) -> String {
+    // the only `b` and `#` of a written symbol are accidentals
+    symbol.to_string().replace('#', "♯").replace('b', "♭")
+}
+
+/// Draws a chord symbol from `x`, on the baseline `y`
+fn draw_chord_symbol(x: f32, y: f32, symbol: &ChordSymbol) -> TextElement {
+    TextElement::new()
+        .add(Text::new(chord_symbol_text(symbol)))
+        .set("x", x)
+        .set("y", y)
+        .set("font-size", CHORD_SYMBOL_SIZE)
+        .set("font-family", "sans-serif")
+        .set(FILL, STROKE_COLOR)
+}
+
+/// Draws a thick and a thin line from `x`, going right (`direction` 1) for the start of
+/// a repeat or left (-1) for its end, with the dots on the inner side
+fn draw_repeat_barline(x: f32, y: f32, direction: f32) -> Group {
+    let line = |x: f32, width: usize| {
+        Line::new()
+            .set("x1", x)
+            .set("x2", x)
+            .set("y1", y)
+            .set("y2", y + (4 * LINES_MARGIN) as f32)
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, width)
+    };
+    let dot = |cy: f32| {
+        Circle::new()
+            .set(
+                "cx",
+                x + direction * (REPEAT_WIDTH as f32 - REPEAT_DOT_RADIUS),
+            )
+            .set("cy", cy)
+            .set("r", REPEAT_DOT_RADIUS)
+            .set(FILL, STROKE_COLOR)
+    };
+
+    Group::new()
+        .add(line(x, 3 * LINES_THICKNESS))
+        .add(line(x + direction * END_BAR_MARGIN as f32, LINES_THICKNESS))
+        // in the second and third spaces
+        .add(dot(y + 1.5 * LINES_MARGIN as f32))
+        .add(dot(y + 2.5 * LINES_MARGIN as f32))
+}
+
+/// Draws the part of the bracket of an ending over a bar, from `x1` to `x2`.
+/// The bracket is hooked down and numbered where it `starts`, and hooked down at its end
+/// if it's `closed`, when the ending goes back to the start of the repeat.
+fn draw_volta(x1: f32, x2: f32, y: f32, volta: &Volta, starts: bool, closed: bool) -> Group {
+    let top = y - VOLTA_OFFSET;
+    let mut d = format!("M {} {} H {}", x1, top, x2);
+    if starts {
+        d = format!("M {} {} V {} H {}", x1, top + VOLTA_HOOK, top, x2);
+    }
+    if closed {
+        d += &format!(" V {}", top + VOLTA_HOOK);
+    }
+
+    let mut g = Group::new().add(
+        Path::new()
+            .set("d", d)
+            .set(FILL, "none")
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, LINES_THICKNESS),
+    );
+    if starts {
+        g.append(
+            TextElement::new()
+                .add(Text::new(volta.to_string()))
+                .set("x", x1 + VOLTA_SIZE / 3.0)
+                .set("y", top + VOLTA_SIZE)
+                .set("font-size", VOLTA_SIZE)
+                .set("font-family", "serif")
+                .set(FILL, STROKE_COLOR),
+        );
+    }
+
+    g
+}
+
+/// Draws the segno and coda signs over the start of the bar, from `x1`, and the words
+/// over its end at `x2`: the fine, "To Coda" with its sign and the jump
+fn draw_markers(x1: f32, x2: f32, y: f32, bar: &Bar) -> Group {
+    let mut g = Group::new();
+    let sign_y = y - TEMPO_OFFSET - SIGN_SIZE;
+    let mut sign_x = x1 + SIGN_SIZE;
+    for marker in &bar.header.markers {
+        match marker {
+            Marker::Segno => g.append(draw_segno(sign_x, sign_y)),
+            Marker::Coda => g.append(draw_coda(sign_x, sign_y)),
+            Marker::ToCoda | Marker::Fine => continue,
+        }
+        sign_x += 3.0 * SIGN_SIZE;
+    }
+
+    let mut words = Vec::new();
+    let mut to_coda = false;
+    for marker in &bar.header.markers {
+        match marker {
+            Marker::Fine => words.push("Fine"),
+            Marker::ToCoda => to_coda = true,
+            Marker::Segno | Marker::Coda => {}
+        }
+    }
+    if let Some(jump) = bar.header.jump {
+        words.push(jump.text());
     }
+    let mut end = x2 - END_BAR_MARGIN as f32;
+    if to_coda {
+        g.append(draw_coda(end - SIGN_SIZE, sign_y));
+        end -= 2.5 * SIGN_SIZE;
+        words.push("To Coda");
+    }
+    if !words.is_empty() {
+        g.append(
+            TextElement::new()
+                .add(Text::new(words.join("  ")))
+                .set("x", end)
+                .set("y", y - TEMPO_OFFSET)
+                .set("font-size", TEMPO_SIZE)
+                .set("font-family", "serif")
+                .set("font-style", "italic")
+                .set("text-anchor", "end")
+                .set(FILL, STROKE_COLOR),
+        );
+    }
+
+    g
+}
+
+/// Draws a segno centered on (cx, cy): a slanted S crossed by a line, with a dot on each side
+fn draw_segno(cx: f32, cy: f32) -> Group {
+    let r = SIGN_SIZE;
+    let dot = |x: f32, y: f32| {
+        Circle::new()
+            .set("cx", x)
+            .set("cy", y)
+            .set("r", REPEAT_DOT_RADIUS)
+            .set(FILL, STROKE_COLOR)
+    };
+
+    Group::new()
+        .add(
+            Path::new()
+                .set(
+                    "d",
+                    format!(
+                        "M {} {} C {} {} {} {} {} {} S {} {} {} {}",
+                        cx + 0.5 * r,
+                        cy - 0.8 * r,
+                        cx - 0.2 * r,
+                        cy - 1.3 * r,
+                        cx - r,
+                        cy - 0.5 * r,
+                        cx,
+                        cy,
+                        cx + 0.2 * r,
+                        cy + 1.3 * r,
+                        cx - 0.5 * r,
+                        cy + 0.8 * r,
+                    ),
+                )
+                .set(FILL, "none")
+                .set(STROKE, STROKE_COLOR)
+                .set(STROKE_WIDTH, 2.0),
+        )
+        .add(
+            Line::new()
+                .set("x1", cx - 0.7 * r)
+                .set("y1", cy + r)
+                .set("x2", cx + 0.7 * r)
+                .set("y2", cy - r)
+                .set(STROKE, STROKE_COLOR)
+                .set(STROKE_WIDTH, LINES_THICKNESS),
+        )
+        .add(dot(cx - 0.7 * r, cy + 0.2 * r))
+        .add(dot(cx + 0.7 * r, cy - 0.2 * r))
+}
+
+/// Draws a coda sign centered on (cx, cy): a ring crossed by a vertical and a horizontal line
+fn draw_coda(cx: f32, cy: f32) -> Group {
+    let r = SIGN_SIZE;
+    let line = |x1: f32, y1: f32, x2: f32, y2: f32| {
+        Line::new()
+            .set("x1", x1)
+            .set("y1", y1)
+            .set("x2", x2)
+            .set("y2", y2)
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, LINES_THICKNESS)
+    };
+
+    Group::new()
+        .add(
+            Ellipse::new()
+                .set("cx", cx)
+                .set("cy", cy)
+                .set("rx", 0.6 * r)
+                .set("ry", 0.8 * r)
+                .set(FILL, "none")
+                .set(STROKE, STROKE_COLOR)
+                .set(STROKE_WIDTH, 2.0),
+        )
+        .add(line(cx, cy - 1.2 * r, cx, cy + 1.2 * r))
+        .add(line(cx - r, cy, cx + r, cy))
+}
+
+/// Draws a dynamic marking centered on `cx`, with `y` in the middle of its letters
+fn draw_dynamic(cx: f32, y: f32, dynamic: Dynamic) -> TextElement {
+    TextElement::new()
+        .add(Text::new(dynamic.symbol()))
+        .set("x", cx)
+        .set("y", y + DYNAMICS_SIZE / 3.0)
+        .set("font-size", DYNAMICS_SIZE)
+        .set("font-family", "serif")
+        .set("font-style", "italic")
+        .set("font-weight", "bold")
+        .set("text-anchor", "middle")
+        .set(FILL, STROKE_COLOR)
+}
+
+/// Draws a curve from one point to the other, bending `height` up (negative) or down,
+/// and thicker in the middle
+fn draw_curve((x1, y1): (f32, f32), (x2, y2): (f32, f32), height: f32) -> Path {
+    let mx = (x1 + x2) / 2.0;
+    let my = (y1 + y2) / 2.0;
+    let inner = height - CURVE_THICKNESS * height.signum();
+    Path::new()
+        .set(
+            "d",
+            format!(
+                "M {} {} Q {} {} {} {} Q {} {} {} {} Z",
+                x1,
+                y1,
+                mx,
+                my + 2.0 * height,
+                x2,
+                y2,
+                mx,
+                my + 2.0 * inner,
+                x1,
+                y1,
+            ),
+        )
+        .set(FILL, STROKE_COLOR)
+}
 
+/// Draws a bar at (x, y), with the top line of the staff at y.
+///
+/// `bar_size` should be at least [`bar_width`] with the same slot `widths`,
+/// any extra space is left after the notes.
+pub fn generate_bar(
+    (x, y): (usize, usize),
+    bar_size: usize,
+    end: bool,
+    bar: &Bar,
+    widths: &[usize],
+    state: &mut LineState,
+    config: &RendererConfig,
+) -> (Group, (usize, usize)) {
     let mut g = Group::new();
 
+    if let Some(ts) = bar.header.time_signature {
+        state.time_signature = ts;
+    }
+    // bars that don't add up to their time signature are highlighted, empty ones are rests
+    if config.error_checking && bar.has_notes() && !bar.fits(state.time_signature) {
+        g.append(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y - LINES_MARGIN)
+                .set("width", bar_size)
+                .set("height", 6 * LINES_MARGIN)
+                .set(FILL, ERROR_COLOR)
+                .set("fill-opacity", 0.2),
+        );
+    }
+
     // starting vertical line
     g.append(
         Line::new()
@@ -72,8 +1115,24 @@ pub fn generate_bar(
         );
     }
 
-    // if it's the last bar, draw one thin line, otherwise do a thin and a thick
-    if !end {
+    // if it's the last bar, or the end of a repeat, draw a thin and a thick line,
+    // otherwise just a thin one
+    if let Some(times) = bar.header.repeat_end {
+        let line_x = (x + bar_size) as f32;
+        g.append(draw_repeat_barline(line_x, y as f32, -1.0));
+        if times > 2 {
+            g.append(
+                TextElement::new()
+                    .add(Text::new(format!("×{}", times)))
+                    .set("x", line_x)
+                    .set("y", y as f32 - VOLTA_SIZE / 2.0)
+                    .set("font-size", VOLTA_SIZE)
+                    .set("font-family", "serif")
+                    .set("text-anchor", "end")
+                    .set(FILL, STROKE_COLOR),
+            );
+        }
+    } else if !end {
         g.append(
             Line::new()
                 .set("x1", x + bar_size)
@@ -104,17 +1163,1193 @@ pub fn generate_bar(
         );
     }
 
+    let mut cursor = x + NOTES_MARGIN / 2;
+
+    // repeats open right at the barline, or after the clef and key at the start of a system
+    if bar.header.repeat_start && bar.header.clef.is_none() {
+        g.append(draw_repeat_barline(x as f32, y as f32, 1.0));
+        cursor += REPEAT_WIDTH;
+    }
+
+    if let Some(volta) = &bar.header.volta {
+        g.append(draw_volta(
+            x as f32,
+            (x + bar_size) as f32,
+            y as f32,
+            volta,
+            state.volta.as_ref() != Some(volta),
+            bar.header.repeat_end.is_some(),
+        ));
+    }
+    state.volta = bar.header.volta.clone();
+    g.append(draw_markers(x as f32, (x + bar_size) as f32, y as f32, bar));
+
+    if let Some(tempo) = &bar.header.tempo {
+        // draw tempo marking, over everything else at the start of the bar
+        g.append(draw_tempo(cursor as f32, y as f32, tempo, config));
+    }
+
+    // notes are placed relative to the clef even if it isn't drawn
+    if let Some(clef) = bar.header.clef {
+        state.clef = clef;
+    }
+    let clef = state.clef;
+    if let Some(key) = bar.header.key_signature {
+        state.key = key;
+    }
     if let Some(clef) = bar.header.clef {
         // draw clef
+        g.append(draw_clef(cursor as f32, y as f32, &clef));
+        cursor += CLEF_SIZE + NOTES_MARGIN;
+    }
+
+    if let Some(key) = bar.header.key_signature {
+        // draw key signature
+        g.append(draw_key_signature(cursor as f32, y as f32, &key, &clef));
+        cursor += key_signature_width(&key);
+    }
+
+    if bar.header.repeat_start && bar.header.clef.is_some() {
+        g.append(draw_repeat_barline(
+            (cursor - NOTES_MARGIN / 2) as f32,
+            y as f32,
+            1.0,
+        ));
+        cursor += REPEAT_WIDTH;
     }
 
     if let Some(ts) = bar.header.time_signature {
         // draw time signature
+        g.append(draw_time_signature(cursor as f32, y as f32, &ts));
+        cursor += TIME_SIGNATURE_SIZE + NOTES_MARGIN;
     }
 
-    for note in &bar.notes {
-        // draw notes
+    let layout = NotesLayout {
+        cursor,
+        onsets: bar_onsets(bar),
+        widths,
+        y: y as f32,
+        clef,
+        config,
+    };
+    let mut top = y as f32;
+    for (v, notes) in bar.used_voices() {
+        let stem = voice_stem(bar, v);
+        // each voice keeps its own accidentals, as they're drawn one after the other
+        let mut accidentals = AccidentalState::new(state.key);
+        top = top.min(draw_notes(
+            &mut g,
+            notes,
+            Duration::ZERO,
+            (1, 1),
+            &layout,
+            stem,
+            &mut accidentals,
+        ));
+    }
+
+    if !bar.chord_symbols.is_empty() {
+        // chord symbols share a row over the notes, and over the tempo marking if there's one
+        let mut base = (y as f32 - CHORD_SYMBOL_OFFSET).min(top - MARK_SPACING);
+        if bar.header.tempo.is_some() {
+            base = base.min(y as f32 - TEMPO_OFFSET - TEMPO_SIZE - MARK_SPACING / 2.0);
+        }
+        for mark in &bar.chord_symbols {
+            g.append(draw_chord_symbol(
+                layout.x(mark.at) - NOTEHEAD_RX,
+                base,
+                &mark.symbol,
+            ));
+        }
     }
 
     (g, (x + bar_size, y + (4 * LINES_MARGIN)))
 }
+
+/// Where the notes of a bar drawn by [`generate_bar`] at (x, y) are
+fn bar_anchors(
+    (x, y): (usize, usize),
+    bar: &Bar,
+    widths: &[usize],
+    clef: Clef,
+    config: &RendererConfig,
+) -> BarAnchors {
+    let layout = NotesLayout {
+        cursor: x + NOTES_MARGIN / 2 + header_width(bar),
+        onsets: bar_onsets(bar),
+        widths,
+        y: y as f32,
+        clef,
+        config,
+    };
+
+    (0..bar.voices.len())
+        .map(|v| {
+            let stem = voice_stem(bar, v);
+            let mut pos = Duration::ZERO;
+            bar.voice(v)
+                .iter()
+                .map(|note| {
+                    let anchor = note_anchor(note, pos, (1, 1), &layout, stem);
+                    pos = pos + note.duration();
+                    anchor
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Where a note starting `pos` into the bar is drawn, in tuplets scaled by `ratio`
+fn note_anchor(
+    note: &Note,
+    pos: Duration,
+    (num, den): (u64, u64),
+    layout: &NotesLayout,
+    stem: Stem,
+) -> Anchor {
+    if let Note::Tuplet(tuplet) = note {
+        let ratio = (
+            num * u64::from(tuplet.normal()),
+            den * u64::from(tuplet.actual()),
+        );
+        let mut pos = pos;
+        let inner: Vec<Anchor> = tuplet
+            .notes()
+            .iter()
+            .map(|note| {
+                let anchor = note_anchor(note, pos, ratio, layout, stem);
+                pos = pos + note.duration().scale(ratio.0, ratio.1);
+                anchor
+            })
+            .collect();
+        // an empty tuplet is anchored where it starts, like a rest
+        if let (Some(first), Some(last)) = (inner.first(), inner.last()) {
+            return Anchor {
+                x: (first.x.0, last.x.1),
+                heads: Vec::new(),
+                top: inner.iter().map(|a| a.top).fold(f32::INFINITY, f32::min),
+                bottom: inner
+                    .iter()
+                    .map(|a| a.bottom)
+                    .fold(f32::NEG_INFINITY, f32::max),
+                stem_down: first.stem_down,
+            };
+        }
+    }
+
+    let x = layout.x(pos);
+    let steps: Vec<i32> = note
+        .tones()
+        .iter()
+        .map(|tone| steps_from_center(tone, &layout.clef))
+        .collect();
+    // rests sit on the middle line
+    let (low, high) = match (steps.first(), steps.last()) {
+        (Some(low), Some(high)) => (*low, *high),
+        _ => (0, 0),
+    };
+
+    Anchor {
+        x: (x, x),
+        heads: note
+            .tones()
+            .iter()
+            .zip(&steps)
+            .map(|(tone, steps)| (*tone, step_y(layout.y, *steps)))
+            .collect(),
+        top: step_y(layout.y, high),
+        bottom: step_y(layout.y, low),
+        stem_down: stem.is_down(low, high),
+    }
+}
+
+/// Which way the stems of a voice of the bar go, with more than one voice they tell them apart
+fn voice_stem(bar: &Bar, voice: usize) -> Stem {
+    let several = bar.used_voices().count() > 1;
+    match (several, voice % 2) {
+        (false, _) => Stem::Auto,
+        (true, 0) => Stem::Up,
+        (true, _) => Stem::Down,
+    }
+}
+
+/// Which way the stems of a note go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stem {
+    /// Up below the middle line, down from it, when the bar has a single voice
+    Auto,
+    Up,
+    Down,
+    /// Smaller, and always up
+    Grace,
+}
+
+impl Stem {
+    /// Whether the stem of notes from `low` to `high` steps from the middle line goes down
+    fn is_down(self, low: i32, high: i32) -> bool {
+        match self {
+            // down if the notes are mostly on or above the middle line
+            Stem::Auto => low + high >= 0,
+            Stem::Down => true,
+            Stem::Up | Stem::Grace => false,
+        }
+    }
+}
+
+/// Where the notes of a bar go, and what they're drawn relative to
+struct NotesLayout<'a, 'c> {
+    /// Where the first slot starts
+    cursor: usize,
+    /// When each slot starts from the start of the bar,
+    /// notes of different voices starting together share one
+    onsets: Vec<Duration>,
+    /// How wide each slot is
+    widths: &'a [usize],
+    y: f32,
+    clef: Clef,
+    config: &'a RendererConfig<'c>,
+}
+
+impl NotesLayout<'_, '_> {
+    /// Center of the slot of a note starting at `pos`
+    fn x(&self, pos: Duration) -> f32 {
+        let slot = self.onsets.partition_point(|onset| *onset < pos);
+        let start = self.cursor + self.widths.iter().take(slot).sum::<usize>();
+        let width = self
+            .widths
+            .get(slot)
+            .copied()
+            .unwrap_or((2 * NOTES_MARGIN) + NOTE_SIZE);
+        start as f32 + width as f32 / 2.0
+    }
+}
+
+/// When the notes of all the voices of a bar and its chord symbols start, each one needs a slot
+fn bar_onsets(bar: &Bar) -> Vec<Duration> {
+    let mut onsets = Vec::new();
+    for (_, notes) in bar.used_voices() {
+        push_onsets(notes, Duration::ZERO, (1, 1), &mut onsets);
+    }
+    // harmony can change while the notes are held
+    onsets.extend(bar.chord_symbols.iter().map(|mark| mark.at));
+    onsets.sort();
+    onsets.dedup();
+
+    onsets
+}
+
+/// Adds when the notes start from `pos`, taking tuplets apart, and returns when they end.
+///
+/// `ratio` is how much the tuplets the notes are in scale them.
+fn push_onsets(
+    notes: &[Note],
+    mut pos: Duration,
+    (num, den): (u64, u64),
+    onsets: &mut Vec<Duration>,
+) -> Duration {
+    for note in notes {
+        match note {
+            Note::Tuplet(tuplet) => {
+                let ratio = (
+                    num * u64::from(tuplet.normal()),
+                    den * u64::from(tuplet.actual()),
+                );
+                push_onsets(tuplet.notes(), pos, ratio, onsets);
+            }
+            _ => onsets.push(pos),
+        }
+        pos = pos + note.duration().scale(num, den);
+    }
+
+    pos
+}
+
+/// Draws notes of a voice starting `start` into the bar, each one in the slot of when it starts,
+/// returning the highest point they reach.
+///
+/// `ratio` is how much the tuplets the notes are in scale them.
+fn draw_notes(
+    g: &mut Group,
+    notes: &[Note],
+    start: Duration,
+    (num, den): (u64, u64),
+    layout: &NotesLayout,
+    stem: Stem,
+    accidentals: &mut AccidentalState,
+) -> f32 {
+    let (y, clef, config) = (layout.y, &layout.clef, layout.config);
+    let mut top = y;
+    let mut pos = start;
+
+    for note in notes {
+        let cx = layout.x(pos);
+        match note {
+            Note::Note(info) => {
+                let accidental = accidentals.accidental(&info.tone);
+                g.append(draw_note(cx, y, info, accidental, clef, stem, config));
+                let steps = steps_from_center(&info.tone, clef);
+                top = top
+                    .min(note_top(steps, steps, &info.size, y, stem))
+                    .min(draw_marks(g, cx, y, info, clef, stem));
+            }
+            Note::Chord(chord) => {
+                let tones: Vec<_> = chord
+                    .tones()
+                    .iter()
+                    .map(|tone| (*tone, accidentals.accidental(tone)))
+                    .collect();
+                g.append(draw_chord(cx, y, &tones, &chord.size, clef, stem, config));
+                let low = steps_from_center(&chord.lowest(), clef);
+                let high = steps_from_center(&chord.highest(), clef);
+                top = top.min(note_top(low, high, &chord.size, y, stem));
+            }
+            Note::Tuplet(tuplet) => {
+                let ratio = (
+                    num * u64::from(tuplet.normal()),
+                    den * u64::from(tuplet.actual()),
+                );
+                let inner = draw_notes(g, tuplet.notes(), pos, ratio, layout, stem, accidentals);
+                let mut onsets = Vec::new();
+                push_onsets(tuplet.notes(), pos, ratio, &mut onsets);
+                let last = onsets.last().copied().unwrap_or(pos);
+                let bracket_y = (inner - TUPLET_SPACING).min(y - LINES_MARGIN as f32);
+                g.append(draw_tuplet_bracket(
+                    cx - NOTEHEAD_RX - 2.0,
+                    layout.x(last) + NOTEHEAD_RX + 2.0,
+                    bracket_y,
+                    tuplet,
+                ));
+                top = top.min(bracket_y - TUPLET_NUMBER_SIZE / 2.0);
+            }
+            Note::WithGrace { grace, note } => {
+                let grace = NoteInfo::new(*grace, NoteSizeUnit::Quaver.into());
+                g.append(draw_grace_note(
+                    cx - GRACE_OFFSET,
+                    y,
+                    &grace,
+                    accidentals.accidental(&grace.tone),
+                    clef,
+                    config,
+                ));
+                let accidental = accidentals.accidental(&note.tone);
+                g.append(draw_note(cx, y, note, accidental, clef, stem, config));
+                let steps = steps_from_center(&note.tone, clef);
+                top = top
+                    .min(note_top(steps, steps, &note.size, y, stem))
+                    .min(draw_marks(g, cx, y, note, clef, stem))
+                    .min(
+                        step_y(y, steps_from_center(&grace.tone, clef)) - STEM_LENGTH * GRACE_SCALE,
+                    );
+            }
+            Note::Rest(size) => {
+                // rests of the upper voice go higher, and the ones of the lower voice lower
+                let offset = match stem {
+                    Stem::Up => -VOICE_REST_OFFSET,
+                    Stem::Down => VOICE_REST_OFFSET,
+                    Stem::Auto | Stem::Grace => 0.0,
+                };
+                g.append(draw_rest(cx, y + offset, size));
+            }
+        }
+        pos = pos + note.duration().scale(num, den);
+    }
+
+    top
+}
+
+/// The highest point of notes from `low` to `high` steps from the middle line,
+/// the end of their stem if it goes up
+fn note_top(low: i32, high: i32, size: &NoteSize, y: f32, stem: Stem) -> f32 {
+    let ny = step_y(y, high);
+    let has_stem = (size.unit as u8) < (NoteSizeUnit::SemiBreve as u8);
+    if has_stem && !stem.is_down(low, high) {
+        ny - STEM_LENGTH - flag_count(size.unit).saturating_sub(2) as f32 * FLAG_SPACING
+    } else {
+        ny - NOTEHEAD_RY
+    }
+}
+
+/// Draws the bracket of a tuplet from `x1` to `x2`, with its number in the middle.
+///
+/// The number is only the actual notes when the ratio is the usual one, like 3 for 3:2.
+fn draw_tuplet_bracket(x1: f32, x2: f32, by: f32, tuplet: &Tuplet) -> Group {
+    let (actual, normal) = (tuplet.actual(), tuplet.normal());
+    let number = if normal == Tuplet::usual_normal(actual) {
+        actual.to_string()
+    } else {
+        format!("{}:{}", actual, normal)
+    };
+    let center = (x1 + x2) / 2.0;
+    // leave a gap in the line for the number
+    let gap = number.len() as f32 * TUPLET_NUMBER_SIZE * 0.3 + 4.0;
+
+    let line = |xa: f32, xb: f32| {
+        Line::new()
+            .set("x1", xa)
+            .set("x2", xb)
+            .set("y1", by)
+            .set("y2", by)
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, LINES_THICKNESS)
+    };
+    let hook = |x: f32| {
+        Line::new()
+            .set("x1", x)
+            .set("x2", x)
+            .set("y1", by)
+            .set("y2", by + TUPLET_HOOK)
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, LINES_THICKNESS)
+    };
+
+    let mut g = Group::new().add(hook(x1)).add(hook(x2)).add(
+        TextElement::new()
+            .add(Text::new(number))
+            .set("x", center)
+            .set("y", by + TUPLET_NUMBER_SIZE / 3.0)
+            .set("font-size", TUPLET_NUMBER_SIZE)
+            .set("font-family", "serif")
+            .set("font-style", "italic")
+            .set("text-anchor", "middle")
+            .set(FILL, STROKE_COLOR),
+    );
+    // very short tuplets only get the number
+    if center - gap > x1 {
+        g = g.add(line(x1, center - gap)).add(line(center + gap, x2));
+    }
+
+    g
+}
+
+/// Number of staff positions (lines and spaces) between C0 and the tone.
+///
+/// Accidentals don't move a note on the staff, so only the name and octave matter.
+fn staff_steps(tone: &Tone) -> i32 {
+    tone.get_steps_since_c0() as i32
+}
+
+/// Staff positions of the tone relative to the middle line, positive is up
+fn steps_from_center(tone: &Tone, clef: &Clef) -> i32 {
+    staff_steps(tone) - staff_steps(&clef.get_center_tone())
+}
+
+/// Vertical coordinate of a staff position, given the top line of the staff
+fn step_y(y: f32, steps: i32) -> f32 {
+    y + (2 * LINES_MARGIN) as f32 - (steps * VERTICAL_NOTE_MARGIN as i32) as f32
+}
+
+fn draw_brace(x: f32, top: f32, height: f32) -> Path {
+    let w = BRACE_SIZE as f32 - 5.0;
+    let mid = top + height / 2.0;
+    let bottom = top + height;
+    // outer curve down to the tip, then the thicker inner curve back up
+    Path::new()
+        .set(
+            "d",
+            format!(
+                "M {x1} {top} \
+                 C {x02} {y05} {x09} {y42} {x} {mid} \
+                 C {x09} {y58} {x02} {y95} {x1} {bottom} \
+                 C {x05} {y95} {x13} {y60} {x} {mid} \
+                 C {x13} {y40} {x05} {y05} {x1} {top} Z",
+                x = x,
+                x1 = x + w,
+                x02 = x + 0.2 * w,
+                x05 = x + 0.5 * w,
+                x09 = x + 0.9 * w,
+                x13 = x + 1.3 * w,
+                top = top,
+                mid = mid,
+                bottom = bottom,
+                y05 = top + 0.05 * height,
+                y40 = top + 0.4 * height,
+                y42 = top + 0.42 * height,
+                y58 = top + 0.58 * height,
+                y60 = top + 0.6 * height,
+                y95 = top + 0.95 * height,
+            ),
+        )
+        .set(FILL, STROKE_COLOR)
+}
+
+fn draw_clef(x: f32, y: f32, clef: &Clef) -> Group {
+    let lines_margin = LINES_MARGIN as f32;
+    match clef {
+        // the curl wraps around the G line
+        Clef::Treble => glyphs::TREBLE_CLEF.place(x, y - lines_margin, 6.6 * lines_margin),
+        // the dots are around the F line
+        Clef::Bass => glyphs::BASS_CLEF.place(x, y - 1.0, 3.2 * lines_margin),
+        // the middle of the C clef points at its center tone
+        Clef::Tenor => glyphs::TENOR_CLEF.place(x, y, 4.0 * lines_margin),
+        Clef::None { .. } => Group::new(),
+    }
+}
+
+fn draw_time_signature(x: f32, y: f32, ts: &TimeSignature) -> Group {
+    let center = x + (TIME_SIGNATURE_SIZE as f32 / 2.0);
+    let number = |n: u8, baseline: f32| {
+        TextElement::new()
+            .add(Text::new(n.to_string()))
+            .set("x", center)
+            .set("y", baseline)
+            .set("font-size", 22)
+            .set("font-family", "serif")
+            .set("font-weight", "bold")
+            .set("text-anchor", "middle")
+    };
+
+    Group::new()
+        .add(number(ts.num.get(), y + 1.8 * LINES_MARGIN as f32))
+        .add(number(ts.den.get(), y + 3.8 * LINES_MARGIN as f32))
+}
+
+/// Horizontal space taken by a key signature, nothing for C major
+fn key_signature_width(key: &KeySignature) -> usize {
+    match key.accidentals().count() {
+        0 => 0,
+        count => count * KEY_ACCIDENTAL_SIZE + NOTES_MARGIN,
+    }
+}
+
+/// Staff positions of the accidentals of a key signature, relative to the middle line.
+///
+/// Every clef uses the same pattern, moved so that the accidentals stay on the staff.
+fn key_signature_steps(key: &KeySignature, clef: &Clef) -> impl Iterator<Item = i32> {
+    // F5 C5 G5 D5 A4 E5 B4 and B4 E5 A4 D5 G4 C5 F4 in treble clef
+    const SHARPS: [i32; 7] = [4, 1, 5, 2, -1, 3, 0];
+    const FLATS: [i32; 7] = [0, 3, -1, 2, -2, 1, -3];
+
+    let shift = match clef {
+        Clef::Treble | Clef::None { .. } => 0,
+        Clef::Tenor => -1,
+        Clef::Bass => -2,
+    };
+    let steps = if key.fifths() >= 0 { SHARPS } else { FLATS };
+
+    steps
+        .into_iter()
+        .take(key.accidentals().count())
+        .map(move |s| s + shift)
+}
+
+fn draw_key_signature(x: f32, y: f32, key: &KeySignature, clef: &Clef) -> Group {
+    let mut g = Group::new();
+
+    let accidentals = key.accidentals().map(|(_, modifiers)| modifiers);
+    for (i, (modifiers, steps)) in accidentals.zip(key_signature_steps(key, clef)).enumerate() {
+        let ax = x + (i * KEY_ACCIDENTAL_SIZE) as f32 + KEY_ACCIDENTAL_SIZE as f32 / 2.0;
+        g.append(draw_accidental(
+            ax,
+            step_y(y, steps),
+            modifiers,
+            1.0,
+            STROKE_COLOR,
+        ));
+    }
+
+    g
+}
+
+fn draw_note(
+    cx: f32,
+    y: f32,
+    info: &NoteInfo,
+    accidental: Option<ToneModifiers>,
+    clef: &Clef,
+    stem: Stem,
+    config: &RendererConfig,
+) -> Group {
+    draw_chord(
+        cx,
+        y,
+        &[(info.tone, accidental)],
+        &info.size,
+        clef,
+        stem,
+        config,
+    )
+}
+
+/// Draws tones played together on one stem, from the lowest, with the accidental
+/// each of them needs.
+///
+/// Heads a second apart can't sit side by side, so every other one of them goes
+/// on the other side of the stem.
+fn draw_chord(
+    cx: f32,
+    y: f32,
+    tones: &[(Tone, Option<ToneModifiers>)],
+    size: &NoteSize,
+    clef: &Clef,
+    stem: Stem,
+    config: &RendererConfig,
+) -> Group {
+    let grace = stem == Stem::Grace;
+    let scale = if grace { GRACE_SCALE } else { 1.0 };
+    let color = |tone: &Tone| config.octave_colors[usize::from(tone.octave.index())];
+    let steps: Vec<i32> = tones
+        .iter()
+        .map(|(tone, _)| steps_from_center(tone, clef))
+        .collect();
+    let (low, high) = (steps[0], steps[steps.len() - 1]);
+    let rx = NOTEHEAD_RX * scale;
+    let ry = NOTEHEAD_RY * scale;
+
+    let stem_down = stem.is_down(low, high);
+
+    let hollow = (size.unit as u8) >= (NoteSizeUnit::Minim as u8);
+    let wide = (size.unit as u8) >= (NoteSizeUnit::SemiBreve as u8);
+    let head_rx = if wide { rx * 1.2 } else { rx };
+
+    // heads a second above (or below, with the stem down) one in its place move over
+    let mut moved = vec![false; steps.len()];
+    let order: Vec<usize> = if stem_down {
+        (0..steps.len()).rev().collect()
+    } else {
+        (0..steps.len()).collect()
+    };
+    for pair in order.windows(2) {
+        let (before, i) = (pair[0], pair[1]);
+        moved[i] = (steps[i] - steps[before]).abs() == 1 && !moved[before];
+    }
+    let side = if stem_down { -1.0 } else { 1.0 };
+    let head_x = |i: usize| {
+        if moved[i] {
+            cx + side * (2.0 * rx - 1.0)
+        } else {
+            cx
+        }
+    };
+    let left = (0..steps.len()).map(head_x).fold(cx, f32::min) - head_rx;
+    let right = (0..steps.len()).map(head_x).fold(cx, f32::max) + head_rx;
+
+    let mut g = Group::new();
+
+    // ledger lines, every two steps outside of the staff
+    let ledger = |g: &mut Group, step: i32| {
+        let ly = step_y(y, step);
+        g.append(
+            Line::new()
+                .set("x1", left - 4.0)
+                .set("x2", right + 4.0)
+                .set("y1", ly)
+                .set("y2", ly)
+                .set(STROKE, STROKE_COLOR)
+                .set(STROKE_WIDTH, LINES_THICKNESS),
+        );
+    };
+    for step in (6..=high).step_by(2) {
+        ledger(&mut g, step);
+    }
+    for step in (6..=-low).step_by(2) {
+        ledger(&mut g, -step);
+    }
+
+    // accidentals go from the top, moving left when they'd touch one above them
+    let mut placed: Vec<(i32, usize)> = Vec::new();
+
+    for (i, ((tone, accidental), steps)) in tones.iter().zip(&steps).enumerate().rev() {
+        let color = color(tone);
+        let hx = head_x(i);
+        let ny = step_y(y, *steps);
+
+        // notehead
+        let mut head = Ellipse::new()
+            .set("cx", hx)
+            .set("cy", ny)
+            .set("rx", head_rx)
+            .set("ry", ry)
+            .set(STROKE, color);
+        if hollow {
+            head = head.set(FILL, "none").set(STROKE_WIDTH, 1.5 * scale);
+        } else {
+            head = head
+                .set(FILL, color)
+                .set("transform", format!("rotate(-20 {} {})", hx, ny));
+        }
+        g.append(head);
+
+        // breves and longer have vertical bars on both sides of the head
+        if (size.unit as u8) >= (NoteSizeUnit::Breve as u8) {
+            for bx in [hx - head_rx - 1.5, hx + head_rx + 1.5] {
+                g.append(
+                    Line::new()
+                        .set("x1", bx)
+                        .set("x2", bx)
+                        .set("y1", ny - ry - 1.0)
+                        .set("y2", ny + ry + 1.0)
+                        .set(STROKE, color)
+                        .set(STROKE_WIDTH, 1.5 * scale),
+                );
+            }
+        }
+
+        // accidental, only when the key and the bar so far don't already imply it
+        if let Some(modifiers) = accidental {
+            let mut column = 0;
+            while placed
+                .iter()
+                .any(|(s, c)| *c == column && (s - steps).abs() < 6)
+            {
+                column += 1;
+            }
+            placed.push((*steps, column));
+
+            g.append(draw_accidental(
+                left - (8.0 + column as f32 * 9.0) * scale,
+                ny,
+                *modifiers,
+                scale,
+                color,
+            ));
+        }
+
+        // the fermata goes once, above the highest note
+        if i == tones.len() - 1 || !matches!(size.modifiers, Some(NoteSizeModifiers::Crown)) {
+            g.append(draw_size_modifiers(
+                right, ny, *steps, size, y, scale, color,
+            ));
+        }
+    }
+
+    // the stem starts from the note furthest from where it ends
+    let (start, end) = if stem_down { (high, low) } else { (low, high) };
+    let stem_color = color(&tones[if stem_down { tones.len() - 1 } else { 0 }].0);
+    let flags = flag_count(size.unit);
+    let stem_length = (STEM_LENGTH + (flags.saturating_sub(2) as f32 * FLAG_SPACING)) * scale;
+    let has_stem = match size.unit {
+        NoteSizeUnit::SemiBreve | NoteSizeUnit::Breve => false,
+        // the longa and the maxima have their stem on the right, going down
+        NoteSizeUnit::Long | NoteSizeUnit::Large => {
+            g.append(
+                Line::new()
+                    .set("x1", right + 1.5)
+                    .set("x2", right + 1.5)
+                    .set("y1", step_y(y, high))
+                    .set("y2", step_y(y, low) + stem_length)
+                    .set(STROKE, stem_color)
+                    .set(STROKE_WIDTH, 1.5 * scale),
+            );
+            false
+        }
+        _ => true,
+    };
+
+    if has_stem {
+        let (sx, sy_end) = if stem_down {
+            (cx - rx + 0.5, step_y(y, end) + stem_length)
+        } else {
+            (cx + rx - 0.5, step_y(y, end) - stem_length)
+        };
+        g.append(
+            Line::new()
+                .set("x1", sx)
+                .set("x2", sx)
+                .set("y1", step_y(y, start))
+                .set("y2", sy_end)
+                .set(STROKE, stem_color)
+                .set(STROKE_WIDTH, 1.2 * scale),
+        );
+
+        for i in 0..flags {
+            let offset = i as f32 * FLAG_SPACING * scale;
+            let fy = if stem_down {
+                sy_end - offset
+            } else {
+                sy_end + offset
+            };
+            g.append(draw_flag(sx, fy, stem_down, scale, stem_color));
+        }
+    }
+
+    g
+}
+
+fn draw_grace_note(
+    cx: f32,
+    y: f32,
+    info: &NoteInfo,
+    accidental: Option<ToneModifiers>,
+    clef: &Clef,
+    config: &RendererConfig,
+) -> Group {
+    let ny = step_y(y, steps_from_center(&info.tone, clef));
+    let color = config.octave_colors[usize::from(info.tone.octave.index())];
+
+    // grace notes always have their stem up, with a slash through it
+    let mut g = draw_note(cx, y, info, accidental, clef, Stem::Grace, config);
+
+    let sx = cx + (NOTEHEAD_RX * GRACE_SCALE);
+    let top = ny - (STEM_LENGTH * GRACE_SCALE);
+    g.append(
+        Line::new()
+            .set("x1", sx - 4.0)
+            .set("x2", sx + 6.0)
+            .set("y1", top + 12.0)
+            .set("y2", top + 4.0)
+            .set(STROKE, color)
+            .set(STROKE_WIDTH, 1),
+    );
+    g
+}
+
+/// Number of flags on the stem of a note
+fn flag_count(unit: NoteSizeUnit) -> usize {
+    (NoteSizeUnit::Quaver as usize + 1).saturating_sub(unit as usize)
+}
+
+fn draw_flag(sx: f32, sy: f32, stem_down: bool, scale: f32, color: &str) -> Path {
+    // flags curve away from the notehead, so flip them for downwards stems
+    let d = if stem_down { -1.0 } else { 1.0 } * scale;
+    Path::new()
+        .set(
+            "d",
+            format!(
+                "M {} {} C {} {} {} {} {} {} C {} {} {} {} {} {} Z",
+                sx,
+                sy,
+                sx + 1.0 * scale,
+                sy + 6.0 * d,
+                sx + 9.0 * scale,
+                sy + 9.0 * d,
+                sx + 8.0 * scale,
+                sy + 20.0 * d,
+                sx + 7.0 * scale,
+                sy + 13.0 * d,
+                sx + 3.0 * scale,
+                sy + 10.0 * d,
+                sx,
+                sy + 8.0 * d,
+            ),
+        )
+        .set(FILL, color)
+}
+
+fn draw_accidental(
+    x: f32,
+    ny: f32,
+    modifiers: ToneModifiers,
+    scale: f32,
+    color: &str,
+) -> TextElement {
+    // the flat's bowl sits on the note, the other signs are centered on it
+    let baseline = match modifiers {
+        ToneModifiers::Flat => ny + 4.0 * scale,
+        _ => ny + 6.0 * scale,
+    };
+    TextElement::new()
+        .add(Text::new(modifiers.to_string()))
+        .set("x", x)
+        .set("y", baseline)
+        .set("font-size", 18.0 * scale)
+        .set("font-family", "serif")
+        .set("text-anchor", "middle")
+        .set(FILL, color)
+}
+
+/// Draws augmentation dots and the fermata (crown)
+fn draw_size_modifiers(
+    x: f32,
+    ny: f32,
+    steps: i32,
+    size: &NoteSize,
+    y: f32,
+    scale: f32,
+    color: &str,
+) -> Group {
+    let mut g = Group::new();
+
+    let dots = match size.modifiers {
+        Some(NoteSizeModifiers::Dot) => 1,
+        Some(NoteSizeModifiers::DoubleDot) => 2,
+        Some(NoteSizeModifiers::TripleDot) => 3,
+        _ => 0,
+    };
+    // dots go in a space, so move them up if the note is on a line
+    let dot_y = if steps % 2 == 0 {
+        ny - VERTICAL_NOTE_MARGIN as f32
+    } else {
+        ny
+    };
+    for i in 0..dots {
+        g.append(
+            Circle::new()
+                .set("cx", x + (5.0 + i as f32 * 4.0) * scale)
+                .set("cy", dot_y)
+                .set("r", 1.5 * scale)
+                .set(FILL, color),
+        );
+    }
+
+    if let Some(NoteSizeModifiers::Crown) = size.modifiers {
+        // above the staff, or above the note if it's higher than that
+        let base = (y - LINES_MARGIN as f32).min(ny - STEM_LENGTH - 5.0);
+        g.append(draw_fermata(x - NOTEHEAD_RX * scale, base, color));
+    }
+
+    g
+}
+
+/// Draws a fermata, an arc over a dot, with the ends of the arc at `base`
+fn draw_fermata(cx: f32, base: f32, color: &str) -> Group {
+    Group::new()
+        .add(
+            Path::new()
+                .set(
+                    "d",
+                    format!("M {} {} A 8 8 0 0 1 {} {}", cx - 8.0, base, cx + 8.0, base),
+                )
+                .set(FILL, "none")
+                .set(STROKE, color)
+                .set(STROKE_WIDTH, 1.5),
+        )
+        .add(
+            Circle::new()
+                .set("cx", cx)
+                .set("cy", base - 2.0)
+                .set("r", 1.5)
+                .set(FILL, color),
+        )
+}
+
+/// Draws the articulations of a note on the side of its head, from the closest one out,
+/// then its fermata and ornament above the staff, returning the highest point they reach
+fn draw_marks(g: &mut Group, cx: f32, y: f32, info: &NoteInfo, clef: &Clef, stem: Stem) -> f32 {
+    let steps = steps_from_center(&info.tone, clef);
+    let ny = step_y(y, steps);
+    let stem_down = stem.is_down(steps, steps);
+    let has_stem = (info.size.unit as u8) < (NoteSizeUnit::SemiBreve as u8);
+
+    // where the next mark goes above and below the note, clear of the stem
+    let mut above = if has_stem && !stem_down {
+        ny - STEM_LENGTH
+    } else {
+        ny - NOTEHEAD_RY
+    } - MARK_SPACING;
+    let mut below = if has_stem && stem_down {
+        ny + STEM_LENGTH
+    } else {
+        ny + NOTEHEAD_RY
+    } + MARK_SPACING;
+    let mut top = f32::INFINITY;
+
+    for articulation in &info.articulations {
+        if *articulation == Articulation::Fermata {
+            continue;
+        }
+        if stem_down {
+            g.append(draw_articulation(cx, above, *articulation, false));
+            top = above - MARK_SPACING / 2.0;
+            above -= MARK_SPACING;
+        } else {
+            g.append(draw_articulation(cx, below, *articulation, true));
+            below += MARK_SPACING;
+        }
+    }
+
+    // fermatas and ornaments are always above the staff
+    above = above.min(y - MARK_SPACING);
+    if info.articulations.contains(&Articulation::Fermata) {
+        g.append(draw_fermata(cx, above + 2.0, STROKE_COLOR));
+        top = above - MARK_SPACING;
+        above -= 1.5 * MARK_SPACING;
+    }
+    if let Some(ornament) = info.ornament {
+        g.append(draw_ornament(cx, above, ornament));
+        top = above - MARK_SPACING;
+    }
+
+    top
+}
+
+/// Draws an articulation centered on (cx, my), pointing at the note below it,
+/// or above it when `flipped`
+fn draw_articulation(cx: f32, my: f32, articulation: Articulation, flipped: bool) -> Group {
+    // towards the note
+    let d = if flipped { -1.0 } else { 1.0 };
+    let stroke = |path: String| {
+        Path::new()
+            .set("d", path)
+            .set(FILL, "none")
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, 1.5)
+    };
+
+    let g = Group::new();
+    match articulation {
+        Articulation::Staccato => g.add(
+            Circle::new()
+                .set("cx", cx)
+                .set("cy", my)
+                .set("r", 1.8)
+                .set(FILL, STROKE_COLOR),
+        ),
+        Articulation::Staccatissimo => g.add(
+            Path::new()
+                .set(
+                    "d",
+                    format!(
+                        "M {} {} L {} {} L {} {} Z",
+                        cx - 2.0,
+                        my - 4.0 * d,
+                        cx + 2.0,
+                        my - 4.0 * d,
+                        cx,
+                        my + 3.0 * d,
+                    ),
+                )
+                .set(FILL, STROKE_COLOR),
+        ),
+        Articulation::Tenuto => g.add(stroke(format!(
+            "M {} {} L {} {}",
+            cx - 5.0,
+            my,
+            cx + 5.0,
+            my
+        ))),
+        Articulation::Accent => g.add(stroke(format!(
+            "M {} {} L {} {} L {} {}",
+            cx - 5.0,
+            my - 3.0,
+            cx + 5.0,
+            my,
+            cx - 5.0,
+            my + 3.0,
+        ))),
+        // a wedge pointing away from the note
+        Articulation::Marcato => g.add(stroke(format!(
+            "M {} {} L {} {} L {} {}",
+            cx - 4.0,
+            my + 3.0 * d,
+            cx,
+            my - 4.0 * d,
+            cx + 4.0,
+            my + 3.0 * d,
+        ))),
+        Articulation::Fermata => g.add(draw_fermata(cx, my + 2.0, STROKE_COLOR)),
+    }
+}
+
+/// Draws an ornament centered on (cx, my)
+fn draw_ornament(cx: f32, my: f32, ornament: Ornament) -> Group {
+    let stroke = |path: String| {
+        Path::new()
+            .set("d", path)
+            .set(FILL, "none")
+            .set(STROKE, STROKE_COLOR)
+            .set(STROKE_WIDTH, 1.5)
+    };
+
+    let g = Group::new();
+    match ornament {
+        Ornament::Trill => g.add(
+            TextElement::new()
+                .add(Text::new("tr"))
+                .set("x", cx)
+                .set("y", my + 4.0)
+                .set("font-size", 14)
+                .set("font-family", "serif")
+                .set("font-style", "italic")
+                .set("font-weight", "bold")
+                .set("text-anchor", "middle")
+                .set(FILL, STROKE_COLOR),
+        ),
+        // a short zigzag, crossed by a line
+        Ornament::Mordent => g
+            .add(stroke(format!(
+                "M {} {} L {} {} L {} {} L {} {} L {} {}",
+                cx - 7.0,
+                my + 2.0,
+                cx - 3.5,
+                my - 2.0,
+                cx,
+                my + 2.0,
+                cx + 3.5,
+                my - 2.0,
+                cx + 7.0,
+                my + 2.0,
+            )))
+            .add(stroke(format!(
+                "M {} {} L {} {}",
+                cx,
+                my - 5.0,
+                cx,
+                my + 5.0
+            ))),
+        // an S lying on its side
+        Ornament::Turn => g.add(stroke(format!(
+            "M {} {} C {} {} {} {} {} {} C {} {} {} {} {} {}",
+            cx - 8.0,
+            my + 1.0,
+            cx - 9.0,
+            my - 5.0,
+            cx - 2.0,
+            my - 5.0,
+            cx,
+            my,
+            cx + 2.0,
+            my + 5.0,
+            cx + 9.0,
+            my + 5.0,
+            cx + 8.0,
+            my - 1.0,
+        ))),
+    }
+}
+
+fn draw_rest(cx: f32, y: f32, size: &NoteSize) -> Group {
+    let lines_margin = LINES_MARGIN as f32;
+    let block = |x: f32, top: f32, width: f32, height: f32| {
+        Rectangle::new()
+            .set("x", x)
+            .set("y", top)
+            .set("width", width)
+            .set("height", height)
+            .set(FILL, STROKE_COLOR)
+    };
+
+    let mut g = Group::new();
+    match size.unit {
+        NoteSizeUnit::Large => {
+            g.append(block(cx - 6.0, y + lines_margin, 4.0, 2.0 * lines_margin));
+            g.append(block(cx + 2.0, y + lines_margin, 4.0, 2.0 * lines_margin));
+        }
+        NoteSizeUnit::Long => g.append(block(cx - 2.0, y + lines_margin, 4.0, 2.0 * lines_margin)),
+        NoteSizeUnit::Breve => g.append(block(cx - 2.0, y + lines_margin, 4.0, lines_margin)),
+        // hangs from the fourth line
+        NoteSizeUnit::SemiBreve => g.append(block(cx - 6.0, y + lines_margin, 12.0, 5.0)),
+        // sits on the middle line
+        NoteSizeUnit::Minim => g.append(block(cx - 6.0, y + 1.5 * lines_margin, 12.0, 5.0)),
+        unit => {
+            let (glyph, top, height) = match unit {
+                NoteSizeUnit::Crotchet => (&glyphs::SEMIMINIMA_REST, 0.5, 3.0),
+                NoteSizeUnit::Quaver => (&glyphs::QUAVER_REST, 1.0, 2.0),
+                NoteSizeUnit::SemiQuaver => (&glyphs::SEMIQUAVER_REST, 1.0, 3.1),
+                NoteSizeUnit::DemiSemiQuaver => (&glyphs::DEMISEMIQUAVER_REST, 0.0, 4.15),
+                NoteSizeUnit::HemiDemiSemiQuaver => (&glyphs::HEMIDEMISEMIQUAVER_REST, -1.0, 5.2),
+                NoteSizeUnit::SemiHemiDemiSemiQuaver => {
+                    (&glyphs::SEMIHEMIDEMISEMIQUAVER_REST, -2.0, 6.3)
+                }
+                _ => (&glyphs::DEMISEMIHEMIDEMISEMIQUAVER_REST, -3.0, 7.3),
+            };
+            let height = height * lines_margin;
+            g.append(glyph.place(
+                cx - (glyph.width(height) / 2.0),
+                y + (top * lines_margin),
+                height,
+            ));
+        }
+    }
+
+    g.append(draw_size_modifiers(
+        cx + NOTEHEAD_RX,
+        step_y(y, 1),
+        1,
+        size,
+        y,
+        1.0,
+        STROKE_COLOR,
+    ));
+
+    g
+}
@@ -0,0 +1,336 @@
+use std::{fmt, str::FromStr};
+
+use rustsheet::{
+    notes::{NoteError, Tone},
+    transpose::Transposition,
+};
+use thiserror::Error;
+
+/// Semitones in the major and perfect intervals from a unison to a seventh
+const MAJOR_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// How an interval sounds compared to the major or perfect one of its number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quality {
+    /// Diminished once, twice or more, a semitone less than minor or perfect each time
+    Diminished(u8),
+    Minor,
+    Major,
+    Perfect,
+    /// Augmented once, twice or more, a semitone more than major or perfect each time
+    Augmented(u8),
+}
+
+/// The distance between two notes, counted in letters and spelled with a quality,
+/// like a minor third or an augmented fourth.
+///
+/// Numbers past 8 are compound intervals, a tenth is a third and an octave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    quality: Quality,
+    number: u8,
+}
+
+impl Interval {
+    pub const UNISON: Interval = Interval::unchecked(Quality::Perfect, 1);
+    pub const AUGMENTED_UNISON: Interval = Interval::unchecked(Quality::Augmented(1), 1);
+    pub const MINOR_SECOND: Interval = Interval::unchecked(Quality::Minor, 2);
+    pub const MAJOR_SECOND: Interval = Interval::unchecked(Quality::Major, 2);
+    pub const AUGMENTED_SECOND: Interval = Interval::unchecked(Quality::Augmented(1), 2);
+    pub const MINOR_THIRD: Interval = Interval::unchecked(Quality::Minor, 3);
+    pub const MAJOR_THIRD: Interval = Interval::unchecked(Quality::Major, 3);
+    pub const PERFECT_FOURTH: Interval = Interval::unchecked(Quality::Perfect, 4);
+    pub const AUGMENTED_FOURTH: Interval = Interval::unchecked(Quality::Augmented(1), 4);
+    pub const DIMINISHED_FIFTH: Interval = Interval::unchecked(Quality::Diminished(1), 5);
+    pub const PERFECT_FIFTH: Interval = Interval::unchecked(Quality::Perfect, 5);
+    pub const AUGMENTED_FIFTH: Interval = Interval::unchecked(Quality::Augmented(1), 5);
+    pub const MINOR_SIXTH: Interval = Interval::unchecked(Quality::Minor, 6);
+    pub const MAJOR_SIXTH: Interval = Interval::unchecked(Quality::Major, 6);
+    pub const AUGMENTED_SIXTH: Interval = Interval::unchecked(Quality::Augmented(1), 6);
+    pub const MINOR_SEVENTH: Interval = Interval::unchecked(Quality::Minor, 7);
+    pub const MAJOR_SEVENTH: Interval = Interval::unchecked(Quality::Major, 7);
+    pub const OCTAVE: Interval = Interval::unchecked(Quality::Perfect, 8);
+
+    const fn unchecked(quality: Quality, number: u8) -> Self {
+        Self { quality, number }
+    }
+
+    /// Creates an interval, or fails if the quality doesn't go with the number, like
+    /// a major fifth or a perfect third, or makes it go down, like a diminished unison.
+    /// ```
+    /// use rustsheet_utils::intervals::{Interval, Quality};
+    ///
+    /// fn main() {
+    ///     let tenth = Interval::new(Quality::Minor, 10).unwrap();
+    ///     assert_eq!(tenth.semitones(), 15);
+    ///
+    ///     assert!(Interval::new(Quality::Major, 5).is_err());
+    ///     assert!(Interval::new(Quality::Perfect, 0).is_err());
+    ///     assert!(Interval::new(Quality::Diminished(1), 1).is_err());
+    ///     assert!(Interval::new(Quality::Diminished(2), 2).is_err());
+    ///     assert!("ddd1".parse::<Interval>().is_err());
+    ///     // the same sound as a unison
+    ///     assert_eq!(Interval::new(Quality::Diminished(1), 2).unwrap().semitones(), 0);
+    /// }
+    /// ```
+    pub fn new(quality: Quality, number: u8) -> Result<Self, IntervalError> {
+        if number == 0 {
+            return Err(IntervalError::ZeroNumber);
+        }
+
+        let interval = Self { quality, number };
+        let fits = match quality {
+            Quality::Perfect => interval.is_perfect_class(),
+            Quality::Major | Quality::Minor => !interval.is_perfect_class(),
+            Quality::Diminished(0) | Quality::Augmented(0) => false,
+            Quality::Diminished(_) | Quality::Augmented(_) => true,
+        };
+        if !fits {
+            return Err(IntervalError::InvalidQuality(quality, number));
+        }
+        if interval.semitones() < 0 {
+            return Err(IntervalError::Negative(quality, number));
+        }
+
+        Ok(interval)
+    }
+
+    /// The interval from `from` up to `to`, counting the letters between them and the semitones.
+    /// If `to` is written lower, it's the interval from `to` up to `from` instead.
+    ///
+    /// Fails if the tones are too far apart for the number to fit in a `u8`, or if the one
+    /// written higher sounds lower, like B♯3 and C♭4.
+    /// ```
+    /// use rustsheet::notes::{Octave, Tone};
+    /// use rustsheet_utils::{intervals::Interval, tones};
+    ///
+    /// fn main() {
+    ///     let third = Interval::between(&tones::C4, &tones::E4_FLAT).unwrap();
+    ///     assert_eq!(third.to_string(), "m3");
+    ///     // the same sound, spelled differently
+    ///     let second = Interval::between(&tones::C4, &tones::D4_SHARP).unwrap();
+    ///     assert_eq!(second.to_string(), "A2");
+    ///     let fourth = Interval::between(&tones::F4, &tones::B4).unwrap();
+    ///     assert_eq!(fourth.to_string(), "A4");
+    ///     let twelfth = Interval::between(&tones::G4, &tones::C3).unwrap();
+    ///     assert_eq!(twelfth.to_string(), "P12");
+    ///
+    ///     assert!(Interval::between(&tones::C0, &Tone::new(Octave::C, 40)).is_err());
+    ///     assert!(Interval::between(&tones::B3_SHARP, &tones::C4_FLAT).is_err());
+    /// }
+    /// ```
+    pub fn between(from: &Tone, to: &Tone) -> Result<Interval, IntervalError> {
+        let (from, to) = if to.get_steps_since_c0() < from.get_steps_since_c0() {
+            (to, from)
+        } else {
+            (from, to)
+        };
+        let steps = to.get_steps_since_c0() - from.get_steps_since_c0();
+        let number = u8::try_from(steps + 1).map_err(|_| IntervalError::TooWide(steps))?;
+        let semitones = to.get_semitones_since_c0() as i32 - from.get_semitones_since_c0() as i32;
+
+        let interval = Self::unchecked(Quality::Perfect, number);
+        // how far it is from the major or perfect interval of the number
+        let off = semitones - interval.major_semitones();
+        let quality = match (interval.is_perfect_class(), off) {
+            (true, 0) => Quality::Perfect,
+            (false, 0) => Quality::Major,
+            (false, -1) => Quality::Minor,
+            (_, off) if off > 0 => Quality::Augmented(off as u8),
+            (true, off) => Quality::Diminished(-off as u8),
+            (false, off) => Quality::Diminished((-off - 1) as u8),
+        };
+
+        Self::new(quality, number)
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// 1 for a unison, 8 for an octave, more for compound intervals
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// How many semitones the interval spans
+    pub fn semitones(&self) -> i32 {
+        let major = self.major_semitones();
+        match self.quality {
+            Quality::Perfect | Quality::Major => major,
+            Quality::Minor => major - 1,
+            Quality::Augmented(n) => major + i32::from(n),
+            Quality::Diminished(n) if self.is_perfect_class() => major - i32::from(n),
+            Quality::Diminished(n) => major - 1 - i32::from(n),
+        }
+    }
+
+    /// How many letters the interval spans, 0 for a unison
+    pub fn steps(&self) -> i32 {
+        i32::from(self.number) - 1
+    }
+
+    /// Whether the interval is wider than an octave
+    pub fn is_compound(&self) -> bool {
+        self.number > 8
+    }
+
+    /// The interval without the octaves it spans, a tenth becomes a third. Octaves stay octaves.
+    ///
+    /// Fails if that makes it go down, like a doubly diminished ninth.
+    pub fn simple(&self) -> Result<Interval, IntervalError> {
+        Self::new(self.quality, self.simple_number())
+    }
+
+    /// The interval that adds up to an octave with this one, like a minor sixth for a
+    /// major third. Compound intervals are inverted without their octaves.
+    ///
+    /// Fails if the inversion would go down, like the diminished unison of an augmented octave.
+    /// ```
+    /// use rustsheet_utils::intervals::Interval;
+    ///
+    /// fn main() {
+    ///     assert_eq!(Interval::MAJOR_THIRD.invert(), Ok(Interval::MINOR_SIXTH));
+    ///     assert_eq!(Interval::AUGMENTED_FOURTH.invert(), Ok(Interval::DIMINISHED_FIFTH));
+    ///     assert_eq!(Interval::OCTAVE.invert(), Ok(Interval::UNISON));
+    ///
+    ///     let ninth: Interval = "M9".parse().unwrap();
+    ///     assert_eq!(ninth.invert().unwrap().to_string(), "m7");
+    ///
+    ///     let octave: Interval = "A8".parse().unwrap();
+    ///     assert!(octave.invert().is_err());
+    /// }
+    /// ```
+    pub fn invert(&self) -> Result<Interval, IntervalError> {
+        let quality = match self.quality {
+            Quality::Perfect => Quality::Perfect,
+            Quality::Major => Quality::Minor,
+            Quality::Minor => Quality::Major,
+            Quality::Augmented(n) => Quality::Diminished(n),
+            Quality::Diminished(n) => Quality::Augmented(n),
+        };
+        Self::new(quality, 9 - self.simple_number())
+    }
+
+    /// The tone the interval above `tone`, spelled with the letter the interval counts
+    /// to when it can be written with a single accidental.
+    /// ```
+    /// use rustsheet_utils::{intervals::Interval, tones};
+    ///
+    /// fn main() {
+    ///     let third = Interval::MINOR_THIRD.above(&tones::C4).unwrap();
+    ///     assert_eq!(third, tones::E4_FLAT);
+    ///     assert_eq!(third.to_string(), "E4♭");
+    ///
+    ///     let sixth = Interval::MAJOR_SIXTH.below(&tones::C4).unwrap();
+    ///     assert_eq!(sixth.to_string(), "E3♭");
+    ///
+    ///     // spelled exactly, not as the B♭ with the same sound
+    ///     let sixth: Interval = "A6".parse().unwrap();
+    ///     assert_eq!(sixth.above(&tones::C4).unwrap(), tones::A4_SHARP);
+    ///     assert_eq!(sixth.above(&tones::C4).unwrap().to_string(), "A4♯");
+    ///     let fourth: Interval = "d4".parse().unwrap();
+    ///     assert_eq!(fourth.above(&tones::E4).unwrap().to_string(), "A4♭");
+    ///
+    ///     assert!(Interval::OCTAVE.below(&tones::C0).is_err());
+    /// }
+    /// ```
+    pub fn above(&self, tone: &Tone) -> Result<Tone, NoteError> {
+        tone.transpose((*self).into())
+    }
+
+    /// The tone the interval below `tone`, see [`Interval::above`]
+    pub fn below(&self, tone: &Tone) -> Result<Tone, NoteError> {
+        tone.transpose(Transposition::from(*self).inverse())
+    }
+
+    fn simple_number(&self) -> u8 {
+        match (self.number - 1) % 7 + 1 {
+            1 if self.number > 1 => 8,
+            number => number,
+        }
+    }
+
+    fn is_perfect_class(&self) -> bool {
+        matches!((self.number - 1) % 7, 0 | 3 | 4)
+    }
+
+    /// Semitones in the major or perfect interval of the same number
+    fn major_semitones(&self) -> i32 {
+        let steps = self.steps();
+        MAJOR_SEMITONES[(steps % 7) as usize] + 12 * (steps / 7)
+    }
+}
+
+/// Transposes up by the interval
+impl From<Interval> for Transposition {
+    fn from(interval: Interval) -> Self {
+        Transposition::diatonic(interval.steps(), interval.semitones())
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quality::Diminished(n) => write!(f, "{}", "d".repeat((*n).into())),
+            Quality::Minor => write!(f, "m"),
+            Quality::Major => write!(f, "M"),
+            Quality::Perfect => write!(f, "P"),
+            Quality::Augmented(n) => write!(f, "{}", "A".repeat((*n).into())),
+        }
+    }
+}
+
+/// Writes the quality and the number, like `m3`, `P5`, `A4` or `dd7`
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.quality, self.number)
+    }
+}
+
+/// Reads intervals the way they're written, like `m3`, `P5`, `A4`, `dd7` or `M10`
+impl FromStr for Interval {
+    type Err = IntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+        let (quality, number) = s.split_at(digits);
+
+        let repeated = |letter: char| {
+            (!quality.is_empty() && quality.chars().all(|c| c == letter))
+                .then(|| u8::try_from(quality.len()).ok())
+                .flatten()
+        };
+        let quality = match quality {
+            "m" => Quality::Minor,
+            "M" => Quality::Major,
+            "P" => Quality::Perfect,
+            _ => match (repeated('d'), repeated('A')) {
+                (Some(n), _) => Quality::Diminished(n),
+                (_, Some(n)) => Quality::Augmented(n),
+                _ => return Err(IntervalError::UnknownQuality(quality.to_string())),
+            },
+        };
+        let number = number
+            .parse()
+            .map_err(|_| IntervalError::InvalidNumber(number.to_string()))?;
+
+        Interval::new(quality, number)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IntervalError {
+    #[error("Intervals are counted from 1, a unison")]
+    ZeroNumber,
+    #[error("A {1} can't be {0}")]
+    InvalidQuality(Quality, u8),
+    #[error("{0}{1} goes down, intervals are counted up from the lower tone")]
+    Negative(Quality, u8),
+    #[error("Unknown quality {0:?}, expected m, M, P, d or A")]
+    UnknownQuality(String),
+    #[error("Invalid interval number {0:?}")]
+    InvalidNumber(String),
+    #[error("Interval numbers go up to 255, but the tones are {0} letters apart")]
+    TooWide(u32),
+}
@@ -1,3 +1,5 @@
+pub mod intervals;
+
 pub mod scales;
 
 pub mod tones;
@@ -1,53 +1,400 @@
-use rustsheet::notes::{Octave, Tone, ToneModifiers};
+use rustsheet::{
+    key::{KeySignature, Mode},
+    notes::{NoteError, Tone, ToneModifiers},
+    transpose::Transposition,
+};
+use thiserror::Error;
 
-pub struct MajorScale {
-    current: u32,
-    iter: u32,
+use crate::intervals::Interval;
+
+/// The scales [`Scale::new`] knows the pattern of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ScaleKind {
+    /// Also the ionian mode
+    #[default]
+    Major,
+    /// Also the aeolian mode
+    NaturalMinor,
+    /// The natural minor with a raised seventh
+    HarmonicMinor,
+    /// The natural minor with a raised sixth and seventh going up, natural going down
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    /// The minor pentatonic with an augmented fourth, written as a diminished fifth going down
+    Blues,
+    WholeTone,
+    /// Every semitone, with sharps going up and flats going down
+    Chromatic,
 }
 
-impl MajorScale {
-    /// Generates a major scale (if it exists) from the starting tone
-    ///
-    /// Major scales exist only for C, G, D, A, E, B, F# and C#
-    #[rustfmt::skip]
-    pub fn new(start: Tone) -> Option<Self> {
-        match start {
-            Tone { octave: Octave::C, modifiers: None, ..} |
-            Tone { octave: Octave::G, modifiers: None, ..} |
-            Tone { octave: Octave::D, modifiers: None, ..} |
-            Tone { octave: Octave::A, modifiers: None, ..} |
-            Tone { octave: Octave::E, modifiers: None, ..} |
-            Tone { octave: Octave::B, modifiers: None, ..} |
-            Tone { octave: Octave::F, modifiers: Some(ToneModifiers::Sharp), ..} |
-            Tone { octave: Octave::G, modifiers: Some(ToneModifiers::Flat), ..} |
-            Tone { octave: Octave::C, modifiers: Some(ToneModifiers::Sharp), ..} |
-            Tone { octave: Octave::D, modifiers: Some(ToneModifiers::Flat), ..} => 
-            Some(Self { current: start.get_semitones_since_c0(), iter: 0 }),
-            _ => None
+impl ScaleKind {
+    /// The degrees going up, as intervals from the tonic
+    pub fn ascending(&self) -> &'static [Interval] {
+        use Interval as I;
+        match self {
+            Self::Major => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MAJOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+                I::MAJOR_SEVENTH,
+            ],
+            Self::NaturalMinor => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SIXTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::HarmonicMinor => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SIXTH,
+                I::MAJOR_SEVENTH,
+            ],
+            Self::MelodicMinor => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+                I::MAJOR_SEVENTH,
+            ],
+            Self::Dorian => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::Phrygian => &[
+                I::UNISON,
+                I::MINOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SIXTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::Lydian => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MAJOR_THIRD,
+                I::AUGMENTED_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+                I::MAJOR_SEVENTH,
+            ],
+            Self::Mixolydian => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MAJOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::Locrian => &[
+                I::UNISON,
+                I::MINOR_SECOND,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::DIMINISHED_FIFTH,
+                I::MINOR_SIXTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::MajorPentatonic => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MAJOR_THIRD,
+                I::PERFECT_FIFTH,
+                I::MAJOR_SIXTH,
+            ],
+            Self::MinorPentatonic => &[
+                I::UNISON,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::Blues => &[
+                I::UNISON,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::AUGMENTED_FOURTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::WholeTone => &[
+                I::UNISON,
+                I::MAJOR_SECOND,
+                I::MAJOR_THIRD,
+                I::AUGMENTED_FOURTH,
+                I::AUGMENTED_FIFTH,
+                I::AUGMENTED_SIXTH,
+            ],
+            Self::Chromatic => &[
+                I::UNISON,
+                I::AUGMENTED_UNISON,
+                I::MAJOR_SECOND,
+                I::AUGMENTED_SECOND,
+                I::MAJOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::AUGMENTED_FOURTH,
+                I::PERFECT_FIFTH,
+                I::AUGMENTED_FIFTH,
+                I::MAJOR_SIXTH,
+                I::AUGMENTED_SIXTH,
+                I::MAJOR_SEVENTH,
+            ],
+        }
+    }
+
+    /// The degrees going down, as intervals from the tonic, lowest first
+    pub fn descending(&self) -> &'static [Interval] {
+        use Interval as I;
+        match self {
+            Self::MelodicMinor => Self::NaturalMinor.ascending(),
+            Self::Blues => &[
+                I::UNISON,
+                I::MINOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::DIMINISHED_FIFTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SEVENTH,
+            ],
+            Self::Chromatic => &[
+                I::UNISON,
+                I::MINOR_SECOND,
+                I::MAJOR_SECOND,
+                I::MINOR_THIRD,
+                I::MAJOR_THIRD,
+                I::PERFECT_FOURTH,
+                I::DIMINISHED_FIFTH,
+                I::PERFECT_FIFTH,
+                I::MINOR_SIXTH,
+                I::MAJOR_SIXTH,
+                I::MINOR_SEVENTH,
+                I::MAJOR_SEVENTH,
+            ],
+            _ => self.ascending(),
         }
     }
 }
 
-impl Iterator for MajorScale {
-    type Item = Tone;
+/// The scale of the mode of a key signature
+impl From<Mode> for ScaleKind {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Major => Self::Major,
+            Mode::Minor => Self::NaturalMinor,
+            Mode::Dorian => Self::Dorian,
+            Mode::Phrygian => Self::Phrygian,
+            Mode::Lydian => Self::Lydian,
+            Mode::Mixolydian => Self::Mixolydian,
+            Mode::Locrian => Self::Locrian,
+        }
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut num = self.current;
-        // FIXME: this code is hot garbage and doesnt work for scales that arent C
-        match self.iter {
-            0 => {}
-            1 | 2 | 4 | 5 | 6 => num += 2,
-            3 | 7 => num += 1,
-            _ => return None,
+/// The degrees of a scale over a tonic, spelled with the letters their intervals count to,
+/// so F major has a B♭ and not an A♯.
+///
+/// Tones that would need a double sharp or flat, like the seventh of G♯ harmonic minor,
+/// are written with the next letter instead.
+/// ```
+/// use rustsheet_utils::{scales::{Scale, ScaleKind}, tones};
+///
+/// fn main() {
+///     let scale = Scale::new(tones::F4, ScaleKind::Major);
+///     let tones: Vec<String> = scale.ascending().take(8).map(|t| t.to_string()).collect();
+///     assert_eq!(tones, ["F4", "G4", "A4", "B4♭", "C5", "D5", "E5", "F5"]);
+///
+///     let scale = Scale::new(tones::A4, ScaleKind::MelodicMinor);
+///     let up: Vec<String> = scale.ascending().skip(5).take(3).map(|t| t.to_string()).collect();
+///     assert_eq!(up, ["F5♯", "G5♯", "A5"]);
+///     let down: Vec<String> = scale.descending().skip(1).take(2).map(|t| t.to_string()).collect();
+///     assert_eq!(down, ["G4", "F4"]);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scale {
+    tonic: Tone,
+    ascending: Vec<Interval>,
+    descending: Vec<Interval>,
+}
+
+impl Scale {
+    pub fn new(tonic: Tone, kind: ScaleKind) -> Self {
+        Self {
+            tonic: without_natural(tonic),
+            ascending: kind.ascending().to_vec(),
+            descending: kind.descending().to_vec(),
         }
+    }
 
-        self.iter += 1;
-        if self.iter == 8 {
-            self.iter = 1;
+    /// Creates a scale from its degrees as intervals from the tonic, starting with the
+    /// unison and going up within an octave
+    /// ```
+    /// use rustsheet_utils::{intervals::Interval, scales::Scale, tones};
+    ///
+    /// fn main() {
+    ///     let pattern = ["P1", "m2", "M3", "P4", "P5", "m6", "m7"];
+    ///     let degrees = pattern.iter().map(|i| i.parse().unwrap()).collect();
+    ///     let scale = Scale::from_intervals(tones::E4, degrees).unwrap();
+    ///     assert_eq!(scale.ascending().nth(2).unwrap(), tones::G4_SHARP);
+    ///
+    ///     assert!(Scale::from_intervals(tones::E4, vec![Interval::MAJOR_SECOND]).is_err());
+    /// }
+    /// ```
+    pub fn from_intervals(tonic: Tone, degrees: Vec<Interval>) -> Result<Self, ScaleError> {
+        match degrees.first() {
+            None => return Err(ScaleError::Empty),
+            Some(first) if *first != Interval::UNISON => return Err(ScaleError::NoTonic(*first)),
+            _ => {}
+        }
+        for pair in degrees.windows(2) {
+            if pair[1].semitones() <= pair[0].semitones() || pair[1].number() < pair[0].number() {
+                return Err(ScaleError::NotAscending(pair[0], pair[1]));
+            }
+        }
+        if let Some(last) = degrees.last().filter(|last| last.number() > 7) {
+            return Err(ScaleError::TooWide(*last));
+        }
+
+        Ok(Self {
+            tonic: without_natural(tonic),
+            ascending: degrees.clone(),
+            descending: degrees,
+        })
+    }
+
+    /// The scale of a key signature from its tonic in octave `position`, failing for C♭0
+    /// ```
+    /// use rustsheet::key::KeySignature;
+    /// use rustsheet_utils::scales::Scale;
+    ///
+    /// fn main() {
+    ///     let key: KeySignature = "Eb major".parse().unwrap();
+    ///     let scale = Scale::from_key(key, 4).unwrap();
+    ///     let tones: Vec<String> = scale.ascending().take(7).map(|t| t.to_string()).collect();
+    ///     assert_eq!(tones, ["E4♭", "F4", "G4", "A4♭", "B4♭", "C5", "D5"]);
+    /// }
+    /// ```
+    pub fn from_key(key: KeySignature, position: u8) -> Result<Self, NoteError> {
+        let (octave, modifiers) = key.tonic();
+        let tonic = match modifiers {
+            Some(modifiers) => Tone::with_modifiers(octave, position, modifiers),
+            None => Some(Tone::new(octave, position)),
         }
+        .ok_or(NoteError::OutOfRange)?;
 
-        self.current = num;
+        Ok(Self::new(tonic, key.mode().into()))
+    }
 
-        Tone::from_semitones_since_c0(num)
+    pub fn tonic(&self) -> Tone {
+        self.tonic
     }
+
+    /// The degrees going up, as intervals from the tonic
+    pub fn degrees(&self) -> &[Interval] {
+        &self.ascending
+    }
+
+    /// The tones from the tonic up, until they go out of range
+    pub fn ascending(&self) -> impl Iterator<Item = Tone> + '_ {
+        (0..).map_while(|index| self.degree(&self.ascending, index))
+    }
+
+    /// The tones from the tonic down, until they go out of range
+    /// ```
+    /// use rustsheet_utils::{scales::{Scale, ScaleKind}, tones};
+    ///
+    /// fn main() {
+    ///     let scale = Scale::new(tones::C4, ScaleKind::Chromatic);
+    ///     let tones: Vec<String> = scale.descending().take(4).map(|t| t.to_string()).collect();
+    ///     assert_eq!(tones, ["C4", "B3", "B3♭", "A3"]);
+    ///
+    ///     let scale = Scale::new(tones::D0, ScaleKind::Major);
+    ///     assert_eq!(scale.descending().count(), 2);
+    /// }
+    /// ```
+    pub fn descending(&self) -> impl Iterator<Item = Tone> + '_ {
+        (0..).map_while(|index| self.degree(&self.descending, -index))
+    }
+
+    /// The tones of the scale from `low` to `high`, going up, over as many octaves as they span
+    /// ```
+    /// use rustsheet_utils::{scales::{Scale, ScaleKind}, tones};
+    ///
+    /// fn main() {
+    ///     let scale = Scale::new(tones::G4, ScaleKind::MajorPentatonic);
+    ///     let tones: Vec<String> = scale
+    ///         .range(&tones::C3, &tones::C5)
+    ///         .map(|t| t.to_string())
+    ///         .collect();
+    ///     assert_eq!(tones, ["D3", "E3", "G3", "A3", "B3", "D4", "E4", "G4", "A4", "B4"]);
+    /// }
+    /// ```
+    pub fn range<'a>(&'a self, low: &'a Tone, high: &'a Tone) -> impl Iterator<Item = Tone> + 'a {
+        let len = self.ascending.len() as i32;
+        let octaves = |tone: &Tone| i32::from(tone.position) - i32::from(self.tonic.position);
+        let first = (octaves(low) - 1) * len;
+        let last = (octaves(high) + 2) * len;
+
+        (first..last)
+            .filter_map(move |index| self.degree(&self.ascending, index))
+            .filter(move |tone| low <= tone && tone <= high)
+    }
+
+    /// The tone of the `index`th degree from the tonic, counting on into the octaves
+    /// above and below
+    fn degree(&self, degrees: &[Interval], index: i32) -> Option<Tone> {
+        let len = degrees.len() as i32;
+        let interval = degrees[index.rem_euclid(len) as usize];
+        let octaves = index.div_euclid(len);
+
+        self.tonic
+            .transpose(Transposition::diatonic(
+                interval.steps() + 7 * octaves,
+                interval.semitones() + 12 * octaves,
+            ))
+            .ok()
+    }
+}
+
+/// A written natural on the tonic would be kept on every degree
+fn without_natural(tone: Tone) -> Tone {
+    Tone {
+        modifiers: tone.modifiers.filter(|m| *m != ToneModifiers::Natural),
+        ..tone
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScaleError {
+    #[error("A scale needs at least one degree")]
+    Empty,
+    #[error("A scale starts with its tonic, not a {0}")]
+    NoTonic(Interval),
+    #[error("The degrees of a scale go up, but {1} comes after {0}")]
+    NotAscending(Interval, Interval),
+    #[error("The degrees of a scale stay within an octave, but {0} is wider")]
+    TooWide(Interval),
 }
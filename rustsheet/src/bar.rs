@@ -1,19 +1,35 @@
+use std::fmt;
+
+use thiserror::Error;
+
 use crate::{
-    notes::{Note, Octave, Tone},
-    tempo::TimeSignature,
+    harmony::ChordSymbolMark,
+    key::KeySignature,
+    notes::{Note, NoteError, Octave, Tone, TupletError},
+    repeat::{Jump, Marker, RepeatError, Volta},
+    span::SpanError,
+    tempo::{Duration, Tempo, TimeSignature},
+    transpose::Transposition,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NotePosition {
     pub line: usize,
+    /// 0 for single lines and the upper staff of double lines, 1 for the lower one
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub staff: usize,
     pub bar: usize,
+    /// 0 for the first voice of the bar, up to [`MAX_VOICES`] - 1
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub voice: usize,
     pub index: usize,
 }
 
 impl PartialOrd for NotePosition {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // you can only order notes if they're on the same line
-        if self.line != other.line {
+        // you can only order notes if they're in the same voice of the same staff
+        if self.line != other.line || self.staff != other.staff || self.voice != other.voice {
             return None;
         }
 
@@ -25,7 +41,26 @@ impl PartialOrd for NotePosition {
     }
 }
 
+/// Counted from 1, the way musicians do. The voice is only written when it isn't the first
+impl fmt::Display for NotePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, staff {}, bar {}, ",
+            self.line + 1,
+            self.staff + 1,
+            self.bar + 1,
+        )?;
+        if self.voice > 0 {
+            write!(f, "voice {}, ", self.voice + 1)?;
+        }
+        write!(f, "note {}", self.index + 1)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Clef {
     Treble,
     Tenor,
@@ -55,11 +90,58 @@ impl Clef {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BarHeader {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub clef: Option<Clef>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub time_signature: Option<TimeSignature>,
-    pub attributes: [Option<Tone>; 8],
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub key_signature: Option<KeySignature>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub tempo: Option<Tempo>,
+    /// Starts a section played again at the next end repeat
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub repeat_start: bool,
+    /// Ends a repeated section after the bar, with how many times it's played in all
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub repeat_end: Option<u8>,
+    /// Makes the bar part of an ending, only played on some of the passes
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub volta: Option<Volta>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub markers: Vec<Marker>,
+    /// Where the music goes back to after the bar
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub jump: Option<Jump>,
 }
 
 impl BarHeader {
@@ -69,29 +151,201 @@ impl BarHeader {
         Self {
             clef: Some(Clef::Bass),
             time_signature: Some(Default::default()),
-            attributes: Default::default(),
+            key_signature: None,
+            tempo: None,
+            repeat_start: false,
+            repeat_end: None,
+            volta: None,
+            markers: Vec::new(),
+            jump: None,
+        }
+    }
+
+    /// Checks the repeat count and the ending of the bar
+    pub fn check_repeats(&self) -> Result<(), RepeatError> {
+        match self.repeat_end {
+            Some(times) if times < 2 => Err(RepeatError::TooFewTimes(times)),
+            _ => self.volta.as_ref().map_or(Ok(()), Volta::check),
         }
     }
 }
 
+/// The most voices a bar can have
+pub const MAX_VOICES: usize = 4;
+
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bar {
     pub header: BarHeader,
-    pub notes: Vec<Note>,
+    /// Independent lines of notes sharing the staff, each one filling the bar on its own.
+    ///
+    /// Most bars have a single voice. With more, the first and third have their
+    /// stems up and the second and fourth down.
+    #[cfg_attr(
+        feature = "serde",
+        serde(alias = "notes", deserialize_with = "crate::serialization::voices")
+    )]
+    pub voices: Vec<Vec<Note>>,
+    /// Harmony written over the staff, in the order it's played
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub chord_symbols: Vec<ChordSymbolMark>,
 }
 
 impl Bar {
+    /// Creates a bar with a single voice
+    pub fn new(header: BarHeader, notes: Vec<Note>) -> Self {
+        Self {
+            header,
+            voices: vec![notes],
+            chord_symbols: Vec::new(),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
-        // FIXME: is this a crappy way to check?
-        self.notes.len() == 0 && self.header == Default::default()
+        self.voices.iter().all(Vec::is_empty)
+            && self.chord_symbols.is_empty()
+            && self.header == Default::default()
+    }
+
+    /// Whether any voice has notes, bars without any are whole bar rests
+    pub fn has_notes(&self) -> bool {
+        self.voices.iter().any(|voice| !voice.is_empty())
     }
 
-    pub fn check_time_signature(&self, signature: TimeSignature) -> bool {
-        true
+    /// The bar transposed with its key signature and chord symbols, as if it were in C major
+    /// when it doesn't set a key. Fails if a note goes out of range
+    /// or can't be spelled with one accidental.
+    pub fn transpose(&self, transposition: Transposition) -> Result<Bar, NoteError> {
+        self.transpose_in(transposition, &mut KeySignature::default())
+    }
+
+    /// Transposes the bar in `key`, which becomes the key the bar sets if it does
+    fn transpose_in(
+        &self,
+        transposition: Transposition,
+        key: &mut KeySignature,
+    ) -> Result<Bar, NoteError> {
+        if let Some(set) = self.header.key_signature {
+            *key = set;
+        }
+        let (moved, (steps, semitones)) = transposition.in_key(*key);
+        let by = Transposition::diatonic(steps, semitones);
+
+        Ok(Bar {
+            header: BarHeader {
+                key_signature: self.header.key_signature.map(|_| moved),
+                ..self.header.clone()
+            },
+            voices: self
+                .voices
+                .iter()
+                .map(|notes| notes.iter().map(|note| note.transpose(by)).collect())
+                .collect::<Result<_, _>>()?,
+            chord_symbols: self
+                .chord_symbols
+                .iter()
+                .map(|mark| {
+                    let symbol = mark.symbol.transpose(by);
+                    symbol.map(|symbol| ChordSymbolMark::new(mark.at, symbol))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The notes of a voice, none if the bar doesn't have it
+    pub fn voice(&self, voice: usize) -> &[Note] {
+        self.voices
+            .get(voice)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The voices of the bar that have notes, with their index
+    pub fn used_voices(&self) -> impl Iterator<Item = (usize, &[Note])> {
+        self.voices
+            .iter()
+            .enumerate()
+            .filter(|(_, notes)| !notes.is_empty())
+            .map(|(v, notes)| (v, notes.as_slice()))
+    }
+
+    /// How long the notes of a voice last
+    pub fn voice_duration(&self, voice: usize) -> Duration {
+        self.voice(voice).iter().map(Note::duration).sum()
+    }
+
+    /// How long the bar lasts, as long as its longest voice
+    pub fn duration(&self) -> Duration {
+        (0..self.voices.len())
+            .map(|v| self.voice_duration(v))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Compares the notes of a voice with how long the time signature says the bar should be.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::BarFill,
+    ///     notation,
+    ///     tempo::{Duration, TimeSignature},
+    /// };
+    ///
+    /// fn main() {
+    ///     let bar = notation::parse_bar("C5:4 D5:4 E5:4. & C4:2.").unwrap();
+    ///     let ts = TimeSignature::new(3, 4).unwrap();
+    ///
+    ///     assert_eq!(bar.check_time_signature(0, ts), BarFill::Overfull(Duration::new(1, 8)));
+    ///     assert_eq!(bar.check_time_signature(1, ts), BarFill::Full);
+    /// }
+    /// ```
+    pub fn check_time_signature(&self, voice: usize, signature: TimeSignature) -> BarFill {
+        let expected = signature.bar_duration();
+        let duration = self.voice_duration(voice);
+
+        match duration.cmp(&expected) {
+            std::cmp::Ordering::Less => BarFill::Underfull(expected - duration),
+            std::cmp::Ordering::Equal => BarFill::Full,
+            std::cmp::Ordering::Greater => BarFill::Overfull(duration - expected),
+        }
+    }
+
+    /// Whether every voice with notes fills the time signature exactly
+    pub fn fits(&self, signature: TimeSignature) -> bool {
+        self.used_voices()
+            .all(|(v, _)| self.check_time_signature(v, signature).is_full())
+    }
+
+    /// Index of the first note of a voice that goes past the end of a bar of `length`
+    pub fn overflowing_note(&self, voice: usize, length: Duration) -> Option<usize> {
+        let mut end = Duration::ZERO;
+        self.voice(voice).iter().position(|note| {
+            end = end + note.duration();
+            end > length
+        })
+    }
+}
+
+/// How the notes of a bar fill its time signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarFill {
+    Full,
+    /// The notes end early, by this much
+    Underfull(Duration),
+    /// The notes go past the end of the bar, by this much
+    Overfull(Duration),
+}
+
+impl BarFill {
+    pub fn is_full(&self) -> bool {
+        *self == BarFill::Full
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SingleLine {
     pub bars: Vec<Bar>,
     // represent all the stuff on top
@@ -111,23 +365,246 @@ impl SingleLine {
     pub fn sane_default() -> Self {
         let mut bars: Vec<Bar> = Vec::with_capacity(crate::STARTING_BAR_CAPACITY);
 
-        let bar = Bar {
-            header: BarHeader::sane_default(),
-            notes: Default::default(),
-        };
+        let bar = Bar::new(BarHeader::sane_default(), Vec::new());
 
         bars.push(bar);
 
         Self { bars }
     }
+
+    /// The line transposed, with every key signature it sets.
+    /// Fails if a note goes out of range or can't be spelled with one accidental.
+    pub fn transpose(&self, transposition: Transposition) -> Result<SingleLine, NoteError> {
+        let mut key = KeySignature::default();
+        let bars = self
+            .bars
+            .iter()
+            .map(|bar| bar.transpose_in(transposition, &mut key))
+            .collect::<Result<_, _>>()?;
+
+        Ok(SingleLine { bars })
+    }
+
+    /// When every bar starts, from the start of the line.
+    ///
+    /// Bars last as long as their time signature says, 4/4 until one is set, or as their
+    /// longest voice if it goes past it.
+    pub fn bar_starts(&self) -> Vec<Duration> {
+        let mut ts = TimeSignature::default();
+        let mut start = Duration::ZERO;
+        self.bars
+            .iter()
+            .map(|bar| {
+                if let Some(signature) = bar.header.time_signature {
+                    ts = signature;
+                }
+                let bar_start = start;
+                start = start + ts.bar_duration().max(bar.duration());
+                bar_start
+            })
+            .collect()
+    }
+
+    /// The bars in the order they're played, following the repeats and jumps.
+    ///
+    /// Sections are played as many times as their end repeat says, from the last start
+    /// repeat, or from the end of the previous section without one. Endings are only
+    /// played on their passes. A D.C. or D.S. is taken once, and after it sections are
+    /// played only once, skipping the endings that go back.
+    /// ```
+    /// use rustsheet::notation;
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line(
+    ///         "C4:1 mark=fine | repeat=start D4:1 | volta=1 repeat=end E4:1 | volta=2 F4:1 jump=D.C._al_Fine |"
+    ///     ).unwrap();
+    ///
+    ///     // the first ending is played once, the second one leads back to the start
+    ///     assert_eq!(line.performance_order(), [0, 1, 2, 1, 3, 0]);
+    ///     assert_eq!(line.unroll().bars.len(), 6);
+    /// }
+    /// ```
+    pub fn performance_order(&self) -> Vec<usize> {
+        let bars = &self.bars;
+        let mut order = Vec::new();
+        // the section being repeated and which time through it this is
+        let mut section = 0;
+        let mut pass = 1;
+        // whether the section is over, either repeated or through one of its endings
+        let mut done = false;
+        let mut going_back = false;
+        let mut jumped = false;
+        // where the music stops or leaves for the coda, after a jump
+        let mut until = None;
+        let mut i = 0;
+
+        while i < bars.len() {
+            let header = &bars[i].header;
+            if !going_back && (header.repeat_start || (done && header.volta.is_none())) {
+                section = i;
+                pass = 1;
+                done = false;
+            }
+            going_back = false;
+
+            if let Some(volta) = &header.volta {
+                let skip = if jumped {
+                    self.ending_repeats(i)
+                } else {
+                    !volta.plays_on(pass)
+                };
+                if skip {
+                    i += 1;
+                    continue;
+                }
+                done = true;
+            }
+            order.push(i);
+
+            if let Some(marker) = until.filter(|m| header.markers.contains(m)) {
+                until = None;
+                let coda = bars
+                    .iter()
+                    .skip(i + 1)
+                    .position(|b| b.header.markers.contains(&Marker::Coda));
+                match (marker, coda) {
+                    (Marker::ToCoda, Some(coda)) => {
+                        i += 1 + coda;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+
+            if let Some(times) = header.repeat_end.filter(|_| !jumped) {
+                if pass < times {
+                    pass += 1;
+                    i = section;
+                    going_back = true;
+                    done = false;
+                    continue;
+                }
+                done = true;
+            }
+
+            if let Some(jump) = header.jump.filter(|_| !jumped) {
+                jumped = true;
+                until = jump.until();
+                i = if jump.to_segno() {
+                    bars.iter()
+                        .position(|b| b.header.markers.contains(&Marker::Segno))
+                        .unwrap_or(0)
+                } else {
+                    0
+                };
+                continue;
+            }
+
+            i += 1;
+        }
+
+        order
+    }
+
+    /// The line as it's played, with its bars written out in [performance order](Self::performance_order)
+    /// and without repeats or jumps. Clefs, key and time signatures and tempos are written
+    /// again wherever the order changes them.
+    pub fn unroll(&self) -> SingleLine {
+        self.unroll_order(&self.performance_order())
+    }
+
+    /// The bars of the line in `order`, bars past its end being whole bar rests
+    pub(crate) fn unroll_order(&self, order: &[usize]) -> SingleLine {
+        // what's in use in every bar as written
+        let mut current = BarHeader::default();
+        let in_use: Vec<BarHeader> = self
+            .bars
+            .iter()
+            .map(|bar| {
+                let header = &bar.header;
+                current.clef = header.clef.or(current.clef);
+                current.time_signature = header.time_signature.or(current.time_signature);
+                current.key_signature = header.key_signature.or(current.key_signature);
+                current.tempo = header.tempo.clone().or(current.tempo.take());
+                current.clone()
+            })
+            .collect();
+
+        let mut current = BarHeader::default();
+        let bars = order
+            .iter()
+            .map(|&b| {
+                let Some(bar) = self.bars.get(b) else {
+                    return Bar::default();
+                };
+                let wanted = &in_use[b];
+                let header = BarHeader {
+                    clef: changed(&wanted.clef, &current.clef),
+                    time_signature: changed(&wanted.time_signature, &current.time_signature),
+                    key_signature: changed(&wanted.key_signature, &current.key_signature),
+                    tempo: changed(&wanted.tempo, &current.tempo),
+                    ..Default::default()
+                };
+                current = wanted.clone();
+                Bar {
+                    header,
+                    voices: bar.voices.clone(),
+                    chord_symbols: bar.chord_symbols.clone(),
+                }
+            })
+            .collect();
+
+        SingleLine { bars }
+    }
+
+    /// Whether the ending bar `index` is in goes back to the start of its section
+    fn ending_repeats(&self, index: usize) -> bool {
+        let volta = &self.bars[index].header.volta;
+        self.bars[index..]
+            .iter()
+            .take_while(|bar| bar.header.volta == *volta)
+            .any(|bar| bar.header.repeat_end.is_some())
+    }
+}
+
+/// The setting in use if it's not the one already in use
+fn changed<T: PartialEq + Clone>(wanted: &Option<T>, current: &Option<T>) -> Option<T> {
+    if wanted != current {
+        wanted.clone()
+    } else {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum LineType {
     Single(SingleLine),
     Double(SingleLine, SingleLine),
 }
 
+impl LineType {
+    /// The staves of the line, from the top
+    pub fn staves(&self) -> Vec<&SingleLine> {
+        match self {
+            LineType::Single(staff) => vec![staff],
+            LineType::Double(upper, lower) => vec![upper, lower],
+        }
+    }
+
+    /// Transposes every staff, see [`SingleLine::transpose`]
+    pub fn transpose(&self, transposition: Transposition) -> Result<LineType, NoteError> {
+        Ok(match self {
+            LineType::Single(staff) => LineType::Single(staff.transpose(transposition)?),
+            LineType::Double(upper, lower) => LineType::Double(
+                upper.transpose(transposition)?,
+                lower.transpose(transposition)?,
+            ),
+        })
+    }
+}
+
 impl From<SingleLine> for LineType {
     fn from(value: SingleLine) -> Self {
         LineType::Single(value)
@@ -141,9 +618,28 @@ impl Default for LineType {
 }
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Line {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub name: Option<String>,
     pub line: LineType,
+    /// For transposing instruments, how the notes the player reads sound, like a major
+    /// second down for a B♭ clarinet
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub transposition: Option<Transposition>,
+    /// Whether the notes of a transposing instrument are written as they sound,
+    /// instead of as the player reads them
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub concert_pitch: bool,
 }
 
 impl Line {
@@ -151,13 +647,235 @@ impl Line {
         Self {
             name: None,
             line: inner,
+            transposition: None,
+            concert_pitch: false,
         }
     }
 
     pub fn new_named(name: String, inner: LineType) -> Self {
         Self {
             name: Some(name),
-            line: inner,
+            ..Self::new(inner)
         }
     }
+
+    /// The same line with its notes transposed, the instrument stays the same
+    pub fn transpose(&self, transposition: Transposition) -> Result<Line, NoteError> {
+        Ok(Line {
+            name: self.name.clone(),
+            line: self.line.transpose(transposition)?,
+            ..*self
+        })
+    }
+
+    /// The line as it sounds, only transposing instruments written as they're read change
+    pub fn to_concert_pitch(&self) -> Result<Line, NoteError> {
+        match self.transposition {
+            Some(transposition) if !self.concert_pitch => Ok(Line {
+                concert_pitch: true,
+                ..self.transpose(transposition)?
+            }),
+            _ => Ok(self.clone()),
+        }
+    }
+
+    /// The line as the player reads it, only transposing instruments written at concert
+    /// pitch change
+    pub fn to_written_pitch(&self) -> Result<Line, NoteError> {
+        match self.transposition {
+            Some(transposition) if self.concert_pitch => Ok(Line {
+                concert_pitch: false,
+                ..self.transpose(transposition.inverse())?
+            }),
+            _ => Ok(self.clone()),
+        }
+    }
+}
+
+/// Problems found by [`MusicSheet::validate`](crate::MusicSheet::validate)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    /// The bar needs more notes, the position is right after its last one
+    #[error("{position}: the bar is short by {missing}")]
+    Underfull {
+        position: NotePosition,
+        missing: Duration,
+    },
+    /// The bar has too many notes, the position is the first one that doesn't fit
+    #[error("{position}: the bar is long by {extra}")]
+    Overfull {
+        position: NotePosition,
+        extra: Duration,
+    },
+    /// A tuplet whose notes don't add up to its ratio, the position is the tuplet
+    #[error("{position}: {error}")]
+    InvalidTuplet {
+        position: NotePosition,
+        error: TupletError,
+    },
+    /// A span that can't join its notes, the position is its start
+    #[error("{position}: {error}")]
+    InvalidSpan {
+        position: NotePosition,
+        error: SpanError,
+    },
+    /// A repeat played less than twice or an ending without passes, the position is the bar
+    #[error("{position}: {error}")]
+    InvalidRepeat {
+        position: NotePosition,
+        error: RepeatError,
+    },
+    /// A dynamic marking under a position without a note
+    #[error("{position}: there's no note for the dynamic to go under")]
+    MissingDynamicNote { position: NotePosition },
+    /// A syllable on a position without a note, or on a rest or a tuplet
+    #[error("{position}: there's no note to sing the syllable on")]
+    MissingLyricNote { position: NotePosition },
+    /// A chord symbol placed past the end of the bar, the position is the bar
+    #[error("{position}: the chord symbol at {at} is past the end of the bar")]
+    ChordSymbolOutsideBar {
+        position: NotePosition,
+        at: Duration,
+    },
+    /// The bar has more than [`MAX_VOICES`] voices, the position is the start of the first extra one
+    #[error("{position}: a bar can't have more than {MAX_VOICES} voices, found {voices}")]
+    TooManyVoices {
+        position: NotePosition,
+        voices: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation, tempo::TimeSignatureError};
+
+    fn signature(num: u8, den: u8) -> TimeSignature {
+        TimeSignature::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn refuses_time_signatures_with_a_zero() {
+        assert!(matches!(
+            TimeSignature::new(0, 4),
+            Err(TimeSignatureError::InvalidRatio(0, 4))
+        ));
+        assert!(matches!(
+            TimeSignature::new(3, 0),
+            Err(TimeSignatureError::InvalidRatio(3, 0))
+        ));
+        assert!(notation::parse_bar("time=4/0 C4:1").is_err());
+    }
+
+    #[test]
+    fn fills_bars_exactly() {
+        let bar = notation::parse_bar("C4:4 D4:4 E4:2").unwrap();
+        assert_eq!(bar.check_time_signature(0, signature(4, 4)), BarFill::Full);
+        assert!(bar.fits(signature(4, 4)));
+        // the same notes are a whole bar of 2/2 and of 8/8 too
+        assert!(bar.fits(signature(2, 2)));
+        assert!(bar.fits(signature(8, 8)));
+
+        let bar = notation::parse_bar("C4:4. D4:8 E4:8 F4:8").unwrap();
+        assert!(bar.fits(signature(6, 8)));
+        let bar = notation::parse_bar("(3 C4:8 D4 E4) F4:4").unwrap();
+        assert!(bar.fits(signature(2, 4)));
+    }
+
+    #[test]
+    fn measures_underfull_bars() {
+        let bar = notation::parse_bar("C4:4 D4:4").unwrap();
+        assert_eq!(
+            bar.check_time_signature(0, signature(4, 4)),
+            BarFill::Underfull(Duration::new(1, 2))
+        );
+        assert_eq!(
+            bar.check_time_signature(0, signature(7, 8)),
+            BarFill::Underfull(Duration::new(3, 8))
+        );
+        assert!(!bar.fits(signature(4, 4)));
+        assert_eq!(
+            bar.overflowing_note(0, signature(4, 4).bar_duration()),
+            None
+        );
+    }
+
+    #[test]
+    fn measures_overfull_bars() {
+        let bar = notation::parse_bar("C4:2 D4:4 E4:4 F4:8").unwrap();
+        assert_eq!(
+            bar.check_time_signature(0, signature(4, 4)),
+            BarFill::Overfull(Duration::new(1, 8))
+        );
+        // the last note is the first one past the end of the bar
+        assert_eq!(
+            bar.overflowing_note(0, signature(4, 4).bar_duration()),
+            Some(3)
+        );
+        // and in 3/4 it's already the third one
+        assert_eq!(
+            bar.overflowing_note(0, signature(3, 4).bar_duration()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn checks_every_voice_on_its_own() {
+        let bar = notation::parse_bar("E5:2 F5:2 & C4:2").unwrap();
+        assert!(bar.check_time_signature(0, signature(4, 4)).is_full());
+        assert_eq!(
+            bar.check_time_signature(1, signature(4, 4)),
+            BarFill::Underfull(Duration::new(1, 2))
+        );
+        assert!(!bar.fits(signature(4, 4)));
+
+        // voices without notes are whole bar rests
+        let empty = Bar::default();
+        assert!(empty.fits(signature(5, 4)));
+        assert_eq!(
+            empty.check_time_signature(2, signature(3, 4)),
+            BarFill::Underfull(Duration::new(3, 4))
+        );
+    }
+
+    fn order(input: &str) -> Vec<usize> {
+        notation::parse_line(input).unwrap().performance_order()
+    }
+
+    #[test]
+    fn plays_voltas_before_dal_segno_al_coda() {
+        let line = "C4:1 | mark=segno repeat=start D4:1 | volta=1 repeat=end E4:1 \
+            | volta=2 F4:1 mark=to_coda | G4:1 jump=D.S._al_Coda | mark=coda A4:1 |";
+        // after the jump the first ending goes back, so only the second one is played
+        assert_eq!(order(line), [0, 1, 2, 1, 3, 4, 1, 3, 5]);
+    }
+
+    #[test]
+    fn plays_voltas_before_dal_segno_al_fine() {
+        let line = "C4:1 | mark=segno repeat=start D4:1 | volta=1 repeat=end E4:1 \
+            | volta=2 F4:1 mark=fine | G4:1 jump=D.S._al_Fine |";
+        assert_eq!(order(line), [0, 1, 2, 1, 3, 4, 1, 3]);
+    }
+
+    #[test]
+    fn plays_repeats_once_after_a_jump() {
+        let line = "mark=segno repeat=start C4:1 | repeat=end D4:1 | E4:1 mark=fine \
+            | F4:1 jump=D.S._al_Fine |";
+        assert_eq!(order(line), [0, 1, 0, 1, 2, 3, 0, 1, 2]);
+    }
+
+    #[test]
+    fn plays_voltas_on_their_passes() {
+        let line = "repeat=start C4:1 | volta=1,2 repeat=end:3 D4:1 | volta=3 E4:1 |";
+        assert_eq!(order(line), [0, 1, 0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn plays_jumps_without_their_markers() {
+        // without a coda the music stops at "To Coda"
+        let line = "mark=segno C4:1 mark=to_coda | D4:1 jump=D.S._al_Coda |";
+        assert_eq!(order(line), [0, 1, 0]);
+        // and without a segno it goes back to the start
+        assert_eq!(order("C4:1 | D4:1 jump=D.S. |"), [0, 1, 0, 1]);
+    }
 }
@@ -0,0 +1,106 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::bar::NotePosition;
+
+/// How loud to play, from the softest to the loudest, with the accented ones last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Dynamic {
+    /// Pianississimo
+    Ppp,
+    /// Pianissimo
+    Pp,
+    /// Piano
+    P,
+    /// Mezzo piano
+    Mp,
+    /// Mezzo forte
+    Mf,
+    /// Forte
+    F,
+    /// Fortissimo
+    Ff,
+    /// Fortississimo
+    Fff,
+    /// Sforzando, a sudden accent on a single note
+    Sfz,
+    /// Forte piano, loud on the note and soft right after it
+    Fp,
+}
+
+impl Dynamic {
+    /// How the marking is written under the staff
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Ppp => "ppp",
+            Self::Pp => "pp",
+            Self::P => "p",
+            Self::Mp => "mp",
+            Self::Mf => "mf",
+            Self::F => "f",
+            Self::Ff => "ff",
+            Self::Fff => "fff",
+            Self::Sfz => "sfz",
+            Self::Fp => "fp",
+        }
+    }
+}
+
+impl Display for Dynamic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+impl FromStr for Dynamic {
+    type Err = ParseDynamicError;
+
+    /// Parses a marking as it's written, like `mf` or `sfz`.
+    /// ```
+    /// use rustsheet::dynamics::Dynamic;
+    ///
+    /// fn main() {
+    ///     assert_eq!("mf".parse(), Ok(Dynamic::Mf));
+    ///     assert_eq!(Dynamic::Sfz.to_string(), "sfz");
+    ///     assert!("mff".parse::<Dynamic>().is_err());
+    /// }
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ppp" => Self::Ppp,
+            "pp" => Self::Pp,
+            "p" => Self::P,
+            "mp" => Self::Mp,
+            "mf" => Self::Mf,
+            "f" => Self::F,
+            "ff" => Self::Ff,
+            "fff" => Self::Fff,
+            "sfz" => Self::Sfz,
+            "fp" => Self::Fp,
+            _ => return Err(ParseDynamicError(s.to_string())),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown dynamic {0}")]
+pub struct ParseDynamicError(String);
+
+/// A dynamic marking written under a note, holding until the next one.
+///
+/// The position points at a note written in the bars, rests included, not inside tuplets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynamicMark {
+    pub position: NotePosition,
+    pub dynamic: Dynamic,
+}
+
+impl DynamicMark {
+    pub fn new(position: NotePosition, dynamic: Dynamic) -> Self {
+        Self { position, dynamic }
+    }
+}
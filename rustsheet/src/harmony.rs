@@ -0,0 +1,440 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    notes::{NoteError, Octave, ToneModifiers},
+    tempo::Duration,
+    transpose::{self, Transposition},
+};
+
+/// The triad a chord symbol is built on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quality {
+    #[default]
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    /// The second instead of the third
+    Suspended2,
+    /// The fourth instead of the third
+    Suspended4,
+    /// Only the root and the fifth
+    Power,
+}
+
+/// The highest note stacked on the triad, each one taking the ones under it with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    Sixth,
+    /// A sixth and a ninth, without a seventh
+    SixNine,
+    Seventh,
+    Ninth,
+    Eleventh,
+    Thirteenth,
+}
+
+impl Extension {
+    /// How the extension is written, without the "maj" of a major seventh
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Sixth => "6",
+            Self::SixNine => "6/9",
+            Self::Seventh => "7",
+            Self::Ninth => "9",
+            Self::Eleventh => "11",
+            Self::Thirteenth => "13",
+        }
+    }
+
+    /// Whether the extension has a seventh, which can be major
+    pub fn has_seventh(&self) -> bool {
+        !matches!(self, Self::Sixth | Self::SixNine)
+    }
+}
+
+/// A note of the chord raised or lowered by a semitone, or added without the ones under it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alteration {
+    Flat(u8),
+    Sharp(u8),
+    Add(u8),
+}
+
+impl Display for Alteration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Flat(degree) => write!(f, "b{}", degree),
+            Self::Sharp(degree) => write!(f, "#{}", degree),
+            Self::Add(degree) => write!(f, "add{}", degree),
+        }
+    }
+}
+
+/// The harmony written over a lead sheet, like `Cmaj7`, `F#m7b5/A` or `G7sus4`.
+///
+/// The root and the bass are letters with an accidental, like the
+/// [tonic of a key](crate::key::KeySignature::tonic).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordSymbol {
+    pub root: (Octave, Option<ToneModifiers>),
+    pub quality: Quality,
+    pub extension: Option<Extension>,
+    /// Whether the seventh of the extension is major, like in `Cmaj7` or `Cm(maj9)`
+    pub major_seventh: bool,
+    pub alterations: Vec<Alteration>,
+    /// A bass note other than the root, written after a slash
+    pub bass: Option<(Octave, Option<ToneModifiers>)>,
+}
+
+impl ChordSymbol {
+    /// A plain triad on `root`
+    pub fn new(root: (Octave, Option<ToneModifiers>), quality: Quality) -> Self {
+        Self {
+            root,
+            quality,
+            extension: None,
+            major_seventh: false,
+            alterations: Vec::new(),
+            bass: None,
+        }
+    }
+
+    /// The same chord with its root and bass transposed, spelled without double sharps or flats
+    /// like [tones](crate::notes::Tone::transpose), and failing the same way
+    /// ```
+    /// use rustsheet::{harmony::ChordSymbol, transpose::Transposition};
+    ///
+    /// fn main() {
+    ///     let chord: ChordSymbol = "F#m7b5/A".parse().unwrap();
+    ///     // a major second up
+    ///     let up = chord.transpose(Transposition::diatonic(1, 2)).unwrap();
+    ///     assert_eq!(up.to_string(), "G#m7b5/B");
+    ///     // a minor third up, spelled with flats
+    ///     let up = chord.transpose(Transposition::diatonic(2, 3)).unwrap();
+    ///     assert_eq!(up.to_string(), "Am7b5/C");
+    ///
+    ///     let chord: ChordSymbol = "Bb7".parse().unwrap();
+    ///     let down = chord.transpose(Transposition::Chromatic(-2)).unwrap();
+    ///     assert_eq!(down.to_string(), "Ab7");
+    /// }
+    /// ```
+    pub fn transpose(&self, transposition: Transposition) -> Result<Self, NoteError> {
+        let (steps, semitones) = transposition.interval();
+        let letter = |(letter, modifiers): (Octave, Option<ToneModifiers>)| {
+            transpose::transpose_letter(
+                (letter, transpose::alteration(modifiers), 0),
+                steps,
+                semitones,
+            )
+            .map(|(letter, alter, _)| (letter, transpose::modifiers(alter, None)))
+        };
+
+        Ok(Self {
+            root: letter(self.root)?,
+            bass: self.bass.map(letter).transpose()?,
+            ..self.clone()
+        })
+    }
+}
+
+fn write_letter(
+    f: &mut std::fmt::Formatter<'_>,
+    (letter, modifiers): (Octave, Option<ToneModifiers>),
+) -> std::fmt::Result {
+    let accidental = match modifiers {
+        Some(ToneModifiers::Sharp) => "#",
+        Some(ToneModifiers::Flat) => "b",
+        Some(ToneModifiers::Natural) | None => "",
+    };
+    write!(f, "{}{}", letter, accidental)
+}
+
+/// Writes the symbol the way it's parsed, with `#` and `b` for the accidentals
+impl Display for ChordSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_letter(f, self.root)?;
+
+        let major = self.major_seventh && self.extension.is_some_and(|e| e.has_seventh());
+        match self.quality {
+            Quality::Minor if major => write!(f, "m(maj")?,
+            Quality::Minor => write!(f, "m")?,
+            Quality::Diminished => write!(f, "dim")?,
+            Quality::Augmented => write!(f, "aug")?,
+            _ if major => write!(f, "maj")?,
+            _ => {}
+        }
+        match self.extension {
+            Some(extension) => write!(f, "{}", extension.symbol())?,
+            None if self.quality == Quality::Power => write!(f, "5")?,
+            None => {}
+        }
+        if self.quality == Quality::Minor && major {
+            write!(f, ")")?;
+        }
+        match self.quality {
+            Quality::Suspended2 => write!(f, "sus2")?,
+            Quality::Suspended4 => write!(f, "sus4")?,
+            _ => {}
+        }
+        for alteration in &self.alterations {
+            write!(f, "{}", alteration)?;
+        }
+
+        if let Some(bass) = self.bass {
+            write!(f, "/")?;
+            write_letter(f, bass)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChordSymbol {
+    type Err = ChordSymbolError;
+
+    /// Reads a chord symbol as it's written on lead sheets: the root, then the quality
+    /// (`m`, `min` or `-`, `dim` or `°`, `aug` or `+`, `ø` for a half-diminished seventh),
+    /// `maj`, `M` or `Δ` for a major seventh, the extension (`6`, `6/9`, `7`, `9`, `11`
+    /// or `13`, or `5` for a power chord), `sus2` or `sus4`, alterations like `b9`, `#11`
+    /// or `add9`, in parentheses or not, and the bass after a slash.
+    /// ```
+    /// use rustsheet::harmony::{Alteration, ChordSymbol, Extension, Quality};
+    ///
+    /// fn main() {
+    ///     let chord: ChordSymbol = "Cmaj7".parse().unwrap();
+    ///     assert_eq!(chord.extension, Some(Extension::Seventh));
+    ///     assert!(chord.major_seventh);
+    ///
+    ///     let chord: ChordSymbol = "F#m7b5/A".parse().unwrap();
+    ///     assert_eq!(chord.quality, Quality::Minor);
+    ///     assert_eq!(chord.alterations, [Alteration::Flat(5)]);
+    ///     assert!(chord.bass.is_some());
+    ///
+    ///     let chord: ChordSymbol = "G7sus4".parse().unwrap();
+    ///     assert_eq!(chord.quality, Quality::Suspended4);
+    ///
+    ///     // written back the usual way
+    ///     let chord: ChordSymbol = "Eb-7(b9,#11)".parse().unwrap();
+    ///     assert_eq!(chord.to_string(), "Ebm7b9#11");
+    ///     assert_eq!("CΔ".parse::<ChordSymbol>().unwrap().to_string(), "Cmaj7");
+    ///
+    ///     assert!("H7".parse::<ChordSymbol>().is_err());
+    ///     assert!("C7x".parse::<ChordSymbol>().is_err());
+    /// }
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        let root = letter(&mut rest)?.ok_or(ChordSymbolError::Empty)?;
+        let mut chord = ChordSymbol::new(root, Quality::Major);
+
+        // "maj" goes before "m", and the minor "-" only right after the root
+        let mut triangle = eat(&mut rest, &["Δ"]);
+        let mut parenthesis = false;
+        // "madd9" adds a ninth to a minor triad
+        if triangle || (!rest.starts_with("madd") && eat(&mut rest, MAJOR)) {
+            chord.major_seventh = true;
+        } else if eat(&mut rest, &["min", "mi", "m", "-"]) {
+            chord.quality = Quality::Minor;
+            // a minor chord with a major seventh, like Cm(maj7)
+            parenthesis = rest.starts_with('(')
+                && ["Δ"].iter().chain(MAJOR).any(|m| rest[1..].starts_with(m));
+            if parenthesis {
+                rest = &rest[1..];
+            }
+            triangle = eat(&mut rest, &["Δ"]);
+            chord.major_seventh = triangle || eat(&mut rest, MAJOR);
+        } else if eat(&mut rest, &["dim", "°", "o"]) {
+            chord.quality = Quality::Diminished;
+        } else if eat(&mut rest, &["aug", "+"]) {
+            chord.quality = Quality::Augmented;
+        } else if eat(&mut rest, &["ø"]) {
+            chord.quality = Quality::Minor;
+            chord.extension = Some(Extension::Seventh);
+            chord.alterations.push(Alteration::Flat(5));
+        }
+
+        let extension = [
+            ("6/9", Extension::SixNine),
+            ("69", Extension::SixNine),
+            ("6", Extension::Sixth),
+            ("7", Extension::Seventh),
+            ("9", Extension::Ninth),
+            ("11", Extension::Eleventh),
+            ("13", Extension::Thirteenth),
+        ]
+        .into_iter()
+        .find(|(symbol, _)| eat(&mut rest, &[symbol]));
+        if let Some((_, extension)) = extension {
+            chord.extension = Some(extension);
+        } else if chord.quality == Quality::Major && eat(&mut rest, &["5"]) {
+            chord.quality = Quality::Power;
+        } else if triangle {
+            // the triangle alone is a major seventh chord, "maj" alone is just a major triad
+            chord.extension = Some(Extension::Seventh);
+        }
+        chord.major_seventh &= chord.extension.is_some_and(|e| e.has_seventh());
+        if parenthesis && !eat(&mut rest, &[")"]) {
+            return Err(ChordSymbolError::Unclosed);
+        }
+
+        if eat(&mut rest, &["sus2"]) {
+            chord.quality = Quality::Suspended2;
+        } else if eat(&mut rest, &["sus4", "sus"]) {
+            chord.quality = Quality::Suspended4;
+        }
+
+        while !rest.is_empty() && !rest.starts_with('/') {
+            if eat(&mut rest, &["(", ")", ","]) {
+                continue;
+            }
+            let written = rest;
+            let alteration: fn(u8) -> Alteration = if eat(&mut rest, &["add"]) {
+                Alteration::Add
+            } else if eat(&mut rest, &["b", "♭", "-"]) {
+                Alteration::Flat
+            } else if eat(&mut rest, &["#", "♯", "+"]) {
+                Alteration::Sharp
+            } else {
+                return Err(ChordSymbolError::Unexpected(rest.to_string()));
+            };
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let degree = rest[..digits]
+                .parse()
+                .map_err(|_| ChordSymbolError::Unexpected(written.to_string()))?;
+            rest = &rest[digits..];
+            chord.alterations.push(alteration(degree));
+        }
+
+        if let Some(bass) = rest.strip_prefix('/') {
+            rest = bass;
+            chord.bass = Some(
+                letter(&mut rest)
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| ChordSymbolError::InvalidBass(s.to_string()))?,
+            );
+            if !rest.is_empty() {
+                return Err(ChordSymbolError::Unexpected(rest.to_string()));
+            }
+        }
+
+        Ok(chord)
+    }
+}
+
+/// The ways of writing a major seventh, besides the triangle
+const MAJOR: &[&str] = &["maj", "Maj", "ma", "M"];
+
+/// Takes the first of `prefixes` that `rest` starts with off it, if any
+fn eat(rest: &mut &str, prefixes: &[&str]) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| match rest.strip_prefix(prefix) {
+            Some(after) => {
+                *rest = after;
+                true
+            }
+            None => false,
+        })
+}
+
+/// Reads a letter and an optional accidental from the start of `rest`
+fn letter(rest: &mut &str) -> Result<Option<(Octave, Option<ToneModifiers>)>, ChordSymbolError> {
+    let mut chars = rest.chars();
+    let Some(c) = chars.next() else {
+        return Ok(None);
+    };
+    let letter = Octave::try_from(c).map_err(|_| ChordSymbolError::InvalidRoot(c))?;
+    let modifiers = match chars.clone().next() {
+        Some('#' | '♯') => Some(ToneModifiers::Sharp),
+        Some('b' | '♭') => Some(ToneModifiers::Flat),
+        _ => None,
+    };
+    *rest = if modifiers.is_some() {
+        let mut after = chars;
+        after.next();
+        after.as_str()
+    } else {
+        chars.as_str()
+    };
+
+    Ok(Some((letter, modifiers)))
+}
+
+/// A chord symbol over a bar, `at` a time from its start
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChordSymbolMark {
+    pub at: Duration,
+    pub symbol: ChordSymbol,
+}
+
+impl ChordSymbolMark {
+    pub fn new(at: Duration, symbol: ChordSymbol) -> Self {
+        Self { at, symbol }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ChordSymbolError {
+    #[error("Empty chord symbol")]
+    Empty,
+    #[error("Invalid root {0:?}, expected A to G")]
+    InvalidRoot(char),
+    #[error("Invalid bass in {0:?}")]
+    InvalidBass(String),
+    #[error("Missing ')' after the major seventh")]
+    Unclosed,
+    #[error("Unexpected {0:?} in the chord symbol")]
+    Unexpected(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(symbol: &str) -> ChordSymbolError {
+        symbol.parse::<ChordSymbol>().unwrap_err()
+    }
+
+    #[test]
+    fn refuses_unclosed_parentheses() {
+        assert_eq!(error("Cm(maj7"), ChordSymbolError::Unclosed);
+        assert_eq!(error("Cm(Δ7"), ChordSymbolError::Unclosed);
+        assert_eq!(error("Cm(maj7/G"), ChordSymbolError::Unclosed);
+        assert!("Cm(maj7)".parse::<ChordSymbol>().is_ok());
+    }
+
+    #[test]
+    fn refuses_alterations_without_a_degree() {
+        assert_eq!(error("C7b"), ChordSymbolError::Unexpected("b".to_string()));
+        assert_eq!(error("C7#"), ChordSymbolError::Unexpected("#".to_string()));
+        assert_eq!(
+            error("Cadd"),
+            ChordSymbolError::Unexpected("add".to_string())
+        );
+        assert_eq!(
+            error("C7(b,#11)"),
+            ChordSymbolError::Unexpected("b,#11)".to_string())
+        );
+        assert_eq!(
+            error("C7b9x"),
+            ChordSymbolError::Unexpected("x".to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_roots_and_basses() {
+        assert_eq!(error(""), ChordSymbolError::Empty);
+        assert_eq!(error("H7"), ChordSymbolError::InvalidRoot('H'));
+        assert_eq!(error("c"), ChordSymbolError::InvalidRoot('c'));
+        assert_eq!(error("C/"), ChordSymbolError::InvalidBass("C/".to_string()));
+        assert_eq!(
+            error("C/H"),
+            ChordSymbolError::InvalidBass("C/H".to_string())
+        );
+        assert_eq!(error("C/Ex"), ChordSymbolError::Unexpected("x".to_string()));
+    }
+}
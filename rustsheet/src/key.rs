@@ -0,0 +1,304 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    notes::{Octave, Tone, ToneModifiers},
+    transpose::Transposition,
+};
+
+/// Order in which sharps are added to a key signature, flats go the other way
+const SHARPS: [Octave; 7] = [
+    Octave::F,
+    Octave::C,
+    Octave::G,
+    Octave::D,
+    Octave::A,
+    Octave::E,
+    Octave::B,
+];
+
+/// A key signature, stored as its position on the circle of fifths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeySignature {
+    fifths: i8,
+    mode: Mode,
+}
+
+impl KeySignature {
+    /// Creates a key signature with `fifths` sharps, or flats if it's negative.
+    /// ```
+    /// use rustsheet::key::{KeySignature, Mode};
+    ///
+    /// fn main() {
+    ///     // two flats, B♭ major or G minor
+    ///     let key = KeySignature::new(-2, Mode::Minor).unwrap();
+    ///     assert_eq!(key.to_string(), "G minor");
+    ///     assert_eq!(key.flats(), 2);
+    ///
+    ///     assert!(KeySignature::new(8, Mode::Major).is_err());
+    /// }
+    /// ```
+    pub fn new(fifths: i8, mode: Mode) -> Result<Self, KeySignatureError> {
+        if !(-7..=7).contains(&fifths) {
+            return Err(KeySignatureError::TooManyAccidentals(fifths));
+        }
+
+        Ok(Self { fifths, mode })
+    }
+
+    /// Creates the key signature of a tonic and a mode, like D dorian.
+    ///
+    /// Fails for keys that would need more than 7 sharps or flats, like G♯ major.
+    pub fn from_tonic(
+        tonic: Octave,
+        modifiers: Option<ToneModifiers>,
+        mode: Mode,
+    ) -> Result<Self, KeySignatureError> {
+        let letter = SHARPS.iter().position(|o| *o == tonic).unwrap() as i8 - 1;
+        let alteration = match modifiers {
+            Some(ToneModifiers::Sharp) => 7,
+            Some(ToneModifiers::Flat) => -7,
+            _ => 0,
+        };
+
+        Self::new(letter + alteration - mode.offset(), mode)
+    }
+
+    /// Sharps in the signature, or flats if negative
+    pub fn fifths(&self) -> i8 {
+        self.fifths
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn sharps(&self) -> u8 {
+        self.fifths.max(0) as u8
+    }
+
+    pub fn flats(&self) -> u8 {
+        (-self.fifths).max(0) as u8
+    }
+
+    /// The first note of the scale, as a letter and an accidental
+    pub fn tonic(&self) -> (Octave, Option<ToneModifiers>) {
+        // the tonic of the major key with no accidentals, F, is one fifth below C
+        let fifths = self.fifths + self.mode.offset() + 1;
+        let modifiers = match fifths.div_euclid(7) {
+            0 => None,
+            1 => Some(ToneModifiers::Sharp),
+            _ => Some(ToneModifiers::Flat),
+        };
+
+        (SHARPS[fifths.rem_euclid(7) as usize], modifiers)
+    }
+
+    /// The key the notes written in this one go to, see [`Transposition::in_key`]
+    pub fn transpose(&self, transposition: Transposition) -> KeySignature {
+        transposition.in_key(*self).0
+    }
+
+    /// The letters the signature alters, in the order they're written
+    pub fn accidentals(&self) -> impl Iterator<Item = (Octave, ToneModifiers)> {
+        let sharps = SHARPS.into_iter().take(self.sharps() as usize);
+        let flats = SHARPS.into_iter().rev().take(self.flats() as usize);
+
+        sharps
+            .map(|o| (o, ToneModifiers::Sharp))
+            .chain(flats.map(|o| (o, ToneModifiers::Flat)))
+    }
+
+    /// How the signature alters a letter, if it does
+    pub fn modifier_of(&self, letter: Octave) -> Option<ToneModifiers> {
+        self.accidentals()
+            .find(|(o, _)| *o == letter)
+            .map(|(_, modifiers)| modifiers)
+    }
+}
+
+impl fmt::Display for KeySignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tonic, modifiers) = self.tonic();
+        match modifiers {
+            None => write!(f, "{} {}", tonic, self.mode),
+            Some(modifiers) => write!(f, "{}{} {}", tonic, modifiers, self.mode),
+        }
+    }
+}
+
+/// Reads a key like `Bb major`, `F♯ minor` or `D dorian`, the mode is major if it's left out
+impl FromStr for KeySignature {
+    type Err = KeySignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tonic, mode) = s.trim().split_once(' ').unwrap_or((s.trim(), "major"));
+        let invalid = || KeySignatureError::InvalidTonic(tonic.to_string());
+
+        let mut chars = tonic.chars();
+        let letter = chars.next().ok_or_else(invalid)?;
+        let letter = Octave::try_from(letter).map_err(|_| invalid())?;
+        let modifiers = match chars.next() {
+            None => None,
+            Some(c) => match ToneModifiers::try_from(c) {
+                Ok(ToneModifiers::Natural) | Err(_) => return Err(invalid()),
+                Ok(modifiers) => Some(modifiers),
+            },
+        };
+        if chars.next().is_some() {
+            return Err(invalid());
+        }
+
+        KeySignature::from_tonic(letter, modifiers, mode.trim().parse()?)
+    }
+}
+
+/// The mode of a key, all of them but major and minor share their signature with
+/// the major key a few fifths away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 7] = [
+        Mode::Major,
+        Mode::Minor,
+        Mode::Dorian,
+        Mode::Phrygian,
+        Mode::Lydian,
+        Mode::Mixolydian,
+        Mode::Locrian,
+    ];
+
+    /// Fifths between the tonic of the mode and the tonic of the major key with the same signature
+    fn offset(&self) -> i8 {
+        match self {
+            Mode::Lydian => -1,
+            Mode::Major => 0,
+            Mode::Mixolydian => 1,
+            Mode::Dorian => 2,
+            Mode::Minor => 3,
+            Mode::Phrygian => 4,
+            Mode::Locrian => 5,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Major => write!(f, "major"),
+            Mode::Minor => write!(f, "minor"),
+            Mode::Dorian => write!(f, "dorian"),
+            Mode::Phrygian => write!(f, "phrygian"),
+            Mode::Lydian => write!(f, "lydian"),
+            Mode::Mixolydian => write!(f, "mixolydian"),
+            Mode::Locrian => write!(f, "locrian"),
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = KeySignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "major" | "ionian" => Ok(Mode::Major),
+            "minor" | "aeolian" => Ok(Mode::Minor),
+            _ => Mode::ALL
+                .into_iter()
+                .find(|m| m.to_string() == s)
+                .ok_or_else(|| KeySignatureError::UnknownMode(s.to_string())),
+        }
+    }
+}
+
+/// Keeps track of the accidentals in effect while going through a bar,
+/// to know which ones have to be written.
+///
+/// An accidental lasts until the end of the bar, and only for notes on the same
+/// line or space. Naturals set on the tone are always written, as a reminder.
+/// ```
+/// use rustsheet::{
+///     key::{AccidentalState, KeySignature, Mode},
+///     notes::{Octave, Tone, ToneModifiers},
+/// };
+///
+/// fn main() {
+///     let mut state = AccidentalState::new(KeySignature::new(1, Mode::Major).unwrap());
+///     let f_sharp = Tone::with_modifiers(Octave::F, 4, ToneModifiers::Sharp).unwrap();
+///
+///     // the key already has the sharp
+///     assert_eq!(state.accidental(&f_sharp), None);
+///     // an F without a sharp needs a natural, and it lasts for the bar
+///     assert_eq!(state.accidental(&Tone::new(Octave::F, 4)), Some(ToneModifiers::Natural));
+///     assert_eq!(state.accidental(&Tone::new(Octave::F, 4)), None);
+///     assert_eq!(state.accidental(&f_sharp), Some(ToneModifiers::Sharp));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AccidentalState {
+    key: KeySignature,
+    /// Letter and octave of the notes altered in the bar so far
+    altered: Vec<(Octave, u8, ToneModifiers)>,
+}
+
+impl AccidentalState {
+    pub fn new(key: KeySignature) -> Self {
+        Self {
+            key,
+            altered: Vec::new(),
+        }
+    }
+
+    /// Forgets the accidentals of the bar, for when the next one starts
+    pub fn next_bar(&mut self) {
+        self.altered.clear();
+    }
+
+    /// Changes the key, for when a bar has a new signature
+    pub fn set_key(&mut self, key: KeySignature) {
+        self.key = key;
+        self.next_bar();
+    }
+
+    /// The accidental to write before the tone, if any, then remembers it for the rest of the bar
+    pub fn accidental(&mut self, tone: &Tone) -> Option<ToneModifiers> {
+        let current = self
+            .altered
+            .iter()
+            .rev()
+            .find(|(o, p, _)| *o == tone.octave && *p == tone.position)
+            .map(|(_, _, modifiers)| *modifiers)
+            .or_else(|| self.key.modifier_of(tone.octave))
+            .filter(|m| *m != ToneModifiers::Natural);
+        let wanted = tone.modifiers.filter(|m| *m != ToneModifiers::Natural);
+
+        if current == wanted && tone.modifiers != Some(ToneModifiers::Natural) {
+            return None;
+        }
+
+        let written = wanted.unwrap_or(ToneModifiers::Natural);
+        self.altered.push((tone.octave, tone.position, written));
+        Some(written)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum KeySignatureError {
+    #[error("Key signatures go up to 7 sharps or flats, found {0} fifths")]
+    TooManyAccidentals(i8),
+    #[error("Invalid tonic {0:?}")]
+    InvalidTonic(String),
+    #[error("Unknown mode {0:?}")]
+    UnknownMode(String),
+}
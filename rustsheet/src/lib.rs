@@ -1,11 +1,26 @@
-use bar::Line;
+use bar::{BarFill, Line, LineType, NotePosition, ValidationError, MAX_VOICES};
+use dynamics::DynamicMark;
+use lyrics::Lyric;
+use notes::{Note, NoteError, Tone};
+use span::{Span, SpanKind};
+use tempo::{Duration, TimeSignature};
+use transpose::Transposition;
 
 pub mod bar;
+pub mod dynamics;
+pub mod harmony;
+pub mod key;
+pub mod lyrics;
 mod math;
+pub mod notation;
 pub mod notes;
+pub mod repeat;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod span;
 pub mod tempo;
+pub mod transpose;
 
-const MAX_NOTES_IN_TUPLET: usize = 5;
 const STARTING_BAR_CAPACITY: usize = 4;
 
 #[derive(Debug, Clone)]
@@ -14,4 +29,610 @@ pub struct MusicSheet {
     pub subtitle: Option<String>,
     pub author: String,
     pub lines: Vec<Line>,
+    /// Ties, slurs, hairpins and tempo changes between the notes of the lines
+    pub spans: Vec<Span>,
+    /// Dynamic markings under the notes of the lines
+    pub dynamics: Vec<DynamicMark>,
+    /// Syllables sung on the notes of the lines, verse by verse
+    pub lyrics: Vec<Lyric>,
+}
+
+impl MusicSheet {
+    /// A sheet of the lines, without a subtitle and with no spans, dynamics or lyrics yet
+    pub fn new(title: String, author: String, lines: Vec<Line>) -> Self {
+        Self {
+            title,
+            subtitle: None,
+            author,
+            lines,
+            spans: Vec::new(),
+            dynamics: Vec::new(),
+            lyrics: Vec::new(),
+        }
+    }
+
+    /// Checks every voice of every bar against its time signature, 4/4 until one is set,
+    /// every tuplet against its ratio, every span against the notes it joins,
+    /// every dynamic against the note it's under, every syllable against the note it's sung on
+    /// and the repeats and endings of every bar.
+    ///
+    /// Voices without notes are whole bar rests, so they always fit.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType, ValidationError},
+    ///     notation, MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("time=3/4 | C4:2. | D4:2 | E4:2 F4:2 |").unwrap();
+    ///     let lines = vec![Line::new(LineType::Single(line))];
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+    ///
+    ///     let errors = sheet.validate();
+    ///     assert_eq!(errors.len(), 2);
+    ///     assert!(matches!(errors[0], ValidationError::Underfull { .. }));
+    ///     assert_eq!(errors[1].to_string(), "line 1, staff 1, bar 3, note 2: the bar is long by 1/4");
+    /// }
+    /// ```
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        for (l, line) in self.lines.iter().enumerate() {
+            for (s, staff) in line.line.staves().into_iter().enumerate() {
+                let mut ts = TimeSignature::default();
+
+                for (b, bar) in staff.bars.iter().enumerate() {
+                    if let Some(signature) = bar.header.time_signature {
+                        ts = signature;
+                    }
+
+                    let position = |voice, index| NotePosition {
+                        line: l,
+                        staff: s,
+                        bar: b,
+                        voice,
+                        index,
+                    };
+                    if let Err(error) = bar.header.check_repeats() {
+                        errors.push(ValidationError::InvalidRepeat {
+                            position: position(0, 0),
+                            error,
+                        });
+                    }
+                    if let Some(mark) = bar
+                        .chord_symbols
+                        .iter()
+                        .find(|mark| mark.at >= ts.bar_duration())
+                    {
+                        errors.push(ValidationError::ChordSymbolOutsideBar {
+                            position: position(0, 0),
+                            at: mark.at,
+                        });
+                    }
+                    if bar.voices.len() > MAX_VOICES {
+                        errors.push(ValidationError::TooManyVoices {
+                            position: position(MAX_VOICES, 0),
+                            voices: bar.voices.len(),
+                        });
+                    }
+
+                    // every voice has to fill the bar on its own
+                    for (v, notes) in bar.used_voices() {
+                        for (i, note) in notes.iter().enumerate() {
+                            if let Note::Tuplet(tuplet) = note {
+                                if let Err(error) = tuplet.check() {
+                                    errors.push(ValidationError::InvalidTuplet {
+                                        position: position(v, i),
+                                        error,
+                                    });
+                                }
+                            }
+                        }
+                        match bar.check_time_signature(v, ts) {
+                            BarFill::Full => {}
+                            BarFill::Underfull(missing) => {
+                                errors.push(ValidationError::Underfull {
+                                    position: position(v, notes.len()),
+                                    missing,
+                                })
+                            }
+                            BarFill::Overfull(extra) => errors.push(ValidationError::Overfull {
+                                position: position(
+                                    v,
+                                    bar.overflowing_note(v, ts.bar_duration())
+                                        .unwrap_or_default(),
+                                ),
+                                extra,
+                            }),
+                        }
+                    }
+                }
+            }
+        }
+
+        for span in &self.spans {
+            if let Err(error) = span.check(self) {
+                errors.push(ValidationError::InvalidSpan {
+                    position: span.start,
+                    error,
+                });
+            }
+        }
+
+        for mark in &self.dynamics {
+            if self.note(mark.position).is_none() {
+                errors.push(ValidationError::MissingDynamicNote {
+                    position: mark.position,
+                });
+            }
+        }
+
+        for lyric in &self.lyrics {
+            if !matches!(
+                self.note(lyric.position),
+                Some(Note::Note(_) | Note::Chord(_) | Note::WithGrace { .. })
+            ) {
+                errors.push(ValidationError::MissingLyricNote {
+                    position: lyric.position,
+                });
+            }
+        }
+
+        errors
+    }
+
+    /// The sheet as it's played, every staff following the
+    /// [performance order](bar::SingleLine::performance_order) of the first one.
+    ///
+    /// Dynamics are repeated with their bars, and so are spans when the bars between their
+    /// notes are played one after the other, a tie going into a repeat is only kept
+    /// on the pass it's followed on. Bars played more than once sing their verses in turn,
+    /// staying on the last one they have, and bars played once keep them all.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType, NotePosition},
+    ///     notation,
+    ///     span::Span,
+    ///     MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("repeat=start C4:1 | repeat=end D4:1 | E4:1").unwrap();
+    ///     let at = |bar| NotePosition { line: 0, staff: 0, bar, voice: 0, index: 0 };
+    ///     let lines = vec![Line::new(LineType::Single(line))];
+    ///     let sheet = MusicSheet {
+    ///         spans: vec![Span::slur(at(0), at(1)), Span::slur(at(1), at(2))],
+    ///         ..MusicSheet::new("Example".to_string(), "Someone".to_string(), lines)
+    ///     };
+    ///
+    ///     let played = sheet.unroll();
+    ///     assert_eq!(played.lines[0].line.staves()[0].bars.len(), 5);
+    ///     // the first slur is played twice, the second one only after the repeat
+    ///     let starts: Vec<usize> = played.spans.iter().map(|s| s.start.bar).collect();
+    ///     assert_eq!(starts, [0, 2, 3]);
+    /// }
+    /// ```
+    pub fn unroll(&self) -> MusicSheet {
+        let order = self
+            .lines
+            .first()
+            .map(|line| line.line.staves()[0].performance_order())
+            .unwrap_or_default();
+
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| Line {
+                name: line.name.clone(),
+                line: match &line.line {
+                    LineType::Single(staff) => LineType::Single(staff.unroll_order(&order)),
+                    LineType::Double(upper, lower) => {
+                        LineType::Double(upper.unroll_order(&order), lower.unroll_order(&order))
+                    }
+                },
+                transposition: line.transposition,
+                concert_pitch: line.concert_pitch,
+            })
+            .collect();
+
+        // where every written bar is played
+        let played = |bar| {
+            order
+                .iter()
+                .enumerate()
+                .filter(move |(_, b)| **b == bar)
+                .map(|(k, _)| k)
+        };
+
+        let mut spans = Vec::new();
+        for span in &self.spans {
+            let Some(length) = span.end.bar.checked_sub(span.start.bar) else {
+                continue;
+            };
+            for k in played(span.start.bar) {
+                let followed = order
+                    .get(k..=k + length)
+                    .is_some_and(|bars| bars.iter().copied().eq(span.start.bar..=span.end.bar));
+                if followed {
+                    let mut span = *span;
+                    span.start.bar = k;
+                    span.end.bar = k + length;
+                    spans.push(span);
+                }
+            }
+        }
+
+        let dynamics = self
+            .dynamics
+            .iter()
+            .flat_map(|mark| {
+                played(mark.position.bar).map(move |k| {
+                    let mut mark = *mark;
+                    mark.position.bar = k;
+                    mark
+                })
+            })
+            .collect();
+
+        let mut lyrics = Vec::new();
+        for lyric in &self.lyrics {
+            let p = lyric.position;
+            let mut verses: Vec<u8> = self
+                .lyrics
+                .iter()
+                .filter(|other| {
+                    let o = other.position;
+                    (o.line, o.staff, o.bar, o.voice) == (p.line, p.staff, p.bar, p.voice)
+                })
+                .map(|other| other.verse)
+                .collect();
+            verses.sort();
+            verses.dedup();
+
+            let passes: Vec<usize> = played(p.bar).collect();
+            for (pass, &k) in passes.iter().enumerate() {
+                let sung = verses
+                    .iter()
+                    .rev()
+                    .find(|verse| usize::from(**verse) <= pass)
+                    .or(verses.first());
+                if passes.len() == 1 || sung == Some(&lyric.verse) {
+                    let mut lyric = lyric.clone();
+                    lyric.position.bar = k;
+                    lyrics.push(lyric);
+                }
+            }
+        }
+
+        MusicSheet {
+            title: self.title.clone(),
+            subtitle: self.subtitle.clone(),
+            author: self.author.clone(),
+            lines,
+            spans,
+            dynamics,
+            lyrics,
+        }
+    }
+
+    /// The sheet with every line transposed, see [`transpose`](crate::transpose).
+    /// Fails if a note goes out of range or can't be spelled with one accidental.
+    pub fn transpose(&self, transposition: Transposition) -> Result<MusicSheet, NoteError> {
+        self.map_lines(|line| line.transpose(transposition))
+    }
+
+    /// The sheet as it sounds, with transposing instruments written at concert pitch
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType},
+    ///     notation,
+    ///     transpose::Transposition,
+    ///     MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("key=D E4:2 F#4:2 |").unwrap();
+    ///     let mut clarinet = Line::new_named("Clarinet in Bb".to_string(), LineType::Single(line));
+    ///     // it sounds a major second lower than written
+    ///     clarinet.transposition = Some(Transposition::diatonic(-1, -2));
+    ///
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), vec![clarinet]);
+    ///
+    ///     let concert = sheet.to_concert_pitch().unwrap();
+    ///     let bar = &concert.lines[0].line.staves()[0].bars[0];
+    ///     assert_eq!(bar.header.key_signature.unwrap().to_string(), "C major");
+    ///     assert_eq!(bar.voices[0][1].tones()[0].to_string(), "E4");
+    ///     assert!(concert.lines[0].concert_pitch);
+    ///
+    ///     // and back to what the player reads
+    ///     let written = concert.to_written_pitch().unwrap();
+    ///     let bar = &written.lines[0].line.staves()[0].bars[0];
+    ///     assert_eq!(bar.voices[0][1].tones()[0].to_string(), "F4♯");
+    /// }
+    /// ```
+    pub fn to_concert_pitch(&self) -> Result<MusicSheet, NoteError> {
+        self.map_lines(Line::to_concert_pitch)
+    }
+
+    /// The sheet as the players read it, with transposing instruments written at their pitch
+    pub fn to_written_pitch(&self) -> Result<MusicSheet, NoteError> {
+        self.map_lines(Line::to_written_pitch)
+    }
+
+    fn map_lines(
+        &self,
+        f: impl Fn(&Line) -> Result<Line, NoteError>,
+    ) -> Result<MusicSheet, NoteError> {
+        Ok(MusicSheet {
+            lines: self.lines.iter().map(f).collect::<Result<_, _>>()?,
+            ..self.clone()
+        })
+    }
+
+    /// Every note of the sheet with its position, staff by staff and voice by voice.
+    ///
+    /// Only the notes written in the bars are given, not the ones inside tuplets.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType},
+    ///     notation, MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("time=2/4 | E5:4 F5:4 & C4:2 | G5:2 |").unwrap();
+    ///     let lines = vec![Line::new(LineType::Single(line))];
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+    ///
+    ///     assert_eq!(sheet.notes().count(), 4);
+    ///     let (position, _) = sheet.notes().nth(2).unwrap();
+    ///     assert_eq!(position.to_string(), "line 1, staff 1, bar 1, voice 2, note 1");
+    ///
+    ///     // the lower voice is only in the first bar
+    ///     assert_eq!(sheet.voice_notes(0, 0, 1).count(), 1);
+    ///     assert!(sheet.note(position).is_some());
+    /// }
+    /// ```
+    pub fn notes(&self) -> impl Iterator<Item = (NotePosition, &Note)> {
+        self.lines.iter().enumerate().flat_map(|(l, line)| {
+            line.line
+                .staves()
+                .into_iter()
+                .enumerate()
+                .flat_map(move |(s, staff)| {
+                    staff.bars.iter().enumerate().flat_map(move |(b, bar)| {
+                        bar.voices.iter().enumerate().flat_map(move |(v, notes)| {
+                            notes.iter().enumerate().map(move |(i, note)| {
+                                let position = NotePosition {
+                                    line: l,
+                                    staff: s,
+                                    bar: b,
+                                    voice: v,
+                                    index: i,
+                                };
+                                (position, note)
+                            })
+                        })
+                    })
+                })
+        })
+    }
+
+    /// The notes of one voice of a staff, from its first bar to its last
+    pub fn voice_notes(
+        &self,
+        line: usize,
+        staff: usize,
+        voice: usize,
+    ) -> impl Iterator<Item = (NotePosition, &Note)> {
+        self.notes()
+            .filter(move |(p, _)| p.line == line && p.staff == staff && p.voice == voice)
+    }
+
+    /// When the note at a position starts, from the start of its line
+    pub fn onset(&self, position: NotePosition) -> Option<Duration> {
+        let line = self.lines.get(position.line)?;
+        let staff = *line.line.staves().get(position.staff)?;
+        let notes = staff.bars.get(position.bar)?.voice(position.voice);
+
+        let start = staff.bar_starts()[position.bar];
+        Some(
+            notes
+                .get(..position.index)?
+                .iter()
+                .fold(start, |pos, note| pos + note.duration()),
+        )
+    }
+
+    /// The note at a position, if there's one
+    pub fn note(&self, position: NotePosition) -> Option<&Note> {
+        let line = self.lines.get(position.line)?;
+        let staff = *line.line.staves().get(position.staff)?;
+
+        staff
+            .bars
+            .get(position.bar)?
+            .voice(position.voice)
+            .get(position.index)
+    }
+
+    /// Where the note after the one at `position` is in its voice, in the same bar or the next
+    pub fn next_position(&self, position: NotePosition) -> Option<NotePosition> {
+        self.note(position)?;
+        let next = NotePosition {
+            index: position.index + 1,
+            ..position
+        };
+        if self.note(next).is_some() {
+            return Some(next);
+        }
+
+        let next = NotePosition {
+            bar: position.bar + 1,
+            index: 0,
+            ..position
+        };
+        self.note(next).map(|_| next)
+    }
+
+    /// The tones of the note at `position` that go on into the next note, through a tie
+    pub fn tied_into_next(&self, position: NotePosition) -> Vec<Tone> {
+        self.tied_tones(|span| span.start == position)
+    }
+
+    /// The tones of the note at `position` that were already held from the previous note
+    pub fn tied_from_previous(&self, position: NotePosition) -> Vec<Tone> {
+        self.tied_tones(|span| span.end == position)
+    }
+
+    /// Tones both ends of the first tie matching `filter` have in common
+    fn tied_tones(&self, filter: impl Fn(&Span) -> bool) -> Vec<Tone> {
+        let tie = self
+            .spans
+            .iter()
+            .find(|span| span.kind == SpanKind::Tie && filter(span));
+        let (Some(start), Some(end)) = (
+            tie.and_then(|t| self.note(t.start)),
+            tie.and_then(|t| self.note(t.end)),
+        ) else {
+            return Vec::new();
+        };
+
+        start
+            .tones()
+            .iter()
+            .filter(|tone| end.tones().contains(tone))
+            .copied()
+            .collect()
+    }
+
+    /// How long the note at `position` sounds, with the notes tied after it.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType, NotePosition},
+    ///     notation,
+    ///     span::Span,
+    ///     tempo::Duration,
+    ///     MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("time=3/4 | C4:4 G4:2 | G4:4 E4:2 |").unwrap();
+    ///     let at = |bar, index| NotePosition { line: 0, staff: 0, bar, voice: 0, index };
+    ///     let lines = vec![Line::new(LineType::Single(line))];
+    ///     let sheet = MusicSheet {
+    ///         spans: vec![Span::tie(at(0, 1), at(1, 0))],
+    ///         ..MusicSheet::new("Example".to_string(), "Someone".to_string(), lines)
+    ///     };
+    ///
+    ///     // a minim tied over the barline to a crotchet
+    ///     assert_eq!(sheet.tied_duration(at(0, 1)), Duration::new(3, 4));
+    ///     assert_eq!(sheet.tied_duration(at(0, 0)), Duration::new(1, 4));
+    /// }
+    /// ```
+    pub fn tied_duration(&self, position: NotePosition) -> Duration {
+        let mut duration = Duration::ZERO;
+        let mut current = Some(position);
+
+        while let Some(position) = current {
+            let Some(note) = self.note(position) else {
+                break;
+            };
+            duration = duration + note.duration();
+            current = self
+                .spans
+                .iter()
+                .find(|span| span.kind == SpanKind::Tie && span.start == position)
+                .map(|span| span.end)
+                // a tie that goes back would never end
+                .filter(|end| position.partial_cmp(end) == Some(std::cmp::Ordering::Less));
+        }
+
+        duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bar::Bar;
+
+    fn sheet(lines: Vec<LineType>) -> MusicSheet {
+        MusicSheet::new(
+            "Test".to_string(),
+            "Someone".to_string(),
+            lines.into_iter().map(Line::new).collect(),
+        )
+    }
+
+    fn single(input: &str) -> LineType {
+        LineType::Single(notation::parse_line(input).unwrap())
+    }
+
+    fn position(line: usize, staff: usize, bar: usize, voice: usize, index: usize) -> NotePosition {
+        NotePosition {
+            line,
+            staff,
+            bar,
+            voice,
+            index,
+        }
+    }
+
+    #[test]
+    fn validates_against_common_time_by_default() {
+        assert!(sheet(vec![single("C4:1 | D4:2 E4:2 |")])
+            .validate()
+            .is_empty());
+
+        let errors = sheet(vec![single("C4:2. |")]).validate();
+        assert_eq!(
+            errors,
+            [ValidationError::Underfull {
+                position: position(0, 0, 0, 0, 1),
+                missing: Duration::new(1, 4),
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_time_signatures_until_the_next_one() {
+        let line = single("time=3/4 C4:2. | D4:2. | time=2/4 E4:2 | F4:4 G4:4 A4:4 |");
+        let errors = sheet(vec![line]).validate();
+        assert_eq!(
+            errors,
+            [ValidationError::Overfull {
+                position: position(0, 0, 3, 0, 2),
+                extra: Duration::new(1, 4),
+            }]
+        );
+    }
+
+    #[test]
+    fn points_at_the_staff_and_voice_of_errors() {
+        let upper = notation::parse_line("C5:1 | D5:2 D5:2 & B4:2 |").unwrap();
+        let lower = notation::parse_line("C3:2 C3:2 C3:8 | D3:1 |").unwrap();
+        let errors = sheet(vec![single("C4:1 |"), LineType::Double(upper, lower)]).validate();
+
+        assert_eq!(
+            errors,
+            [
+                ValidationError::Underfull {
+                    position: position(1, 0, 1, 1, 1),
+                    missing: Duration::new(1, 2),
+                },
+                ValidationError::Overfull {
+                    position: position(1, 1, 0, 0, 2),
+                    extra: Duration::new(1, 8),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_empty_bars_and_voices() {
+        let mut line = notation::parse_line("time=5/4 C4:1 D4:4 | E4:1 F4:4 |").unwrap();
+        line.bars.push(Bar::default());
+        line.bars[1].voices.push(Vec::new());
+        assert!(sheet(vec![LineType::Single(line)]).validate().is_empty());
+    }
 }
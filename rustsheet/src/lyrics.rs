@@ -0,0 +1,166 @@
+use thiserror::Error;
+
+use crate::{bar::NotePosition, notes::Note, span::SpanKind, MusicSheet};
+
+/// Where a syllable is in its word, the ones that don't end it are followed by a hyphen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Syllabic {
+    /// A whole word
+    #[default]
+    Single,
+    Begin,
+    Middle,
+    End,
+}
+
+impl Syllabic {
+    /// Whether a hyphen goes on to the next syllable of the word
+    pub fn continues(&self) -> bool {
+        matches!(self, Self::Begin | Self::Middle)
+    }
+
+    /// Where the syllable at `index` of a word split in `count` is
+    fn of(index: usize, count: usize) -> Self {
+        match (index, count) {
+            (_, 1) => Self::Single,
+            (0, _) => Self::Begin,
+            (i, n) if i == n - 1 => Self::End,
+            _ => Self::Middle,
+        }
+    }
+}
+
+/// A syllable sung on a note, under the staff in the row of its verse.
+///
+/// The position points at a note written in the bars, not a rest and not inside tuplets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lyric {
+    pub position: NotePosition,
+    /// Counted from 0, the first verse is sung on the first pass of a repeat
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verse: u8,
+    pub text: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub syllabic: Syllabic,
+    /// Whether the syllable is held over the next notes, with an extender line up to the
+    /// last one before the next syllable
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub extend: bool,
+}
+
+impl Lyric {
+    /// A whole word on the note at `position`
+    pub fn new(position: NotePosition, verse: u8, text: &str) -> Self {
+        Self {
+            position,
+            verse,
+            text: text.to_string(),
+            syllabic: Syllabic::Single,
+            extend: false,
+        }
+    }
+}
+
+/// Sets the words of a verse to a voice of a staff, a syllable on every note
+/// from the start of the line.
+///
+/// Words are split by spaces and syllables by hyphens. Every `_` at the end of a syllable
+/// holds it over one more note, drawn with an extender line at the end of a word,
+/// and a lone `_` skips a note. Rests, tuplets and the notes tied to the one before
+/// don't get a syllable.
+/// ```
+/// use rustsheet::{
+///     bar::{Line, LineType},
+///     lyrics::{self, Syllabic},
+///     notation, MusicSheet,
+/// };
+///
+/// fn main() {
+///     let line = notation::parse_line("C4:4 C4:4 D4:4 C4:4 | F4:4 E4:2 r:4").unwrap();
+///     let lines = vec![Line::new(LineType::Single(line))];
+///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+///
+///     let verse = lyrics::parse_verse(&sheet, 0, 0, 0, 0, "Hap-py birth-day to you").unwrap();
+///     assert_eq!(verse.len(), 6);
+///     assert_eq!(verse[0].syllabic, Syllabic::Begin);
+///     assert_eq!(verse[5].position.bar, 1);
+///
+///     let verse = lyrics::parse_verse(&sheet, 0, 0, 0, 1, "Glo___-ri-a").unwrap();
+///     assert_eq!((verse[1].position.bar, verse[1].position.index), (1, 0));
+///     assert!(!verse[0].extend);
+///     assert!(lyrics::parse_verse(&sheet, 0, 0, 0, 0, "one two three four five six seven").is_err());
+/// }
+/// ```
+pub fn parse_verse(
+    sheet: &MusicSheet,
+    line: usize,
+    staff: usize,
+    voice: usize,
+    verse: u8,
+    text: &str,
+) -> Result<Vec<Lyric>, LyricsError> {
+    let tied: Vec<NotePosition> = sheet
+        .spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::Tie)
+        .map(|span| span.end)
+        .collect();
+    let mut notes = sheet
+        .voice_notes(line, staff, voice)
+        .filter(|(position, note)| {
+            matches!(
+                note,
+                Note::Note(_) | Note::Chord(_) | Note::WithGrace { .. }
+            ) && !tied.contains(position)
+        })
+        .map(|(position, _)| position);
+
+    let mut lyrics = Vec::new();
+    let mut missing = 0;
+    for word in text.split_whitespace() {
+        let syllables: Vec<&str> = word.split('-').collect();
+        for (i, syllable) in syllables.iter().enumerate() {
+            let held = syllable.len() - syllable.trim_end_matches('_').len();
+            let text = syllable.trim_end_matches('_');
+            if text.is_empty() && held == 0 {
+                return Err(LyricsError::EmptySyllable(word.to_string()));
+            }
+
+            if !text.is_empty() {
+                let syllabic = Syllabic::of(i, syllables.len());
+                match notes.next() {
+                    Some(position) => lyrics.push(Lyric {
+                        position,
+                        verse,
+                        text: text.to_string(),
+                        syllabic,
+                        extend: held > 0 && !syllabic.continues(),
+                    }),
+                    None => missing += 1,
+                }
+            }
+            for _ in 0..held {
+                notes.next();
+            }
+        }
+    }
+
+    if missing > 0 {
+        return Err(LyricsError::TooManySyllables(missing));
+    }
+    Ok(lyrics)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LyricsError {
+    #[error("The verse has {0} more syllables than there are notes")]
+    TooManySyllables(usize),
+    #[error("Empty syllable in {0}")]
+    EmptySyllable(String),
+}
@@ -0,0 +1,709 @@
+//! A compact text notation for writing bars and lines by hand.
+//!
+//! ```text
+//! clef=bass time=4/4 | G2:4 A2:4 B2:4 C3:4 | r:2 (3 C4:8 D4:8 E4:8) {D4}C4:4 | <C3 E3 G3>:1 |
+//! ```
+//!
+//! - `|` separates bars, the barlines at the start and at the end are optional.
+//! - `clef=treble`, `clef=tenor` and `clef=bass` set the clef of the bar they're in,
+//!   `time=3/4` sets its time signature and `key=Bb` or `key=F#:minor` its key signature.
+//! - `tempo=120` sets a tempo of 120 crotchets per minute from the bar it's in, `tempo=4.=60`
+//!   beats of another length, and `tempo=Allegro:4=120` writes words before the metronome
+//!   mark, with `_` for spaces.
+//! - `repeat=start` and `repeat=end` put repeat barlines around the bar, `repeat=end:3`
+//!   plays the section three times. `volta=1` or `volta=1,2` makes the bar part of an
+//!   ending, `mark=segno`, `mark=coda`, `mark=to_coda` and `mark=fine` add a
+//!   [`Marker`] and `jump=D.S._al_Coda` a [`Jump`], written like over the staff.
+//! - Notes are a tone, read with [`Tone::from_str`], and a duration after a colon:
+//!   `1` for a semibreve, `2` for a minim, `4` for a crotchet and so on down to `256`,
+//!   or `breve`, `long` and `large`. Up to three `.` after the duration add dots.
+//! - Notes without a duration take the one of the note before them, the first one is a crotchet.
+//! - `r:2` is a rest.
+//! - `<C4 E4 G4>:2` is a chord, with the duration after the closing bracket.
+//! - `(3 C4:8 D4:8 E4:8)` is a tuplet of three notes in the time of two, `(5:3 ...)` of five
+//!   in the time of three. The number alone goes with the [usual ratio](Tuplet::usual_normal).
+//!   Tuplets can hold rests, grace notes and other tuplets, and their notes have to split
+//!   in as many notes of the same size as the first number says.
+//! - `{D4}C4:4` puts a grace note before the note.
+//! - `C4:4!staccato!accent` adds articulations to a note, drawn from the notehead out:
+//!   `staccato`, `staccatissimo`, `tenuto`, `accent`, `marcato` and `fermata`, or one of
+//!   the ornaments `trill`, `mordent` and `turn`.
+//! - `"Cmaj7"` puts a [chord symbol](ChordSymbol) over the bar, at the time the notes
+//!   written before it in the voice take up. `"G7" C4:2 "F" F4:2` changes the harmony
+//!   halfway through the bar, and bars with only chord symbols are kept.
+//! - `&` starts another voice of the bar, like `E5:2 F5:2 & C4:1`, up to [`MAX_VOICES`].
+//! - `%` starts a comment, up to the end of the line.
+//!
+//! Errors point at the line and column of the input they were found at.
+//! ```
+//! use rustsheet::{bar::Clef, notation, notes::Note, tempo::Duration};
+//!
+//! fn main() {
+//!     let line = notation::parse_line("clef=bass time=4/4 | G2:4 A2 B2 C3 | r:2 (3 C4:8 D4 E4) <G2 B2 D3>:4 |").unwrap();
+//!
+//!     assert_eq!(line.bars.len(), 2);
+//!     assert_eq!(line.bars[0].header.clef, Some(Clef::Bass));
+//!     assert!(matches!(line.bars[1].voices[0][1], Note::Tuplet(_)));
+//!     assert!(matches!(&line.bars[1].voices[0][2], Note::Chord(chord) if chord.tones().len() == 3));
+//!
+//!     // two voices, each one filling the bar
+//!     let bar = notation::parse_bar("E5:4 F5 G5:2 & C4:1").unwrap();
+//!     assert_eq!(bar.voices.len(), 2);
+//!
+//!     let bar = notation::parse_bar("C5:2!staccato!accent D5:2!trill").unwrap();
+//!     assert!(matches!(&bar.voices[0][0], Note::Note(info) if info.articulations.len() == 2));
+//!
+//!     let bar = notation::parse_bar("\"Dm7\" D4:2 \"G7\" G4:2").unwrap();
+//!     assert_eq!(bar.chord_symbols[1].symbol.to_string(), "G7");
+//!     assert_eq!(bar.chord_symbols[1].at, Duration::new(1, 2));
+//!
+//!     let err = notation::parse_line("G2:4 A2:4\nB2:3").unwrap_err();
+//!     // the duration is wrong, not the tone
+//!     assert_eq!((err.line, err.column), (2, 4));
+//! }
+//! ```
+
+use std::{ops::Range, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    bar::{Bar, BarHeader, Clef, SingleLine, MAX_VOICES},
+    harmony::{ChordSymbol, ChordSymbolError, ChordSymbolMark},
+    key::KeySignatureError,
+    notes::{Articulation, Chord, Note, NoteError, NoteInfo, Ornament, Tone, Tuplet, TupletError},
+    repeat::{Jump, Marker, Volta},
+    tempo::{NoteSize, NoteSizeModifiers, NoteSizeUnit, Tempo, TimeSignature, TimeSignatureError},
+};
+
+/// Characters that end a word even without whitespace
+const DELIMITERS: &[char] = &['|', '&', '(', ')', '{', '}', '<', '>', '%'];
+
+/// Parses a whole line, with bars separated by `|`
+pub fn parse_line(input: &str) -> Result<SingleLine, NotationError> {
+    Parser::new(input).line()
+}
+
+/// Parses a single bar, without barlines
+/// ```
+/// use rustsheet::notation;
+///
+/// fn main() {
+///     let bar = notation::parse_bar("clef=treble time=3/4 C5:2. ").unwrap();
+///     assert_eq!(bar.voices[0].len(), 1);
+///
+///     assert!(notation::parse_bar("C5:2 | D5:2").is_err());
+/// }
+/// ```
+pub fn parse_bar(input: &str) -> Result<Bar, NotationError> {
+    let mut parser = Parser::new(input);
+    let bar = parser.bar()?;
+
+    match parser.peek() {
+        None => Ok(bar),
+        Some(_) => {
+            let start = parser.pos;
+            Err(parser.error(start..start + 1, NotationErrorKind::UnexpectedBarline))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("line {line}, column {column}: {kind}")]
+pub struct NotationError {
+    /// Line of the input, from 1
+    pub line: usize,
+    /// Column in characters, from 1
+    pub column: usize,
+    /// Byte range of the input the error is about
+    pub span: Range<usize>,
+    pub kind: NotationErrorKind,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum NotationErrorKind {
+    #[error("Invalid tone {0:?}: {1}")]
+    InvalidTone(String, NoteError),
+    #[error("Invalid duration {0:?}")]
+    InvalidDuration(String),
+    #[error("Invalid time signature {0:?}")]
+    InvalidTimeSignature(String, #[source] Option<TimeSignatureError>),
+    #[error("Invalid key signature {0:?}: {1}")]
+    InvalidKeySignature(String, KeySignatureError),
+    #[error("Unknown clef {0:?}")]
+    UnknownClef(String),
+    #[error("Invalid tempo {0:?}, expected beats per minute like 120, or 4.=60")]
+    InvalidTempo(String),
+    #[error("Invalid repeat {0:?}, expected start, end or end:3")]
+    InvalidRepeat(String),
+    #[error("Invalid ending {0:?}, expected the passes it's played on, like 1 or 1,2")]
+    InvalidVolta(String),
+    #[error("Unknown marker {0:?}")]
+    UnknownMarker(String),
+    #[error("Unknown jump {0:?}")]
+    UnknownJump(String),
+    #[error("Unknown setting {0:?}")]
+    UnknownSetting(String),
+    #[error("Expected a note, found {0:?}")]
+    ExpectedNote(String),
+    #[error("Invalid chord symbol {0:?}: {1}")]
+    InvalidChordSymbol(String, ChordSymbolError),
+    #[error("Chords need at least one tone")]
+    EmptyChord,
+    #[error("Invalid tuplet ratio {0:?}, expected a number like 3, or a ratio like 3:2")]
+    InvalidTupletRatio(String),
+    #[error("Invalid tuplet: {0}")]
+    InvalidTuplet(TupletError),
+    #[error("Missing {0:?}")]
+    Unclosed(char),
+    #[error("Unexpected {0:?}")]
+    Unexpected(char),
+    #[error("Unexpected barline in a single bar")]
+    UnexpectedBarline,
+    #[error("A bar can't have more than {MAX_VOICES} voices")]
+    TooManyVoices,
+    #[error("Unknown articulation or ornament {0:?}")]
+    UnknownMark(String),
+    #[error("A note can only have one ornament")]
+    TooManyOrnaments,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Notes without a duration take the last one
+    size: NoteSize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            size: NoteSizeUnit::Crotchet.into(),
+        }
+    }
+
+    fn line(&mut self) -> Result<SingleLine, NotationError> {
+        let mut bars = Vec::new();
+        // settings before a barline with no notes carry over to the next bar
+        let mut header = BarHeader::default();
+
+        loop {
+            let mut bar = self.bar()?;
+            merge_header(&mut bar.header, header);
+            header = BarHeader::default();
+
+            let end = self.peek().is_none();
+            if bar.has_notes() || !bar.chord_symbols.is_empty() {
+                bars.push(bar);
+            } else if end && bar.header != BarHeader::default() {
+                // settings at the very end still get a bar, to keep them
+                bars.push(bar);
+            } else {
+                header = bar.header;
+            }
+
+            if end {
+                break;
+            }
+            // the barline
+            self.pos += 1;
+        }
+
+        Ok(SingleLine { bars })
+    }
+
+    /// Parses until the next barline or the end of the input
+    fn bar(&mut self) -> Result<Bar, NotationError> {
+        let mut header = BarHeader::default();
+        let mut voices = vec![Vec::new()];
+        let mut chord_symbols = Vec::new();
+
+        while let Some(c) = self.peek() {
+            let start = self.pos;
+            // notes go in the last voice that was started
+            let notes = voices.last_mut().unwrap();
+            match c {
+                '|' => break,
+                '&' => {
+                    self.pos += 1;
+                    if voices.len() == MAX_VOICES {
+                        return Err(self.error(start..start + 1, NotationErrorKind::TooManyVoices));
+                    }
+                    voices.push(Vec::new());
+                }
+                '(' => {
+                    self.pos += 1;
+                    notes.push(self.tuplet(start)?);
+                }
+                '{' => {
+                    self.pos += 1;
+                    notes.push(self.grace_note(start)?);
+                }
+                '<' => {
+                    self.pos += 1;
+                    notes.push(self.chord(start)?);
+                }
+                '"' => {
+                    let at = notes.iter().map(Note::duration).sum();
+                    chord_symbols.push(ChordSymbolMark::new(at, self.chord_symbol(start)?));
+                }
+                ')' | '}' | '>' => {
+                    return Err(self.error(start..start + 1, NotationErrorKind::Unexpected(c)))
+                }
+                _ => {
+                    let (span, word) = self.word();
+                    match word.split_once('=') {
+                        Some((key, value)) => self.setting(&mut header, span, key, value)?,
+                        None => notes.push(self.note_or_rest(span, word)?),
+                    }
+                }
+            }
+        }
+
+        Ok(Bar {
+            header,
+            voices,
+            chord_symbols,
+        })
+    }
+
+    /// Reads a chord symbol between quotes, `start` is the opening one
+    fn chord_symbol(&mut self, start: usize) -> Result<ChordSymbol, NotationError> {
+        let rest = &self.input[start + 1..];
+        let Some(len) = rest.find('"') else {
+            return Err(self.error(start..start + 1, NotationErrorKind::Unclosed('"')));
+        };
+        let text = &rest[..len];
+        self.pos = start + len + 2;
+
+        text.parse().map_err(|e| {
+            self.error(
+                start..self.pos,
+                NotationErrorKind::InvalidChordSymbol(text.to_string(), e),
+            )
+        })
+    }
+
+    fn setting(
+        &self,
+        header: &mut BarHeader,
+        span: Range<usize>,
+        key: &str,
+        value: &str,
+    ) -> Result<(), NotationError> {
+        match key {
+            "clef" => {
+                header.clef = Some(match value {
+                    "treble" => Clef::Treble,
+                    "tenor" => Clef::Tenor,
+                    "bass" => Clef::Bass,
+                    _ => {
+                        return Err(
+                            self.error(span, NotationErrorKind::UnknownClef(value.to_string()))
+                        )
+                    }
+                });
+            }
+            "key" => {
+                let key = value.replacen(':', " ", 1).parse().map_err(|e| {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::InvalidKeySignature(value.to_string(), e),
+                    )
+                })?;
+                header.key_signature = Some(key);
+            }
+            "time" => {
+                let invalid = |e| {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::InvalidTimeSignature(value.to_string(), e),
+                    )
+                };
+                let (num, den) = value.split_once('/').ok_or_else(|| invalid(None))?;
+                let num = u8::from_str(num).map_err(|_| invalid(None))?;
+                let den = u8::from_str(den).map_err(|_| invalid(None))?;
+                header.time_signature =
+                    Some(TimeSignature::new(num, den).map_err(|e| invalid(Some(e)))?);
+            }
+            "tempo" => {
+                let invalid = || {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::InvalidTempo(value.to_string()),
+                    )
+                };
+                let (text, mark) = match value.split_once(':') {
+                    Some((text, mark)) => (Some(text.replace('_', " ")), mark),
+                    None => (None, value),
+                };
+                let (beat, bpm) = match mark.split_once('=') {
+                    Some((beat, bpm)) => (note_size(beat).ok_or_else(invalid)?, bpm),
+                    None => (NoteSizeUnit::Crotchet.into(), mark),
+                };
+                let bpm = u16::from_str(bpm).map_err(|_| invalid())?;
+                let tempo = Tempo::new(beat, bpm).map_err(|_| invalid())?;
+                header.tempo = Some(Tempo { text, ..tempo });
+            }
+            "repeat" => {
+                let invalid = || {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::InvalidRepeat(value.to_string()),
+                    )
+                };
+                match value.split_once(':') {
+                    _ if value == "start" => header.repeat_start = true,
+                    _ if value == "end" => header.repeat_end = Some(2),
+                    Some(("end", times)) => {
+                        let times = u8::from_str(times).map_err(|_| invalid())?;
+                        if times < 2 {
+                            return Err(invalid());
+                        }
+                        header.repeat_end = Some(times);
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            "volta" => {
+                let invalid = || {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::InvalidVolta(value.to_string()),
+                    )
+                };
+                let passes = value
+                    .split(',')
+                    .map(|pass| u8::from_str(pass).map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?;
+                header.volta = Some(Volta::new(passes).map_err(|_| invalid())?);
+            }
+            "mark" => {
+                header.markers.push(match value {
+                    "segno" => Marker::Segno,
+                    "coda" => Marker::Coda,
+                    "to_coda" => Marker::ToCoda,
+                    "fine" => Marker::Fine,
+                    _ => {
+                        return Err(
+                            self.error(span, NotationErrorKind::UnknownMarker(value.to_string()))
+                        )
+                    }
+                });
+            }
+            "jump" => {
+                let jump = Jump::from_str(&value.replace('_', " ")).map_err(|_| {
+                    self.error(
+                        span.clone(),
+                        NotationErrorKind::UnknownJump(value.to_string()),
+                    )
+                })?;
+                header.jump = Some(jump);
+            }
+            _ => return Err(self.error(span, NotationErrorKind::UnknownSetting(key.to_string()))),
+        }
+
+        Ok(())
+    }
+
+    /// Parses a tuplet, after its opening parenthesis
+    fn tuplet(&mut self, start: usize) -> Result<Note, NotationError> {
+        let ratio_start = self.pos;
+        let len = self.input[self.pos..]
+            .find(|c: char| !c.is_ascii_digit() && c != ':')
+            .unwrap_or(self.input.len() - self.pos);
+        self.pos += len;
+        let ratio_span = ratio_start..self.pos;
+        let ratio = &self.input[ratio_span.clone()];
+
+        let invalid = || {
+            self.error(
+                ratio_span.clone(),
+                NotationErrorKind::InvalidTupletRatio(ratio.to_string()),
+            )
+        };
+        let (actual, normal) = match ratio.split_once(':') {
+            Some((actual, normal)) => (actual, Some(normal)),
+            None => (ratio, None),
+        };
+        let actual = u8::from_str(actual).map_err(|_| invalid())?;
+        let normal = match normal {
+            Some(normal) => u8::from_str(normal).map_err(|_| invalid())?,
+            None => Tuplet::usual_normal(actual),
+        };
+
+        let mut notes = Vec::new();
+        loop {
+            let at = self.pos;
+            match self.peek() {
+                Some(')') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('(') => {
+                    self.pos += 1;
+                    notes.push(self.tuplet(at)?);
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    notes.push(self.grace_note(at)?);
+                }
+                Some('<') => {
+                    self.pos += 1;
+                    notes.push(self.chord(at)?);
+                }
+                Some(c @ ('|' | '&' | '}' | '>')) => {
+                    let at = self.pos;
+                    return Err(self.error(at..at + 1, NotationErrorKind::Unexpected(c)));
+                }
+                None => return Err(self.error(start..start + 1, NotationErrorKind::Unclosed(')'))),
+                Some(_) => {
+                    let (span, word) = self.word();
+                    notes.push(self.note_or_rest(span, word)?);
+                }
+            }
+        }
+
+        Tuplet::with_ratio(actual, normal, notes)
+            .map(Note::Tuplet)
+            .map_err(|e| self.error(start..self.pos, NotationErrorKind::InvalidTuplet(e)))
+    }
+
+    /// Parses a grace note and the note it's attached to, after the opening brace
+    fn grace_note(&mut self, start: usize) -> Result<Note, NotationError> {
+        let (span, word) = self.word();
+        let grace = self.tone(span, word)?;
+        if self.peek() != Some('}') {
+            return Err(self.error(start..start + 1, NotationErrorKind::Unclosed('}')));
+        }
+        self.pos += 1;
+
+        // the main note follows right away
+        let (span, word) = self.word();
+        let note = self.note(span, word)?;
+
+        Ok(Note::WithGrace { grace, note })
+    }
+
+    /// Parses the tones of a chord and its duration, after the opening angle bracket
+    fn chord(&mut self, start: usize) -> Result<Note, NotationError> {
+        let mut tones = Vec::new();
+        loop {
+            match self.peek() {
+                Some('>') => {
+                    self.pos += 1;
+                    break;
+                }
+                None | Some('|') => {
+                    return Err(self.error(start..start + 1, NotationErrorKind::Unclosed('>')))
+                }
+                Some(c) if DELIMITERS.contains(&c) => {
+                    let at = self.pos;
+                    return Err(self.error(at..at + 1, NotationErrorKind::Unexpected(c)));
+                }
+                Some(_) => {
+                    let (span, word) = self.word();
+                    tones.push(self.tone(span, word)?);
+                }
+            }
+        }
+
+        // the duration goes right after the bracket, like for a single note
+        let size = if self.input[self.pos..].starts_with(':') {
+            let (span, word) = self.word();
+            self.size(span.start + 1..span.end, &word[1..])?
+        } else {
+            self.size
+        };
+
+        Chord::new(tones, size)
+            .map(Note::Chord)
+            .ok_or_else(|| self.error(start..self.pos, NotationErrorKind::EmptyChord))
+    }
+
+    fn note_or_rest(&mut self, span: Range<usize>, word: &str) -> Result<Note, NotationError> {
+        let (tone, duration) = word.split_once(':').unwrap_or((word, ""));
+        if tone == "r" {
+            let size = self.size(span.start + 2..span.end, duration)?;
+            return Ok(Note::Rest(size));
+        }
+
+        self.note(span, word).map(Note::Note)
+    }
+
+    fn note(&mut self, span: Range<usize>, word: &str) -> Result<NoteInfo, NotationError> {
+        let (word, marks) = match word.split_once('!') {
+            Some((word, marks)) => (word, Some(marks)),
+            None => (word, None),
+        };
+        let (tone, duration) = word.split_once(':').unwrap_or((word, ""));
+        let tone_span = span.start..span.start + tone.len();
+
+        let mut note = NoteInfo::new(
+            self.tone(tone_span, tone)?,
+            self.size(
+                span.start + tone.len() + 1..span.start + word.len(),
+                duration,
+            )?,
+        );
+
+        let mut at = span.start + word.len() + 1;
+        for mark in marks.into_iter().flat_map(|m| m.split('!')) {
+            let mark_span = at..at + mark.len();
+            at = mark_span.end + 1;
+
+            if let Some(articulation) = articulation(mark) {
+                note.articulations.push(articulation);
+                continue;
+            }
+            let ornament = ornament(mark).ok_or_else(|| {
+                self.error(
+                    mark_span.clone(),
+                    NotationErrorKind::UnknownMark(mark.to_string()),
+                )
+            })?;
+            if note.ornament.replace(ornament).is_some() {
+                return Err(self.error(mark_span, NotationErrorKind::TooManyOrnaments));
+            }
+        }
+
+        Ok(note)
+    }
+
+    fn tone(&self, span: Range<usize>, word: &str) -> Result<Tone, NotationError> {
+        if word.is_empty() {
+            let found = self.input[span.start..].chars().next();
+            return Err(self.error(
+                span.start..span.start + found.map_or(0, char::len_utf8),
+                NotationErrorKind::ExpectedNote(found.map(String::from).unwrap_or_default()),
+            ));
+        }
+
+        Tone::from_str(word)
+            .map_err(|e| self.error(span, NotationErrorKind::InvalidTone(word.to_string(), e)))
+    }
+
+    fn size(&mut self, span: Range<usize>, duration: &str) -> Result<NoteSize, NotationError> {
+        if duration.is_empty() {
+            return Ok(self.size);
+        }
+
+        self.size = note_size(duration).ok_or_else(|| {
+            self.error(
+                span.clone(),
+                NotationErrorKind::InvalidDuration(duration.to_string()),
+            )
+        })?;
+        Ok(self.size)
+    }
+
+    /// Skips whitespace and comments, then returns the next character without consuming it
+    fn peek(&mut self) -> Option<char> {
+        loop {
+            let rest = &self.input[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with('%') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+                continue;
+            }
+
+            return trimmed.chars().next();
+        }
+    }
+
+    /// Reads until the next whitespace or delimiter
+    fn word(&mut self) -> (Range<usize>, &'a str) {
+        self.peek();
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || DELIMITERS.contains(&c))
+            .unwrap_or(rest.len());
+
+        let span = self.pos..self.pos + len;
+        self.pos += len;
+
+        let word = &self.input[span.clone()];
+        (span, word)
+    }
+
+    fn error(&self, span: Range<usize>, kind: NotationErrorKind) -> NotationError {
+        let before = &self.input[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+
+        NotationError {
+            line,
+            column,
+            span,
+            kind,
+        }
+    }
+}
+
+/// Settings written in the bar win over the ones carried over from before it
+fn merge_header(header: &mut BarHeader, carried: BarHeader) {
+    header.clef = header.clef.or(carried.clef);
+    header.time_signature = header.time_signature.or(carried.time_signature);
+    header.key_signature = header.key_signature.or(carried.key_signature);
+    header.tempo = header.tempo.take().or(carried.tempo);
+    header.repeat_start |= carried.repeat_start;
+    header.repeat_end = header.repeat_end.or(carried.repeat_end);
+    header.volta = header.volta.take().or(carried.volta);
+    header.markers.extend(carried.markers);
+    header.jump = header.jump.or(carried.jump);
+}
+
+/// Reads a duration like `4` or `2.`, without the colon
+fn note_size(duration: &str) -> Option<NoteSize> {
+    let value = duration.trim_end_matches('.');
+    let modifiers = match duration.len() - value.len() {
+        0 => None,
+        1 => Some(NoteSizeModifiers::Dot),
+        2 => Some(NoteSizeModifiers::DoubleDot),
+        3 => Some(NoteSizeModifiers::TripleDot),
+        _ => return None,
+    };
+    let unit = match value {
+        "large" => NoteSizeUnit::Large,
+        "long" => NoteSizeUnit::Long,
+        "breve" => NoteSizeUnit::Breve,
+        "1" => NoteSizeUnit::SemiBreve,
+        "2" => NoteSizeUnit::Minim,
+        "4" => NoteSizeUnit::Crotchet,
+        "8" => NoteSizeUnit::Quaver,
+        "16" => NoteSizeUnit::SemiQuaver,
+        "32" => NoteSizeUnit::DemiSemiQuaver,
+        "64" => NoteSizeUnit::HemiDemiSemiQuaver,
+        "128" => NoteSizeUnit::SemiHemiDemiSemiQuaver,
+        "256" => NoteSizeUnit::DemiSemiHemiDemiSemiQuaver,
+        _ => return None,
+    };
+
+    Some(NoteSize { unit, modifiers })
+}
+
+fn articulation(name: &str) -> Option<Articulation> {
+    Some(match name {
+        "staccato" => Articulation::Staccato,
+        "staccatissimo" => Articulation::Staccatissimo,
+        "tenuto" => Articulation::Tenuto,
+        "accent" => Articulation::Accent,
+        "marcato" => Articulation::Marcato,
+        "fermata" => Articulation::Fermata,
+        _ => return None,
+    })
+}
+
+fn ornament(name: &str) -> Option<Ornament> {
+    Some(match name {
+        "trill" => Ornament::Trill,
+        "mordent" => Ornament::Mordent,
+        "turn" => Ornament::Turn,
+        _ => return None,
+    })
+}
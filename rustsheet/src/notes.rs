@@ -1,28 +1,407 @@
-use crate::tempo::NoteSize;
+use crate::tempo::{Duration, NoteSize, NoteSizeModifiers};
+use crate::transpose::{self, Transposition};
 use std::fmt;
-use std::num::ParseIntError;
+use std::num::{NonZeroU8, ParseIntError};
 use std::str::FromStr;
 use thiserror::Error;
-use tinyvec::ArrayVec;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Note {
     Note(NoteInfo),
-    Tuplet(ArrayVec<[NoteInfo; crate::MAX_NOTES_IN_TUPLET]>),
+    Chord(Chord),
+    Tuplet(Tuplet),
     WithGrace { grace: Tone, note: NoteInfo },
     Rest(NoteSize),
 }
 
+impl Note {
+    /// How long the note lasts in the bar, grace notes take no time.
+    /// ```
+    /// use rustsheet::{
+    ///     notes::{Note, NoteInfo, Tuplet},
+    ///     tempo::{Duration, NoteSizeUnit},
+    /// };
+    ///
+    /// fn main() {
+    ///     let quaver = Note::Note(NoteInfo {
+    ///         size: NoteSizeUnit::Quaver.into(),
+    ///         ..Default::default()
+    ///     });
+    ///     let triplet = Tuplet::new(3, 2, NoteSizeUnit::Quaver.into(), vec![quaver; 3]).unwrap();
+    ///     let triplet = Note::Tuplet(triplet);
+    ///
+    ///     // three quavers in the time of two
+    ///     assert_eq!(triplet.duration(), Duration::new(1, 4));
+    /// }
+    /// ```
+    pub fn duration(&self) -> Duration {
+        match self {
+            Note::Note(info) | Note::WithGrace { note: info, .. } => info.size.duration(),
+            Note::Chord(chord) => chord.size.duration(),
+            Note::Tuplet(tuplet) => tuplet.duration(),
+            Note::Rest(size) => size.duration(),
+        }
+    }
+
+    /// Whether the note is held longer than written, with a fermata or a crown.
+    ///
+    /// Tuplets don't have one of their own, only the notes in them can.
+    pub fn has_fermata(&self) -> bool {
+        let crown = |size: &NoteSize| matches!(size.modifiers, Some(NoteSizeModifiers::Crown));
+        match self {
+            Note::Note(info) | Note::WithGrace { note: info, .. } => {
+                info.articulations.contains(&Articulation::Fermata) || crown(&info.size)
+            }
+            Note::Chord(chord) => crown(&chord.size),
+            Note::Rest(size) => crown(size),
+            Note::Tuplet(_) => false,
+        }
+    }
+
+    /// The tones held for the whole note, none for rests and tuplets
+    pub fn tones(&self) -> &[Tone] {
+        match self {
+            Note::Note(info) | Note::WithGrace { note: info, .. } => {
+                std::slice::from_ref(&info.tone)
+            }
+            Note::Chord(chord) => chord.tones(),
+            Note::Tuplet(_) | Note::Rest(_) => &[],
+        }
+    }
+
+    /// The same note with its tones, its grace note and the notes of tuplets transposed,
+    /// failing if any would go out of range or need more than one accidental
+    pub fn transpose(&self, transposition: Transposition) -> Result<Note, NoteError> {
+        let (steps, semitones) = transposition.interval();
+        let by = Transposition::diatonic(steps, semitones);
+        let info = |info: &NoteInfo| -> Result<NoteInfo, NoteError> {
+            Ok(NoteInfo {
+                tone: info.tone.transpose(by)?,
+                ..info.clone()
+            })
+        };
+
+        Ok(match self {
+            Note::Note(note) => Note::Note(info(note)?),
+            Note::Chord(chord) => Note::Chord(Chord {
+                tones: chord
+                    .tones
+                    .iter()
+                    .map(|tone| tone.transpose(by))
+                    .collect::<Result<_, _>>()?,
+                size: chord.size,
+            }),
+            Note::Tuplet(tuplet) => Note::Tuplet(Tuplet {
+                notes: tuplet
+                    .notes
+                    .iter()
+                    .map(|note| note.transpose(by))
+                    .collect::<Result<_, _>>()?,
+                ..tuplet.clone()
+            }),
+            Note::WithGrace { grace, note } => Note::WithGrace {
+                grace: grace.transpose(by)?,
+                note: info(note)?,
+            },
+            Note::Rest(size) => Note::Rest(*size),
+        })
+    }
+}
+
+/// Notes played in the time of a different number of them, like three quavers in the time of two.
+///
+/// The ratio counts `unit`s: a 3:2 tuplet of quavers holds anything that adds up to three
+/// quavers, rests and other tuplets included, and lasts as long as two of them.
+///
+/// Tuplets are only made by [`new`](Tuplet::new) and [`with_ratio`](Tuplet::with_ratio),
+/// so their notes always add up to their ratio.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Tuplet {
+    actual: NonZeroU8,
+    normal: NonZeroU8,
+    unit: NoteSize,
+    notes: Vec<Note>,
+}
+
+impl Tuplet {
+    /// Creates a tuplet, or fails if the ratio has a zero or the notes don't add up
+    /// to `actual` units.
+    /// ```
+    /// use rustsheet::{
+    ///     notes::{Note, NoteInfo, Tuplet},
+    ///     tempo::NoteSizeUnit,
+    /// };
+    ///
+    /// fn main() {
+    ///     let quaver = Note::Note(NoteInfo {
+    ///         size: NoteSizeUnit::Quaver.into(),
+    ///         ..Default::default()
+    ///     });
+    ///
+    ///     // a septuplet of quavers in the time of four
+    ///     assert!(Tuplet::new(7, 4, NoteSizeUnit::Quaver.into(), vec![quaver.clone(); 7]).is_ok());
+    ///     // one quaver is missing
+    ///     assert!(Tuplet::new(7, 4, NoteSizeUnit::Quaver.into(), vec![quaver; 6]).is_err());
+    /// }
+    /// ```
+    pub fn new(
+        actual: u8,
+        normal: u8,
+        unit: NoteSize,
+        notes: Vec<Note>,
+    ) -> Result<Self, TupletError> {
+        if actual == 0 || normal == 0 {
+            return Err(TupletError::InvalidRatio(actual, normal));
+        }
+
+        let tuplet = Self {
+            actual: NonZeroU8::new(actual).unwrap(),
+            normal: NonZeroU8::new(normal).unwrap(),
+            unit,
+            notes,
+        };
+        tuplet.check()?;
+
+        Ok(tuplet)
+    }
+
+    /// Creates a tuplet with the unit its notes are written in, like quavers
+    /// for three quavers in a triplet.
+    ///
+    /// Fails if the notes can't be split in `actual` notes of one size.
+    /// ```
+    /// use rustsheet::{
+    ///     notes::{Note, NoteInfo, Tuplet},
+    ///     tempo::{Duration, NoteSizeUnit},
+    /// };
+    ///
+    /// fn main() {
+    ///     let note = |unit: NoteSizeUnit| Note::Note(NoteInfo {
+    ///         size: unit.into(),
+    ///         ..Default::default()
+    ///     });
+    ///
+    ///     // a crotchet and a quaver, in the time of two quavers
+    ///     let notes = vec![note(NoteSizeUnit::Crotchet), note(NoteSizeUnit::Quaver)];
+    ///     let tuplet = Tuplet::with_ratio(3, 2, notes).unwrap();
+    ///     assert_eq!(tuplet.unit().unit, NoteSizeUnit::Quaver);
+    ///     assert_eq!(tuplet.duration(), Duration::new(1, 4));
+    /// }
+    /// ```
+    pub fn with_ratio(actual: u8, normal: u8, notes: Vec<Note>) -> Result<Self, TupletError> {
+        if actual == 0 || normal == 0 {
+            return Err(TupletError::InvalidRatio(actual, normal));
+        }
+
+        let written: Duration = notes.iter().map(Note::duration).sum();
+        let unit = match written.scale(1, actual.into()).to_note_sizes().as_deref() {
+            Some([unit]) => *unit,
+            _ => return Err(TupletError::NoUnit { actual, written }),
+        };
+
+        Self::new(actual, normal, unit, notes)
+    }
+
+    /// How many units are written
+    pub fn actual(&self) -> u8 {
+        self.actual.get()
+    }
+
+    /// How many units they're played in the time of
+    pub fn normal(&self) -> u8 {
+        self.normal.get()
+    }
+
+    /// The note the ratio counts
+    pub fn unit(&self) -> NoteSize {
+        self.unit
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// The usual number of notes a tuplet of `actual` notes is played in the time of:
+    /// two for triplets, three for duplets and quadruplets, and the power of two
+    /// right below for the others, like four for quintuplets and septuplets
+    pub fn usual_normal(actual: u8) -> u8 {
+        match actual {
+            0 | 1 => actual,
+            2 | 4 => 3,
+            _ => 1 << (7 - (actual - 1).leading_zeros()),
+        }
+    }
+
+    /// How long the notes inside are, as written
+    pub fn written_duration(&self) -> Duration {
+        self.notes.iter().map(Note::duration).sum()
+    }
+
+    /// How long the tuplet lasts in the bar
+    pub fn duration(&self) -> Duration {
+        self.written_duration()
+            .scale(self.normal.get().into(), self.actual.get().into())
+    }
+
+    /// Checks that the tuplet and the ones inside it add up to their ratio
+    pub fn check(&self) -> Result<(), TupletError> {
+        if self.notes.is_empty() {
+            return Err(TupletError::Empty);
+        }
+
+        let expected = self.unit.duration().scale(self.actual.get().into(), 1);
+        let found = self.written_duration();
+        if expected != found {
+            return Err(TupletError::WrongLength { expected, found });
+        }
+
+        self.notes.iter().try_for_each(|note| match note {
+            Note::Tuplet(tuplet) => tuplet.check(),
+            _ => Ok(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum TupletError {
+    #[error("Tuplets can't have a zero in their ratio, found {0}:{1}")]
+    InvalidRatio(u8, u8),
+    #[error("Tuplets need at least one note")]
+    Empty,
+    #[error("The notes of the tuplet add up to {found} instead of {expected}")]
+    WrongLength { expected: Duration, found: Duration },
+    #[error("Notes adding up to {written} can't be split in {actual} notes of the same size")]
+    NoUnit { actual: u8, written: Duration },
+}
+
 impl Default for Note {
     fn default() -> Self {
         Note::Note(Default::default())
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoteInfo {
     pub tone: Tone,
     pub size: NoteSize,
+    /// Drawn from the notehead outwards, in this order
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub articulations: Vec<Articulation>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub ornament: Option<Ornament>,
+}
+
+impl NoteInfo {
+    /// A plain note, without articulations or ornaments
+    pub fn new(tone: Tone, size: NoteSize) -> Self {
+        Self {
+            tone,
+            size,
+            articulations: Vec::new(),
+            ornament: None,
+        }
+    }
+}
+
+/// How a note is attacked and held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Articulation {
+    /// Detached, played for about half of its length
+    Staccato,
+    /// Even shorter than staccato
+    Staccatissimo,
+    /// Held for its whole length
+    Tenuto,
+    /// Played louder than the notes around it
+    Accent,
+    /// A strong accent, louder and a bit detached
+    Marcato,
+    /// Held longer than written, like a [`NoteSizeModifiers::Crown`] but on a note
+    /// that can also have dots
+    Fermata,
+}
+
+/// Quick notes played around a note, in its time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Ornament {
+    /// The note and the one above it, alternating quickly
+    Trill,
+    /// The note, the one below it and the note again
+    Mordent,
+    /// The note above, the note, the one below and the note again
+    Turn,
+}
+
+/// Several tones played together, all as long as each other.
+///
+/// The tones are kept from the lowest to the highest, without repeating any of them.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Chord {
+    tones: Vec<Tone>,
+    pub size: NoteSize,
+}
+
+impl Chord {
+    /// Creates a chord out of tones in any order, or returns `None` if there are none.
+    ///
+    /// Tones that sound the same are only kept once, even if they're spelled differently.
+    /// ```
+    /// use rustsheet::{notes::Chord, tempo::NoteSizeUnit};
+    ///
+    /// fn main() {
+    ///     let tones = ["G4", "C4", "E4", "C4"].map(|t| t.parse().unwrap());
+    ///     let chord = Chord::new(tones, NoteSizeUnit::Minim.into()).unwrap();
+    ///
+    ///     let names: Vec<String> = chord.tones().iter().map(|t| t.to_string()).collect();
+    ///     assert_eq!(names, ["C4", "E4", "G4"]);
+    /// }
+    /// ```
+    pub fn new(tones: impl IntoIterator<Item = Tone>, size: NoteSize) -> Option<Self> {
+        let mut tones: Vec<Tone> = tones.into_iter().collect();
+        tones.sort();
+        tones.dedup();
+
+        if tones.is_empty() {
+            return None;
+        }
+
+        Some(Self { tones, size })
+    }
+
+    /// The tones, from the lowest
+    pub fn tones(&self) -> &[Tone] {
+        &self.tones
+    }
+
+    pub fn lowest(&self) -> Tone {
+        self.tones[0]
+    }
+
+    pub fn highest(&self) -> Tone {
+        self.tones[self.tones.len() - 1]
+    }
+
+    /// Adds a tone, unless the chord already has it
+    pub fn add(&mut self, tone: Tone) {
+        if let Err(i) = self.tones.binary_search(&tone) {
+            self.tones.insert(i, tone);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -123,6 +502,51 @@ impl Tone {
         }
     }
 
+    /// The tone moved by `transposition`, spelled without double sharps or flats.
+    ///
+    /// Chromatic transpositions are spelled as in C major. Fails for tones below C0 or above B255.
+    /// ```
+    /// use rustsheet::{notes::Tone, transpose::Transposition};
+    ///
+    /// fn main() {
+    ///     let tone: Tone = "B3".parse().unwrap();
+    ///     // an augmented second up, C## would need a double sharp
+    ///     let up = tone.transpose(Transposition::diatonic(1, 3)).unwrap();
+    ///     assert_eq!(up.to_string(), "D4");
+    ///     assert_eq!(tone.transpose(Transposition::Chromatic(-1)).unwrap().to_string(), "A3♯");
+    ///
+    ///     let low: Tone = "D0".parse().unwrap();
+    ///     assert!(low.transpose(Transposition::diatonic(-2, -4)).is_err());
+    ///     // five semitones don't make a unison, not even with a double sharp
+    ///     assert!(tone.transpose(Transposition::diatonic(0, 5)).is_err());
+    /// }
+    /// ```
+    pub fn transpose(&self, transposition: Transposition) -> Result<Tone, NoteError> {
+        let (steps, semitones) = transposition.interval();
+        let (octave, alter, position) = transpose::transpose_letter(
+            (
+                self.octave,
+                transpose::alteration(self.modifiers),
+                self.position.into(),
+            ),
+            steps,
+            semitones,
+        )?;
+        let position = transpose::octave(position)?;
+        let modifiers = transpose::modifiers(alter, self.modifiers);
+
+        match modifiers {
+            Some(modifiers) => Tone::with_modifiers(octave, position, modifiers),
+            None => Some(Tone::new(octave, position)),
+        }
+        .ok_or(NoteError::OutOfRange)
+    }
+
+    /// How many letters the tone is from C0, accidentals don't change it
+    pub fn get_steps_since_c0(&self) -> u32 {
+        self.position as u32 * 7 + u32::from(self.octave.index())
+    }
+
     pub fn get_semitones_since_c0(&self) -> u32 {
         let mut semitones = self.position as u32 * 12;
         semitones += self.octave as u32;
@@ -148,8 +572,7 @@ impl Eq for Tone {}
 
 impl PartialOrd for Tone {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.get_semitones_since_c0()
-            .partial_cmp(&other.get_semitones_since_c0())
+        Some(self.cmp(other))
     }
 }
 
@@ -169,25 +592,91 @@ impl fmt::Display for Tone {
     }
 }
 
+/// Reads a tone in scientific notation, like `C4`, `C#4` or `Bb3`, or in Helmholtz notation,
+/// like `C,` (C1), `C` (C2), `c` (C3) or `c'` (C4).
+///
+/// Accidentals are `#`, `b` and `n` or `♯`, `♭` and `♮`. In scientific notation they can go
+/// either before or after the octave number, so what [`Display`](fmt::Display) prints reads back.
+/// ```
+/// use rustsheet::notes::{NoteError, Octave, Tone, ToneModifiers};
+///
+/// fn main() {
+///     let b_flat = Tone::with_modifiers(Octave::B, 3, ToneModifiers::Flat).unwrap();
+///
+///     assert_eq!("Bb3".parse::<Tone>().unwrap(), b_flat);
+///     assert_eq!("B♭3".parse::<Tone>().unwrap(), b_flat);
+///     assert_eq!("bb".parse::<Tone>().unwrap(), b_flat);
+///     assert_eq!(b_flat.to_string().parse::<Tone>().unwrap(), b_flat);
+///     assert_eq!("c''".parse::<Tone>().unwrap(), Tone::new(Octave::C, 5));
+///
+///     assert!(matches!("Cb0".parse::<Tone>(), Err(NoteError::OutOfRange)));
+///     assert!(matches!("C#b4".parse::<Tone>(), Err(NoteError::DuplicateAccidental)));
+/// }
+/// ```
 impl FromStr for Tone {
     type Err = NoteError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // u8 max value is 3 digits, so check that value is at most 5 and at least 2
-        if s.len() > 4 || s.len() < 2 {
-            return Err(NoteError::InvalidNote);
+        let mut chars = s.chars().peekable();
+
+        let letter = chars.next().ok_or(NoteError::Empty)?;
+        let octave = Octave::try_from(letter.to_ascii_uppercase())
+            .map_err(|_| NoteError::InvalidOctave(letter))?;
+        let lowercase = letter.is_ascii_lowercase();
+
+        let mut modifiers = None;
+        let mut number = None;
+        let mut marks = 0;
+        let mut marked = false;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '0'..='9' => {
+                    if number.is_some() || marked {
+                        return Err(NoteError::MixedNotation);
+                    }
+                    let mut digits = String::from(c);
+                    while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                        digits.push(d);
+                    }
+                    number = Some(u8::from_str(&digits)?);
+                }
+                '\'' | '′' | ',' => {
+                    if number.is_some() {
+                        return Err(NoteError::MixedNotation);
+                    }
+                    // primes raise lowercase letters, commas lower uppercase ones
+                    if lowercase == (c == ',') {
+                        return Err(NoteError::InvalidOctaveMark(c));
+                    }
+                    marked = true;
+                    marks += 1;
+                }
+                _ => {
+                    let modifier = ToneModifiers::try_from(c)
+                        .map_err(|_| NoteError::UnexpectedCharacter(c))?;
+                    if modifiers.is_some() {
+                        return Err(NoteError::DuplicateAccidental);
+                    }
+                    modifiers = Some(modifier);
+                }
+            }
         }
 
-        let oct = s.chars().nth(0).unwrap();
+        let position = match number {
+            Some(_) if lowercase => return Err(NoteError::MixedNotation),
+            Some(number) => number,
+            // Helmholtz notation, C is C2 and c is C3
+            None if lowercase => 3u8.checked_add(marks).ok_or(NoteError::OutOfRange)?,
+            None => 2u8.checked_sub(marks).ok_or(NoteError::OutOfRange)?,
+        };
 
-        let octave = Octave::try_from(oct)?;
-        let num = &s[1..];
-        Ok(Tone {
-            octave,
-            position: u8::from_str(num)?,
-            // not implemented yet
-            modifiers: None,
-        })
+        match modifiers {
+            Some(modifiers) => {
+                Tone::with_modifiers(octave, position, modifiers).ok_or(NoteError::OutOfRange)
+            }
+            None => Ok(Tone::new(octave, position)),
+        }
     }
 }
 
@@ -202,6 +691,38 @@ pub enum Octave {
     F = 5,
     G = 7,
 }
+
+impl Octave {
+    /// The letters in order, from C
+    pub const ALL: [Octave; 7] = [
+        Octave::C,
+        Octave::D,
+        Octave::E,
+        Octave::F,
+        Octave::G,
+        Octave::A,
+        Octave::B,
+    ];
+
+    /// How many letters the letter is from C, 0 for C and 6 for B
+    pub fn index(&self) -> u8 {
+        match self {
+            Octave::C => 0,
+            Octave::D => 1,
+            Octave::E => 2,
+            Octave::F => 3,
+            Octave::G => 4,
+            Octave::A => 5,
+            Octave::B => 6,
+        }
+    }
+
+    /// The letter `index` letters from C, going around every 7 letters either way
+    pub fn from_index(index: i32) -> Octave {
+        Self::ALL[index.rem_euclid(7) as usize]
+    }
+}
+
 impl TryFrom<char> for Octave {
     type Error = NoteError;
 
@@ -214,7 +735,7 @@ impl TryFrom<char> for Octave {
             'E' => Ok(Octave::E),
             'F' => Ok(Octave::F),
             'G' => Ok(Octave::G),
-            _ => Err(NoteError::InvalidOctave),
+            _ => Err(NoteError::InvalidOctave(value)),
         }
     }
 }
@@ -240,6 +761,19 @@ pub enum ToneModifiers {
     Natural = 1,
 }
 
+impl TryFrom<char> for ToneModifiers {
+    type Error = NoteError;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value {
+            '#' | '♯' => Ok(ToneModifiers::Sharp),
+            'b' | '♭' => Ok(ToneModifiers::Flat),
+            'n' | '♮' => Ok(ToneModifiers::Natural),
+            _ => Err(NoteError::InvalidAccidental(value)),
+        }
+    }
+}
+
 impl fmt::Display for ToneModifiers {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -252,10 +786,230 @@ impl fmt::Display for ToneModifiers {
 
 #[derive(Debug, Clone, Error)]
 pub enum NoteError {
-    #[error("Invalid note")]
-    InvalidNote,
-    #[error("Invalid octave")]
-    InvalidOctave,
+    #[error("Empty tone")]
+    Empty,
+    #[error("Invalid note letter {0:?}, expected A to G")]
+    InvalidOctave(char),
+    #[error("Invalid accidental {0:?}, expected #, b, n, ♯, ♭ or ♮")]
+    InvalidAccidental(char),
     #[error("Invalid octave number")]
     InvalidNumber(#[from] ParseIntError),
+    #[error(
+        "Octave mark {0:?} doesn't go with the letter, use ' after lowercase and , after uppercase"
+    )]
+    InvalidOctaveMark(char),
+    #[error("Octave numbers can't be mixed with Helmholtz notation")]
+    MixedNotation,
+    #[error("A tone can only have one accidental")]
+    DuplicateAccidental,
+    #[error("Unexpected character {0:?}")]
+    UnexpectedCharacter(char),
+    #[error("The tone is out of range, nothing goes below C0")]
+    OutOfRange,
+    #[error("The tone would need more than one accidental on any letter")]
+    TooManyAccidentals,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notation, tempo::NoteSizeUnit};
+
+    fn note(unit: NoteSizeUnit) -> Note {
+        Note::Note(NoteInfo {
+            size: unit.into(),
+            ..Default::default()
+        })
+    }
+
+    fn first_note(input: &str) -> Note {
+        notation::parse_bar(input).unwrap().voices[0][0].clone()
+    }
+
+    #[test]
+    fn times_nested_tuplets() {
+        // a triplet of semiquavers in the time of the first quaver of a triplet of quavers
+        let inner = Tuplet::new(
+            3,
+            2,
+            NoteSizeUnit::SemiQuaver.into(),
+            vec![note(NoteSizeUnit::SemiQuaver); 3],
+        )
+        .unwrap();
+        assert_eq!(inner.duration(), Duration::new(1, 8));
+
+        let outer = Tuplet::new(
+            3,
+            2,
+            NoteSizeUnit::Quaver.into(),
+            vec![
+                Note::Tuplet(inner),
+                note(NoteSizeUnit::Quaver),
+                note(NoteSizeUnit::Quaver),
+            ],
+        )
+        .unwrap();
+        assert_eq!(outer.written_duration(), Duration::new(3, 8));
+        assert_eq!(Note::Tuplet(outer).duration(), Duration::new(1, 4));
+
+        // the same, written down
+        assert_eq!(
+            first_note("(3 (3 C4:16 D4 E4) F4:8 G4:8)").duration(),
+            Duration::new(1, 4)
+        );
+        // a quintuplet of quavers in the time of four, holding a triplet of quavers for two of them
+        let tuplet = first_note("(5 (3 C4:8 D4 E4) F4:8 G4:8 A4:8)");
+        assert_eq!(tuplet.duration(), Duration::new(1, 2));
+        // three levels deep
+        let tuplet = first_note("(3 (3 (3 C4:32 D4 E4) F4:16 G4:16) A4:8 B4:8)");
+        assert_eq!(tuplet.duration(), Duration::new(1, 4));
+    }
+
+    #[test]
+    fn times_tuplets_with_rests_and_grace_notes() {
+        assert_eq!(
+            first_note("(3 r:8 {D4}C4:8 r:8)").duration(),
+            Duration::new(1, 4)
+        );
+        // a crotchet and a quaver make a triplet of quavers too
+        assert_eq!(first_note("(3 C4:4 D4:8)").duration(), Duration::new(1, 4));
+        // duplets of dotted crotchets last as long as three of them
+        let duplet = Tuplet::new(
+            2,
+            3,
+            NoteSizeUnit::Quaver.into(),
+            vec![note(NoteSizeUnit::Quaver); 2],
+        )
+        .unwrap();
+        assert_eq!(duplet.duration(), Duration::new(3, 8));
+    }
+
+    #[test]
+    fn checks_nested_tuplets() {
+        // the inner triplet is a semiquaver short
+        let inner = Tuplet {
+            actual: NonZeroU8::new(3).unwrap(),
+            normal: NonZeroU8::new(2).unwrap(),
+            unit: NoteSizeUnit::SemiQuaver.into(),
+            notes: vec![note(NoteSizeUnit::SemiQuaver); 2],
+        };
+        let outer = Tuplet {
+            actual: NonZeroU8::new(3).unwrap(),
+            normal: NonZeroU8::new(2).unwrap(),
+            unit: NoteSizeUnit::Quaver.into(),
+            notes: vec![
+                Note::Tuplet(inner),
+                note(NoteSizeUnit::Quaver),
+                note(NoteSizeUnit::Quaver),
+            ],
+        };
+        assert!(matches!(
+            outer.check(),
+            Err(TupletError::WrongLength { .. })
+        ));
+
+        let empty = Tuplet {
+            notes: Vec::new(),
+            ..outer.clone()
+        };
+        assert_eq!(empty.check(), Err(TupletError::Empty));
+        let nested_empty = Tuplet {
+            notes: vec![Note::Tuplet(empty), note(NoteSizeUnit::Quaver)],
+            ..outer
+        };
+        assert!(nested_empty.check().is_err());
+    }
+
+    #[test]
+    fn refuses_tuplets_that_dont_add_up() {
+        let error = |tuplet: Result<Tuplet, TupletError>| tuplet.unwrap_err();
+        assert_eq!(
+            error(Tuplet::new(
+                0,
+                2,
+                NoteSizeUnit::Quaver.into(),
+                vec![note(NoteSizeUnit::Quaver)]
+            )),
+            TupletError::InvalidRatio(0, 2)
+        );
+        assert_eq!(
+            error(Tuplet::new(
+                3,
+                2,
+                NoteSizeUnit::Quaver.into(),
+                vec![note(NoteSizeUnit::Quaver); 4]
+            )),
+            TupletError::WrongLength {
+                expected: Duration::new(3, 8),
+                found: Duration::new(1, 2),
+            }
+        );
+        // five semiquavers can't be three notes of the same size
+        assert_eq!(
+            error(Tuplet::with_ratio(
+                3,
+                2,
+                vec![note(NoteSizeUnit::SemiQuaver); 5]
+            )),
+            TupletError::NoUnit {
+                actual: 3,
+                written: Duration::new(5, 16),
+            }
+        );
+        assert!(notation::parse_bar("(3 C4:8 D4:8)").is_err());
+    }
+
+    #[test]
+    fn plays_tuplets_in_their_usual_time() {
+        let usual: Vec<u8> = (1..=9).map(Tuplet::usual_normal).collect();
+        assert_eq!(usual, [1, 3, 2, 3, 4, 4, 4, 4, 8]);
+    }
+
+    fn tone(tone: &str) -> Tone {
+        tone.parse().unwrap()
+    }
+
+    #[test]
+    fn spells_transposed_tones_with_one_accidental() {
+        // an augmented second up from F♯ would be G𝄪, written A
+        let up = tone("F#4")
+            .transpose(Transposition::diatonic(1, 3))
+            .unwrap();
+        assert_eq!(up, tone("A4"));
+        // a diminished third down from D♭ would be B𝄫, written A
+        let down = tone("Db4")
+            .transpose(Transposition::diatonic(-2, -4))
+            .unwrap();
+        assert_eq!(down, tone("A3"));
+    }
+
+    #[test]
+    fn refuses_transpositions_no_letter_can_spell() {
+        // five semitones up on the same letter, not F4 spelled as a C
+        let unison = Transposition::diatonic(0, 5);
+        assert!(matches!(
+            tone("C4").transpose(unison),
+            Err(NoteError::TooManyAccidentals)
+        ));
+        assert!(matches!(
+            first_note("C4:4").transpose(unison),
+            Err(NoteError::TooManyAccidentals)
+        ));
+        assert!(matches!(
+            first_note("(3 C4:8 D4 E4)").transpose(unison),
+            Err(NoteError::TooManyAccidentals)
+        ));
+        let chord: crate::harmony::ChordSymbol = "C7".parse().unwrap();
+        assert!(matches!(
+            chord.transpose(unison),
+            Err(NoteError::TooManyAccidentals)
+        ));
+
+        // a second down by a tritone
+        let second = Transposition::diatonic(-1, -6);
+        assert!(matches!(
+            tone("C4").transpose(second),
+            Err(NoteError::TooManyAccidentals)
+        ));
+    }
 }
@@ -0,0 +1,172 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+/// An ending of a repeated section, played on the passes it's numbered with.
+///
+/// The bars of an ending all carry the same volta, the first and second endings
+/// of a section are usually numbered `1` and `2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Volta {
+    pub passes: Vec<u8>,
+}
+
+impl Volta {
+    /// An ending played on `passes`, counted from 1
+    /// ```
+    /// use rustsheet::repeat::Volta;
+    ///
+    /// fn main() {
+    ///     let volta = Volta::new(vec![1, 2]).unwrap();
+    ///     assert!(volta.plays_on(2));
+    ///     assert_eq!(volta.to_string(), "1, 2.");
+    ///
+    ///     assert!(Volta::new(vec![0]).is_err());
+    /// }
+    /// ```
+    pub fn new(passes: Vec<u8>) -> Result<Self, RepeatError> {
+        let volta = Self { passes };
+        volta.check()?;
+        Ok(volta)
+    }
+
+    /// Checks that the ending is played at least once, on passes counted from 1
+    pub fn check(&self) -> Result<(), RepeatError> {
+        if self.passes.is_empty() {
+            return Err(RepeatError::EmptyVolta);
+        }
+        if self.passes.contains(&0) {
+            return Err(RepeatError::ZeroPass);
+        }
+        Ok(())
+    }
+
+    pub fn plays_on(&self, pass: u8) -> bool {
+        self.passes.contains(&pass)
+    }
+}
+
+impl Display for Volta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let passes: Vec<String> = self.passes.iter().map(u8::to_string).collect();
+        write!(f, "{}.", passes.join(", "))
+    }
+}
+
+/// A sign or word telling where a jump goes to or ends.
+///
+/// The segno and the coda are written at the start of their bar, "To Coda" and "Fine"
+/// at its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Marker {
+    /// Where a dal segno goes back to
+    Segno,
+    /// The start of the coda, where an al coda jump goes on
+    Coda,
+    /// Where an al coda jump leaves for the coda
+    ToCoda,
+    /// Where an al fine jump ends the music
+    Fine,
+}
+
+/// Going back to the start or to the segno after a bar, written at its end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Jump {
+    /// Back to the start, then to the end
+    DaCapo,
+    /// Back to the start, then up to the fine
+    DaCapoAlFine,
+    /// Back to the start, then up to "To Coda" and on from the coda
+    DaCapoAlCoda,
+    /// Back to the segno, then to the end
+    DalSegno,
+    /// Back to the segno, then up to the fine
+    DalSegnoAlFine,
+    /// Back to the segno, then up to "To Coda" and on from the coda
+    DalSegnoAlCoda,
+}
+
+impl Jump {
+    /// How the jump is written over the staff
+    pub fn text(&self) -> &'static str {
+        match self {
+            Self::DaCapo => "D.C.",
+            Self::DaCapoAlFine => "D.C. al Fine",
+            Self::DaCapoAlCoda => "D.C. al Coda",
+            Self::DalSegno => "D.S.",
+            Self::DalSegnoAlFine => "D.S. al Fine",
+            Self::DalSegnoAlCoda => "D.S. al Coda",
+        }
+    }
+
+    /// Whether the jump goes back to the segno rather than to the start
+    pub fn to_segno(&self) -> bool {
+        matches!(
+            self,
+            Self::DalSegno | Self::DalSegnoAlFine | Self::DalSegnoAlCoda
+        )
+    }
+
+    /// The marker the music goes back up to after the jump, [`Marker::Fine`]
+    /// or [`Marker::ToCoda`], if it doesn't go on to the end
+    pub fn until(&self) -> Option<Marker> {
+        match self {
+            Self::DaCapoAlFine | Self::DalSegnoAlFine => Some(Marker::Fine),
+            Self::DaCapoAlCoda | Self::DalSegnoAlCoda => Some(Marker::ToCoda),
+            Self::DaCapo | Self::DalSegno => None,
+        }
+    }
+}
+
+impl Display for Jump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl FromStr for Jump {
+    type Err = ParseJumpError;
+
+    /// Parses a jump as it's written, like `D.S. al Coda`
+    /// ```
+    /// use rustsheet::repeat::{Jump, Marker};
+    ///
+    /// fn main() {
+    ///     let jump: Jump = "D.S. al Coda".parse().unwrap();
+    ///     assert!(jump.to_segno());
+    ///     assert_eq!(jump.until(), Some(Marker::ToCoda));
+    /// }
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::DaCapo,
+            Self::DaCapoAlFine,
+            Self::DaCapoAlCoda,
+            Self::DalSegno,
+            Self::DalSegnoAlFine,
+            Self::DalSegnoAlCoda,
+        ]
+        .into_iter()
+        .find(|jump| jump.text() == s)
+        .ok_or_else(|| ParseJumpError(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum RepeatError {
+    #[error("A repeated section is played at least twice, not {0} times")]
+    TooFewTimes(u8),
+    #[error("An ending has to be played on at least one pass")]
+    EmptyVolta,
+    #[error("Passes are counted from 1")]
+    ZeroPass,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown jump {0}")]
+pub struct ParseJumpError(String);
@@ -0,0 +1,569 @@
+//! Saving and loading sheets with [serde], behind the `serde` feature.
+//!
+//! The representation is meant to be read and written by hand, and is the same
+//! for every self-describing format. In JSON, a sheet looks like this:
+//!
+//! ```json
+//! {
+//!   "version": 3,
+//!   "title": "Example",
+//!   "author": "Someone",
+//!   "lines": [
+//!     {
+//!       "name": "Piano",
+//!       "line": {
+//!         "single": {
+//!           "bars": [
+//!             {
+//!               "header": {
+//!                 "clef": "treble",
+//!                 "time_signature": "3/4",
+//!                 "key_signature": "A major",
+//!                 "tempo": { "beat": { "unit": "minim", "modifiers": "dot" }, "bpm": 60, "text": "Allegro" },
+//!                 "repeat_start": true,
+//!                 "repeat_end": 2,
+//!                 "volta": { "passes": [1] },
+//!                 "markers": ["segno", "fine"],
+//!                 "jump": "dal_segno_al_fine"
+//!               },
+//!               "voices": [
+//!                 [
+//!                   { "chord": { "tones": ["C#4", "E4", "A4"], "size": { "unit": "crotchet" } } },
+//!                   { "with_grace": { "grace": "E4", "note": { "tone": "D4", "size": { "unit": "crotchet" }, "ornament": "trill" } } },
+//!                   { "tuplet": { "actual": 3, "normal": 2, "unit": { "unit": "quaver" }, "notes": [
+//!                     { "note": { "tone": "E4", "size": { "unit": "quaver" } } },
+//!                     { "rest": { "unit": "crotchet" } }
+//!                   ] } }
+//!                 ],
+//!                 [{ "note": { "tone": "A2", "size": { "unit": "minim", "modifiers": "dot" }, "articulations": ["tenuto", "fermata"] } }]
+//!               ],
+//!               "chord_symbols": [
+//!                 { "at": "0/1", "symbol": "A" },
+//!                 { "at": "1/2", "symbol": "F#m7b5/C" }
+//!               ]
+//!             }
+//!           ]
+//!         }
+//!       }
+//!     },
+//!     {
+//!       "name": "Clarinet in Bb",
+//!       "transposition": { "diatonic": { "steps": -1, "semitones": -2 } },
+//!       "line": { "single": { "bars": [
+//!         { "header": { "key_signature": "B major" }, "voices": [[{ "note": { "tone": "D#5", "size": { "unit": "semi_breve" } } }]] }
+//!       ] } }
+//!     }
+//!   ],
+//!   "spans": [
+//!     { "kind": "slur", "start": { "line": 0, "bar": 0, "index": 1 }, "end": { "line": 0, "bar": 0, "index": 2 } },
+//!     { "kind": "crescendo", "start": { "line": 0, "bar": 0, "index": 0 }, "end": { "line": 0, "bar": 0, "index": 2 } },
+//!     { "kind": "ritardando", "start": { "line": 0, "bar": 0, "index": 1 }, "end": { "line": 0, "bar": 0, "index": 2 } }
+//!   ],
+//!   "dynamics": [
+//!     { "position": { "line": 0, "bar": 0, "index": 0 }, "dynamic": "mp" }
+//!   ],
+//!   "lyrics": [
+//!     { "position": { "line": 0, "bar": 0, "index": 0 }, "text": "A", "syllabic": "begin" },
+//!     { "position": { "line": 0, "bar": 0, "index": 1 }, "text": "men", "syllabic": "end", "extend": true },
+//!     { "position": { "line": 0, "bar": 0, "index": 0 }, "verse": 1, "text": "Praise" }
+//!   ]
+//! }
+//! ```
+//!
+//! - Every sheet starts with the [`FORMAT_VERSION`] it was written with. Sheets from every
+//!   version up to it are read, the fields added since then are left empty, and newer
+//!   sheets are refused instead of being read wrong.
+//! - Tones are strings with the letter, an optional accidental (`#` for sharp, `b`
+//!   for flat and `n` for natural) and the octave number, like `"C#4"` or `"Bb3"`.
+//!   Anything [`Tone::from_str`](std::str::FromStr::from_str) reads is accepted when loading.
+//! - Bars hold a list of voices, each one a list of notes. Before version 3 they had a
+//!   single list of `notes`, read as their only voice.
+//! - Lines of transposing instruments have a `transposition` from how they're read to how
+//!   they sound, either `diatonic` with a number of `steps` and `semitones`, or `chromatic`
+//!   with only semitones, and are written as they're read unless `concert_pitch` is set.
+//! - Chords list their tones from the lowest, they're sorted and the repeated ones
+//!   dropped when loading.
+//! - Notes can have a list of `articulations`, from the notehead out, and an `ornament`.
+//! - Tempo markings have the note that gets the beat, the beats per minute and
+//!   optional words to write before them.
+//! - Bars can start a repeated section with `repeat_start`, end one with `repeat_end`
+//!   set to how many times it's played, be part of an ending played on the `passes`
+//!   of its `volta`, and hold `markers` and a `jump`, like `"da_capo_al_coda"`.
+//! - Ties, slurs, hairpins, tempo changes and dynamics point at their notes by line,
+//!   staff, bar, voice and index, all counted from 0. The staff and voice can be left
+//!   out when they're the first one, and sheets without any leave the lists out.
+//! - Dynamics are written as they're printed, like `"pp"`, `"mf"` or `"sfz"`.
+//! - Lyrics point at their notes the same way, with the `verse` counted from 0 and
+//!   where the syllable is in its word, which can be left out for the first verse and
+//!   for whole words. Syllables held over the next notes have `extend` set.
+//! - Bars can have `chord_symbols`, each one written like `"Bbmaj7"` or `"F#m7b5/A"` and
+//!   placed `at` a time from the start of the bar.
+//! - Time signatures are strings like `"3/4"`, and times are fractions of a semibreve
+//!   like `"3/8"`.
+//! - Key signatures are strings with the tonic and the mode, like `"Bb major"` or `"F# minor"`.
+//! - Tuplets have their ratio, the note it counts and their notes. Before version 2 they
+//!   were only a list of notes, read in the time of one less of them, or of three for duplets.
+//! - Enums are written in snake case, and variants with data are objects with a
+//!   single key, like `{ "none": { "center": "C4" } }` for a clef.
+//! - Fields that are empty by default (a missing subtitle, line name, transposition,
+//!   clef, time signature, key signature, tempo, tempo words, end repeat, volta, jump,
+//!   note size modifier or ornament, bars that don't start a repeat or have no markers
+//!   or chord symbols, lines written as they're read, and notes without articulations)
+//!   are left out.
+//!
+//! Loading checks what the types alone can't: time signatures can't have a zero,
+//! tones can't go below C0, bars can't have more than [`MAX_VOICES`] voices and
+//! the notes of tuplets have to add up to their ratio.
+//!
+//! The full JSON schema is in `rustsheet/schema/music-sheet.schema.json`.
+//!
+//! ```
+//! use rustsheet::MusicSheet;
+//!
+//! fn main() {
+//!     let json = r#"{
+//!         "version": 3,
+//!         "title": "Example",
+//!         "author": "Someone",
+//!         "lines": [{ "line": { "single": { "bars": [{
+//!             "header": { "clef": "bass", "time_signature": "4/4" },
+//!             "voices": [[{ "note": { "tone": "Bb2", "size": { "unit": "semi_breve" } } }]]
+//!         }] } } }]
+//!     }"#;
+//!
+//!     let sheet: MusicSheet = serde_json::from_str(json).unwrap();
+//!     assert_eq!(sheet.title, "Example");
+//!
+//!     // and back, without losing anything
+//!     let saved = serde_json::to_string(&sheet).unwrap();
+//!     let reloaded: MusicSheet = serde_json::from_str(&saved).unwrap();
+//!     assert_eq!(saved, serde_json::to_string(&reloaded).unwrap());
+//!
+//!     // a 0/4 time signature is refused
+//!     let json = json.replace("4/4", "0/4");
+//!     assert!(serde_json::from_str::<MusicSheet>(&json).is_err());
+//! }
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{self, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    bar::{Line, MAX_VOICES},
+    dynamics::DynamicMark,
+    harmony::ChordSymbol,
+    key::KeySignature,
+    lyrics::Lyric,
+    notes::{Chord, Note, NoteInfo, Tone, ToneModifiers, Tuplet},
+    span::Span,
+    tempo::{Duration, NoteSize, TimeSignature},
+    MusicSheet,
+};
+
+/// Version of the representation, bumped on every incompatible change. Fields that can be
+/// left out don't change it.
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct SheetRef<'a> {
+    version: u32,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitle: Option<&'a str>,
+    author: &'a str,
+    lines: &'a [Line],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    spans: &'a [Span],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    dynamics: &'a [DynamicMark],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    lyrics: &'a [Lyric],
+}
+
+#[derive(Deserialize)]
+struct SheetOwned {
+    version: u32,
+    title: String,
+    #[serde(default)]
+    subtitle: Option<String>,
+    author: String,
+    lines: Vec<Line>,
+    #[serde(default)]
+    spans: Vec<Span>,
+    #[serde(default)]
+    dynamics: Vec<DynamicMark>,
+    #[serde(default)]
+    lyrics: Vec<Lyric>,
+}
+
+impl Serialize for MusicSheet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SheetRef {
+            version: FORMAT_VERSION,
+            title: &self.title,
+            subtitle: self.subtitle.as_deref(),
+            author: &self.author,
+            lines: &self.lines,
+            spans: &self.spans,
+            dynamics: &self.dynamics,
+            lyrics: &self.lyrics,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MusicSheet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sheet = SheetOwned::deserialize(deserializer)?;
+        if !(1..=FORMAT_VERSION).contains(&sheet.version) {
+            return Err(de::Error::invalid_value(
+                Unexpected::Unsigned(sheet.version.into()),
+                &VersionExpected,
+            ));
+        }
+
+        Ok(MusicSheet {
+            title: sheet.title,
+            subtitle: sheet.subtitle,
+            author: sheet.author,
+            lines: sheet.lines,
+            spans: sheet.spans,
+            dynamics: sheet.dynamics,
+            lyrics: sheet.lyrics,
+        })
+    }
+}
+
+struct VersionExpected;
+
+impl de::Expected for VersionExpected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "format version 1 to {}", FORMAT_VERSION)
+    }
+}
+
+impl Serialize for Tone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let accidental = match self.modifiers {
+            None => "",
+            Some(ToneModifiers::Sharp) => "#",
+            Some(ToneModifiers::Flat) => "b",
+            Some(ToneModifiers::Natural) => "n",
+        };
+
+        serializer.collect_str(&format_args!(
+            "{}{}{}",
+            self.octave, accidental, self.position
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for Tone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| de::Error::custom(format_args!("invalid tone {:?}: {}", s, e)))
+    }
+}
+
+impl Serialize for TimeSignature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeSignature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.split_once('/')
+            .and_then(|(num, den)| Some((num.parse().ok()?, den.parse().ok()?)))
+            .and_then(|(num, den)| TimeSignature::new(num, den).ok())
+            .ok_or_else(|| {
+                de::Error::invalid_value(Unexpected::Str(&s), &"a time signature like \"3/4\"")
+            })
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.split_once('/')
+            .and_then(|(num, den)| Some((num.parse().ok()?, den.parse().ok()?)))
+            .filter(|&(_, den)| den != 0)
+            .map(|(num, den)| Duration::new(num, den))
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&s), &"a time like \"3/8\""))
+    }
+}
+
+impl Serialize for ChordSymbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChordSymbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| de::Error::custom(format_args!("invalid chord symbol {:?}: {}", s, e)))
+    }
+}
+
+impl Serialize for KeySignature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (tonic, modifiers) = self.tonic();
+        let accidental = match modifiers {
+            Some(ToneModifiers::Sharp) => "#",
+            Some(ToneModifiers::Flat) => "b",
+            _ => "",
+        };
+
+        serializer.collect_str(&format_args!("{}{} {}", tonic, accidental, self.mode()))
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySignature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| de::Error::custom(format_args!("invalid key signature {:?}: {}", s, e)))
+    }
+}
+
+#[derive(Deserialize)]
+struct TupletOwned {
+    actual: u8,
+    normal: u8,
+    unit: NoteSize,
+    notes: Vec<Note>,
+}
+
+impl<'de> Deserialize<'de> for Tuplet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TupletVisitor)
+    }
+}
+
+struct TupletVisitor;
+
+impl<'de> de::Visitor<'de> for TupletVisitor {
+    type Value = Tuplet;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a tuplet with its ratio, unit and notes")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Tuplet, A::Error> {
+        let tuplet = TupletOwned::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        Tuplet::new(tuplet.actual, tuplet.normal, tuplet.unit, tuplet.notes)
+            .map_err(|e| de::Error::custom(format_args!("invalid tuplet: {}", e)))
+    }
+
+    /// Tuplets before version 2, only their notes
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Tuplet, A::Error> {
+        let notes = Vec::<NoteInfo>::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+        let actual = u8::try_from(notes.len())
+            .map_err(|_| de::Error::invalid_length(notes.len(), &"at most 255 notes"))?;
+        let normal = match actual {
+            0 | 1 => 1,
+            2 => 3,
+            actual => actual - 1,
+        };
+        Tuplet::with_ratio(actual, normal, notes.into_iter().map(Note::Note).collect())
+            .map_err(|e| de::Error::custom(format_args!("invalid tuplet: {}", e)))
+    }
+}
+
+#[derive(Deserialize)]
+struct ChordOwned {
+    tones: Vec<Tone>,
+    size: NoteSize,
+}
+
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let chord = ChordOwned::deserialize(deserializer)?;
+        Chord::new(chord.tones, chord.size)
+            .ok_or_else(|| de::Error::invalid_length(0, &"a chord with at least one tone"))
+    }
+}
+
+/// Reads the voices of a bar, refusing more than [`MAX_VOICES`], or the notes of
+/// its only voice as bars had them before version 3
+pub(crate) fn voices<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<Note>>, D::Error> {
+    let mut voices = Vec::new();
+    let mut notes = Vec::new();
+    for item in Vec::<VoiceOrNote>::deserialize(deserializer)? {
+        match item {
+            VoiceOrNote::Voice(voice) => voices.push(voice),
+            VoiceOrNote::Note(note) => notes.push(note),
+        }
+    }
+    if !notes.is_empty() {
+        if !voices.is_empty() {
+            return Err(de::Error::custom("a bar has either voices or notes"));
+        }
+        voices.push(notes);
+    }
+    if voices.len() > MAX_VOICES {
+        return Err(de::Error::invalid_length(voices.len(), &"at most 4 voices"));
+    }
+
+    Ok(voices)
+}
+
+/// A voice of a bar, or one of its notes in sheets from before version 3
+enum VoiceOrNote {
+    Voice(Vec<Note>),
+    Note(Note),
+}
+
+impl<'de> Deserialize<'de> for VoiceOrNote {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(VoiceOrNoteVisitor)
+    }
+}
+
+struct VoiceOrNoteVisitor;
+
+impl<'de> de::Visitor<'de> for VoiceOrNoteVisitor {
+    type Value = VoiceOrNote;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a voice")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<VoiceOrNote, A::Error> {
+        Vec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(VoiceOrNote::Voice)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<VoiceOrNote, A::Error> {
+        Note::deserialize(de::value::MapAccessDeserializer::new(map)).map(VoiceOrNote::Note)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bar::{Bar, LineType},
+        tempo::NoteSizeUnit,
+    };
+
+    /// The example of the documentation as it was written with version 1
+    const VERSION_1: &str = r#"{
+        "version": 1,
+        "title": "Example",
+        "author": "Someone",
+        "lines": [{
+            "name": "Piano",
+            "line": { "single": { "bars": [{
+                "header": { "clef": "treble", "time_signature": "3/4" },
+                "notes": [
+                    { "note": { "tone": "C#4", "size": { "unit": "crotchet" } } },
+                    { "with_grace": { "grace": "E4", "note": { "tone": "D4", "size": { "unit": "crotchet" } } } },
+                    { "tuplet": [
+                        { "tone": "E4", "size": { "unit": "quaver" } },
+                        { "tone": "F4", "size": { "unit": "quaver" } },
+                        { "tone": "G4", "size": { "unit": "quaver" } }
+                    ] }
+                ]
+            }] } }
+        }]
+    }"#;
+
+    /// The same with the tuplets of version 2
+    const VERSION_2: &str = r#"{
+        "version": 2,
+        "title": "Example",
+        "author": "Someone",
+        "lines": [{ "line": { "single": { "bars": [{
+            "header": { "time_signature": "3/4" },
+            "notes": [
+                { "rest": { "unit": "minim" } },
+                { "tuplet": { "actual": 3, "normal": 2, "unit": { "unit": "quaver" }, "notes": [
+                    { "note": { "tone": "E4", "size": { "unit": "quaver" } } },
+                    { "rest": { "unit": "crotchet" } }
+                ] } }
+            ]
+        }] } } }]
+    }"#;
+
+    fn bar(sheet: &MusicSheet) -> &Bar {
+        match &sheet.lines[0].line {
+            LineType::Single(line) => &line.bars[0],
+            LineType::Double(..) => panic!("expected a single line"),
+        }
+    }
+
+    #[test]
+    fn reads_notes_and_tuplets_of_version_1() {
+        let sheet: MusicSheet = serde_json::from_str(VERSION_1).unwrap();
+        let bar = bar(&sheet);
+        assert_eq!(bar.voices.len(), 1);
+        assert_eq!(bar.voices[0].len(), 3);
+
+        let Note::Tuplet(tuplet) = &bar.voices[0][2] else {
+            panic!("expected a tuplet, found {:?}", bar.voices[0][2]);
+        };
+        assert_eq!((tuplet.actual(), tuplet.normal()), (3, 2));
+        assert_eq!(tuplet.unit().unit, NoteSizeUnit::Quaver);
+        assert_eq!(tuplet.duration(), Duration::new(1, 4));
+
+        // and saves them with the current version
+        let saved = serde_json::to_value(&sheet).unwrap();
+        assert_eq!(saved["version"], FORMAT_VERSION);
+        assert!(saved["lines"][0]["line"]["single"]["bars"][0]["voices"].is_array());
+    }
+
+    #[test]
+    fn reads_notes_of_version_2() {
+        let sheet: MusicSheet = serde_json::from_str(VERSION_2).unwrap();
+        let bar = bar(&sheet);
+        assert_eq!(bar.voices.len(), 1);
+        assert_eq!(bar.duration(), Duration::new(3, 4));
+    }
+
+    #[test]
+    fn reads_duplets_of_version_1() {
+        // two quavers in the time of three
+        let duplet: Note = serde_json::from_str(
+            r#"{ "tuplet": [
+                { "tone": "E4", "size": { "unit": "quaver" } },
+                { "tone": "F4", "size": { "unit": "quaver" } }
+            ] }"#,
+        )
+        .unwrap();
+        assert_eq!(duplet.duration(), Duration::new(3, 8));
+    }
+
+    #[test]
+    fn refuses_bars_mixing_voices_and_notes() {
+        let json = VERSION_2.replace(
+            r#"{ "rest": { "unit": "minim" } },"#,
+            r#"[{ "rest": { "unit": "minim" } }],"#,
+        );
+        assert!(serde_json::from_str::<MusicSheet>(&json).is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_versions() {
+        for version in [0, FORMAT_VERSION + 1] {
+            let json = VERSION_1.replace(r#""version": 1"#, &format!(r#""version": {version}"#));
+            let error = serde_json::from_str::<MusicSheet>(&json).unwrap_err();
+            assert!(error.to_string().contains("format version 1 to"), "{error}");
+        }
+    }
+}
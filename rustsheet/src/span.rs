@@ -0,0 +1,178 @@
+use thiserror::Error;
+
+use crate::{bar::NotePosition, notes::Note, MusicSheet};
+
+/// What connecting two notes means
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SpanKind {
+    /// Joins a note to the next one of the same pitch, to play them as a single longer note
+    Tie,
+    /// Plays the notes from the start to the end legato, as one phrase
+    Slur,
+    /// A hairpin getting louder from the start until the end
+    Crescendo,
+    /// A hairpin getting softer from the start until the end
+    Diminuendo,
+    /// Getting faster from the start until the end
+    Accelerando,
+    /// Getting slower from the start until the end
+    Ritardando,
+}
+
+impl SpanKind {
+    /// Whether this is a crescendo or a diminuendo, that changes the dynamics
+    pub fn is_hairpin(&self) -> bool {
+        matches!(self, Self::Crescendo | Self::Diminuendo)
+    }
+
+    /// Whether this is an accelerando or a ritardando, that changes the tempo
+    pub fn is_tempo_change(&self) -> bool {
+        matches!(self, Self::Accelerando | Self::Ritardando)
+    }
+}
+
+/// A tie, a slur, a hairpin or a tempo change, from one note of a voice to a later one
+/// of the same voice.
+///
+/// Both ends point at notes written in the bars, not inside tuplets. Slurs can
+/// start or end on a tuplet, ties only join notes and chords, and neither go on rests.
+/// Hairpins and tempo changes go from any note or rest to any later one, and reach their
+/// dynamic or tempo on the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub kind: SpanKind,
+    pub start: NotePosition,
+    pub end: NotePosition,
+}
+
+impl Span {
+    pub fn tie(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Tie,
+            start,
+            end,
+        }
+    }
+
+    pub fn slur(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Slur,
+            start,
+            end,
+        }
+    }
+
+    pub fn crescendo(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Crescendo,
+            start,
+            end,
+        }
+    }
+
+    pub fn diminuendo(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Diminuendo,
+            start,
+            end,
+        }
+    }
+
+    pub fn accelerando(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Accelerando,
+            start,
+            end,
+        }
+    }
+
+    pub fn ritardando(start: NotePosition, end: NotePosition) -> Self {
+        Self {
+            kind: SpanKind::Ritardando,
+            start,
+            end,
+        }
+    }
+
+    /// Checks that both ends are in the sheet and can be connected.
+    ///
+    /// Ties go from a note to the next one in the same voice, even across a barline,
+    /// and need at least one tone in common between them.
+    /// ```
+    /// use rustsheet::{
+    ///     bar::{Line, LineType, NotePosition},
+    ///     notation,
+    ///     span::{Span, SpanError},
+    ///     MusicSheet,
+    /// };
+    ///
+    /// fn main() {
+    ///     let line = notation::parse_line("time=2/4 | C4:4 D4:4 | D4:4 E4:4 |").unwrap();
+    ///     let lines = vec![Line::new(LineType::Single(line))];
+    ///     let sheet = MusicSheet::new("Example".to_string(), "Someone".to_string(), lines);
+    ///     let at = |bar, index| NotePosition { line: 0, staff: 0, bar, voice: 0, index };
+    ///
+    ///     // the D goes on over the barline
+    ///     assert_eq!(Span::tie(at(0, 1), at(1, 0)).check(&sheet), Ok(()));
+    ///     assert_eq!(Span::tie(at(0, 0), at(0, 1)).check(&sheet), Err(SpanError::DifferentPitches));
+    ///     assert_eq!(Span::tie(at(0, 0), at(1, 0)).check(&sheet), Err(SpanError::NotNextNote));
+    ///     // slurs can go over as many notes as needed
+    ///     assert_eq!(Span::slur(at(0, 0), at(1, 1)).check(&sheet), Ok(()));
+    ///     assert_eq!(Span::crescendo(at(1, 1), at(0, 0)).check(&sheet), Err(SpanError::Backwards));
+    /// }
+    /// ```
+    pub fn check(&self, sheet: &MusicSheet) -> Result<(), SpanError> {
+        let note = |position: NotePosition| {
+            let note = sheet
+                .note(position)
+                .ok_or(SpanError::MissingNote(position))?;
+            match (self.kind, note) {
+                (SpanKind::Tie | SpanKind::Slur, Note::Rest(_)) => {
+                    Err(SpanError::NotANote(position))
+                }
+                (SpanKind::Tie, Note::Tuplet(_)) => Err(SpanError::NotANote(position)),
+                _ => Ok(note),
+            }
+        };
+        let start = note(self.start)?;
+        let end = note(self.end)?;
+
+        if self.start.partial_cmp(&self.end) != Some(std::cmp::Ordering::Less) {
+            return Err(SpanError::Backwards);
+        }
+
+        match self.kind {
+            SpanKind::Tie => {
+                if sheet.next_position(self.start) != Some(self.end) {
+                    return Err(SpanError::NotNextNote);
+                }
+                if !start.tones().iter().any(|tone| end.tones().contains(tone)) {
+                    return Err(SpanError::DifferentPitches);
+                }
+                Ok(())
+            }
+            SpanKind::Slur
+            | SpanKind::Crescendo
+            | SpanKind::Diminuendo
+            | SpanKind::Accelerando
+            | SpanKind::Ritardando => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SpanError {
+    #[error("There's no note at {0}")]
+    MissingNote(NotePosition),
+    #[error("Ties and slurs can't go on rests, and ties can't go on tuplets, found one at {0}")]
+    NotANote(NotePosition),
+    #[error("The end comes before the start, or is in another voice")]
+    Backwards,
+    #[error("Ties have to end on the note right after the one they start on")]
+    NotNextNote,
+    #[error("Ties have to join notes with the same pitch")]
+    DifferentPitches,
+}
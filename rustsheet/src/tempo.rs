@@ -1,8 +1,15 @@
-use std::{fmt, num::NonZeroU8};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BTreeMap,
+    fmt,
+    iter::Sum,
+    num::{NonZeroU16, NonZeroU8},
+    ops::{Add, Sub},
+};
 
 use thiserror::Error;
 
-use crate::math;
+use crate::{math, span::SpanKind, MusicSheet};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TimeSignature {
@@ -81,6 +88,21 @@ impl TimeSignature {
     }
 }
 
+impl TimeSignature {
+    /// How long a full bar is
+    /// ```
+    /// use rustsheet::tempo::{Duration, TimeSignature};
+    ///
+    /// fn main() {
+    ///     let ts = TimeSignature::new(6, 8).unwrap();
+    ///     assert_eq!(ts.bar_duration(), Duration::new(3, 4));
+    /// }
+    /// ```
+    pub fn bar_duration(&self) -> Duration {
+        Duration::new(self.num.get().into(), self.den.get().into())
+    }
+}
+
 impl fmt::Display for TimeSignature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}/{}", self.num, self.den)
@@ -99,16 +121,282 @@ impl Default for TimeSignature {
     }
 }
 
-pub trait TimeSignatureralElement {
-    fn get_tempo(&self) -> TimeSignature;
+/// A tempo marking, how many beats go in a minute from the bar it's on
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tempo {
+    /// The note that gets the beat
+    pub beat: NoteSize,
+    /// Beats per minute
+    pub bpm: NonZeroU16,
+    /// Words written before the metronome mark, like "Allegro"
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub text: Option<String>,
+}
+
+impl Tempo {
+    /// Creates a metronome mark of `bpm` beats of `beat` per minute, or fails if `bpm` is zero
+    pub fn new(beat: NoteSize, bpm: u16) -> Result<Self, TempoError> {
+        Ok(Self {
+            beat,
+            bpm: NonZeroU16::new(bpm).ok_or(TempoError::ZeroBpm)?,
+            text: None,
+        })
+    }
+
+    /// How many crotchets go in a minute.
+    /// ```
+    /// use rustsheet::tempo::{NoteSize, NoteSizeModifiers, NoteSizeUnit, Tempo};
+    ///
+    /// fn main() {
+    ///     let beat = NoteSize {
+    ///         unit: NoteSizeUnit::Crotchet,
+    ///         modifiers: Some(NoteSizeModifiers::Dot),
+    ///     };
+    ///     // a dotted crotchet at 60 in 6/8
+    ///     let tempo = Tempo::new(beat, 60).unwrap();
+    ///     assert_eq!(tempo.quarters_per_minute(), 90.0);
+    /// }
+    /// ```
+    pub fn quarters_per_minute(&self) -> f64 {
+        f64::from(self.bpm.get()) * quarters(self.beat.duration())
+    }
+}
+
+/// A crotchet at 120
+impl Default for Tempo {
+    fn default() -> Self {
+        Self {
+            beat: NoteSizeUnit::Crotchet.into(),
+            bpm: NonZeroU16::new(120).unwrap(),
+            text: None,
+        }
+    }
+}
+
+/// How many ritardandos slow down and accelerandos speed up, when there's no tempo
+/// marking where they end
+const TEMPO_CHANGE: f64 = 0.25;
+
+/// How fast a sheet goes from start to end, to turn positions in the music into real time.
+///
+/// Tempo markings are read from the bars of the first line, the others are expected
+/// to agree, and accelerandos and ritardandos from every line. A tempo change goes
+/// gradually from the tempo where it starts to the marking where it ends, or by a
+/// quarter when there's none, and the tempo stays there after it.
+/// ```
+/// use rustsheet::{
+///     bar::{Line, LineType, NotePosition},
+///     notation,
+///     span::Span,
+///     tempo::{Duration, TempoMap},
+///     MusicSheet,
+/// };
+///
+/// fn main() {
+///     let line = notation::parse_line("tempo=2=30 | C4:1 | D4:1 | E4:1 |").unwrap();
+///     let at = |bar| NotePosition { line: 0, staff: 0, bar, voice: 0, index: 0 };
+///     let lines = vec![Line::new(LineType::Single(line))];
+///     let sheet = MusicSheet {
+///         spans: vec![Span::ritardando(at(1), at(2))],
+///         ..MusicSheet::new("Example".to_string(), "Someone".to_string(), lines)
+///     };
+///
+///     // two minims at 30 take four seconds
+///     let tempos = TempoMap::new(&sheet, 120.0);
+///     assert_eq!(tempos.seconds(Duration::new(1, 1)), 4.0);
+///     // then it slows down, until it's three quarters of what it was
+///     assert_eq!(tempos.quarters_per_minute(Duration::new(3, 2)), 52.5);
+///     assert_eq!(tempos.quarters_per_minute(Duration::new(5, 2)), 45.0);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    /// The tempo before the first marking
+    initial: f64,
+    /// Where the tempo is set, in crotchets per minute, in order
+    marks: Vec<(Duration, f64)>,
+    /// Gradual changes, from where they start to where they end and the tempo they reach
+    ramps: Vec<(Duration, Duration, f64)>,
+}
+
+impl TempoMap {
+    /// The tempo of the sheet, starting at `quarters_per_minute` until the first marking
+    pub fn new(sheet: &MusicSheet, quarters_per_minute: f64) -> Self {
+        let mut map = Self {
+            initial: quarters_per_minute,
+            marks: Vec::new(),
+            ramps: Vec::new(),
+        };
+
+        if let Some(line) = sheet.lines.first() {
+            let staff = line.line.staves()[0];
+            for (bar, start) in staff.bars.iter().zip(staff.bar_starts()) {
+                if let Some(tempo) = &bar.header.tempo {
+                    map.marks.push((start, tempo.quarters_per_minute()));
+                }
+            }
+        }
+
+        let mut changes: Vec<_> = sheet
+            .spans
+            .iter()
+            .filter(|span| span.kind.is_tempo_change())
+            .filter_map(|span| Some((sheet.onset(span.start)?, sheet.onset(span.end)?, span.kind)))
+            .filter(|(start, end, _)| start < end)
+            .collect();
+        changes.sort_by_key(|(start, _, _)| *start);
+        for (start, end, kind) in changes {
+            let target = match map.marks.iter().find(|(at, _)| *at == end) {
+                Some((_, tempo)) => *tempo,
+                None => {
+                    let from = map.level(start);
+                    let target = if kind == SpanKind::Ritardando {
+                        from * (1.0 - TEMPO_CHANGE)
+                    } else {
+                        from * (1.0 + TEMPO_CHANGE)
+                    };
+                    let index = map.marks.partition_point(|(at, _)| *at <= end);
+                    map.marks.insert(index, (end, target));
+                    target
+                }
+            };
+            map.ramps.push((start, end, target));
+        }
+
+        map
+    }
+
+    /// The tempo at `pos`, in crotchets per minute
+    pub fn quarters_per_minute(&self, pos: Duration) -> f64 {
+        match self.ramp(pos) {
+            Some(&(start, end, target)) => {
+                let from = self.level(start);
+                let done = quarters(pos - start) / quarters(end - start);
+                from + (target - from) * done
+            }
+            None => self.level(pos),
+        }
+    }
+
+    /// How many seconds into the music `pos` is
+    pub fn seconds(&self, pos: Duration) -> f64 {
+        // the tempo is either steady or changing steadily between two of these
+        let mut bounds: Vec<Duration> = self
+            .marks
+            .iter()
+            .map(|(at, _)| *at)
+            .chain(self.ramps.iter().flat_map(|(start, end, _)| [*start, *end]))
+            .filter(|at| *at < pos)
+            .chain([Duration::ZERO, pos])
+            .collect();
+        bounds.sort();
+        bounds.dedup();
+
+        bounds
+            .windows(2)
+            .map(|w| {
+                let (from, to) = (w[0], w[1]);
+                let length = quarters(to - from);
+                let first = self.quarters_per_minute(from);
+                match self.ramp(from) {
+                    // the time of every crotchet goes with the inverse of the tempo
+                    Some(&(_, end, _)) if to <= end => {
+                        let last = self.quarters_per_minute(to);
+                        if (last - first).abs() < f64::EPSILON {
+                            60.0 * length / first
+                        } else {
+                            60.0 * length * (last / first).ln() / (last - first)
+                        }
+                    }
+                    _ => 60.0 * length / first,
+                }
+            })
+            .sum()
+    }
+
+    /// Every point where the tempo changes and what it changes to, from the start.
+    /// Gradual changes go in steps of `step`, each one at the tempo in its middle.
+    pub fn steps(&self, step: Duration) -> Vec<(Duration, f64)> {
+        let mut steps = BTreeMap::new();
+        steps.insert(Duration::ZERO, self.initial);
+        steps.extend(self.marks.iter().copied());
+
+        for &(start, end, _) in &self.ramps {
+            let mut pos = start;
+            while pos < end {
+                let next = (pos + step).min(end);
+                steps.insert(pos, self.quarters_per_minute((pos + next).scale(1, 2)));
+                pos = next;
+            }
+        }
+
+        steps.into_iter().collect()
+    }
+
+    /// The gradual change `pos` is in
+    fn ramp(&self, pos: Duration) -> Option<&(Duration, Duration, f64)> {
+        self.ramps
+            .iter()
+            .find(|(start, end, _)| *start <= pos && pos < *end)
+    }
+
+    /// The tempo of the last marking at or before `pos`
+    fn level(&self, pos: Duration) -> f64 {
+        self.marks
+            .iter()
+            .take_while(|(at, _)| *at <= pos)
+            .last()
+            .map_or(self.initial, |(_, tempo)| *tempo)
+    }
+}
+
+/// How many crotchets long a duration is
+fn quarters(duration: Duration) -> f64 {
+    4.0 * duration.num as f64 / duration.den as f64
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoteSize {
     pub unit: NoteSizeUnit,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub modifiers: Option<NoteSizeModifiers>,
 }
 
+impl NoteSize {
+    /// How long the note is, with its dots. Fermatas don't change it.
+    /// ```
+    /// use rustsheet::tempo::{Duration, NoteSize, NoteSizeModifiers, NoteSizeUnit};
+    ///
+    /// fn main() {
+    ///     let dotted_crotchet = NoteSize {
+    ///         unit: NoteSizeUnit::Crotchet,
+    ///         modifiers: Some(NoteSizeModifiers::Dot),
+    ///     };
+    ///     assert_eq!(dotted_crotchet.duration(), Duration::new(3, 8));
+    /// }
+    /// ```
+    pub fn duration(&self) -> Duration {
+        let unit = Duration::new(1 << self.unit as u64, 1 << NoteSizeUnit::SemiBreve as u64);
+        // every dot adds half of what came before
+        let (num, den) = match self.modifiers {
+            Some(NoteSizeModifiers::Dot) => (3, 2),
+            Some(NoteSizeModifiers::DoubleDot) => (7, 4),
+            Some(NoteSizeModifiers::TripleDot) => (15, 8),
+            Some(NoteSizeModifiers::Crown) | None => (1, 1),
+        };
+
+        unit.scale(num, den)
+    }
+}
+
 impl From<NoteSizeUnit> for NoteSize {
     fn from(value: NoteSizeUnit) -> Self {
         NoteSize {
@@ -120,6 +408,8 @@ impl From<NoteSizeUnit> for NoteSize {
 
 /// Expressed as
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NoteSizeUnit {
     /// 8/1
     Large = 11,
@@ -148,7 +438,27 @@ pub enum NoteSizeUnit {
     DemiSemiHemiDemiSemiQuaver = 0,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl NoteSizeUnit {
+    /// Every unit, from the longest
+    pub const ALL: [NoteSizeUnit; 12] = [
+        NoteSizeUnit::Large,
+        NoteSizeUnit::Long,
+        NoteSizeUnit::Breve,
+        NoteSizeUnit::SemiBreve,
+        NoteSizeUnit::Minim,
+        NoteSizeUnit::Crotchet,
+        NoteSizeUnit::Quaver,
+        NoteSizeUnit::SemiQuaver,
+        NoteSizeUnit::DemiSemiQuaver,
+        NoteSizeUnit::HemiDemiSemiQuaver,
+        NoteSizeUnit::SemiHemiDemiSemiQuaver,
+        NoteSizeUnit::DemiSemiHemiDemiSemiQuaver,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NoteSizeModifiers {
     Dot,
     DoubleDot,
@@ -156,8 +466,201 @@ pub enum NoteSizeModifiers {
     Crown,
 }
 
+/// An exact length of time, as a fraction of a semibreve
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Duration {
+    num: u64,
+    den: u64,
+}
+
+impl Duration {
+    pub const ZERO: Duration = Duration { num: 0, den: 1 };
+
+    /// Creates the duration `num/den` of a semibreve, always kept in lowest terms.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `den` is zero.
+    pub fn new(num: u64, den: u64) -> Self {
+        assert!(den != 0, "a duration can't have a zero denominator");
+        let gcd = math::gcd(num, den);
+
+        Self {
+            num: num / gcd,
+            den: den / gcd,
+        }
+    }
+
+    pub fn num(&self) -> u64 {
+        self.num
+    }
+
+    pub fn den(&self) -> u64 {
+        self.den
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    /// Multiplies the duration by `num/den`
+    pub fn scale(self, num: u64, den: u64) -> Self {
+        Self::new(self.num * num, self.den * den)
+    }
+
+    /// Subtracts `rhs`, or returns `None` if it's longer
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        (self >= rhs).then(|| self - rhs)
+    }
+
+    /// The fewest note sizes that add up to the duration, longest first, to be tied together.
+    ///
+    /// Returns `None` if no note sizes add up to it, like for a third of a crotchet.
+    /// ```
+    /// use rustsheet::tempo::{Duration, NoteSizeUnit};
+    ///
+    /// fn main() {
+    ///     // a semibreve tied to a dotted crotchet
+    ///     let sizes = Duration::new(11, 8).to_note_sizes().unwrap();
+    ///     assert_eq!(sizes.len(), 2);
+    ///     assert_eq!(sizes[0].unit, NoteSizeUnit::SemiBreve);
+    ///     assert_eq!(sizes[1].duration(), Duration::new(3, 8));
+    ///
+    ///     assert!(Duration::new(1, 12).to_note_sizes().is_none());
+    /// }
+    /// ```
+    pub fn to_note_sizes(&self) -> Option<Vec<NoteSize>> {
+        // the shortest length a note can have is a triple dot on the shortest unit
+        let finest: u64 = 1 << (NoteSizeUnit::SemiBreve as u64 + 3);
+        if !finest.is_multiple_of(self.den) {
+            return None;
+        }
+        let length = self.num * (finest / self.den);
+
+        let mut sizes: Vec<(NoteSize, u64)> = NoteSizeUnit::ALL
+            .iter()
+            .flat_map(|unit| {
+                [
+                    None,
+                    Some(NoteSizeModifiers::Dot),
+                    Some(NoteSizeModifiers::DoubleDot),
+                    Some(NoteSizeModifiers::TripleDot),
+                ]
+                .map(|modifiers| NoteSize {
+                    unit: *unit,
+                    modifiers,
+                })
+            })
+            .map(|size| {
+                let d = size.duration();
+                (size, d.num * (finest / d.den))
+            })
+            .collect();
+        sizes.sort_by_key(|(_, l)| Reverse(*l));
+
+        // try with one note, then two and so on, most lengths need very few of them
+        let mut found = Vec::new();
+        (0..=length / sizes[sizes.len() - 1].1 + 3)
+            .any(|count| fill(length, count, &sizes, &mut found))
+            .then_some(found)
+    }
+}
+
+/// Looks for `count` sizes adding up to `length`, never longer than the ones before them
+fn fill(length: u64, count: u64, sizes: &[(NoteSize, u64)], found: &mut Vec<NoteSize>) -> bool {
+    if length == 0 {
+        return true;
+    }
+
+    for (i, (size, l)) in sizes.iter().enumerate() {
+        if *l > length {
+            continue;
+        }
+        // the sizes only get shorter from here
+        if *l * count < length {
+            return false;
+        }
+
+        found.push(*size);
+        if fill(length - l, count - 1, &sizes[i..], found) {
+            return true;
+        }
+        found.pop();
+    }
+
+    false
+}
+
+impl From<NoteSize> for Duration {
+    fn from(value: NoteSize) -> Self {
+        value.duration()
+    }
+}
+
+impl Default for Duration {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let den = self.den / math::gcd(self.den, rhs.den) * rhs.den;
+        Duration::new(self.num * (den / self.den) + rhs.num * (den / rhs.den), den)
+    }
+}
+
+/// # Panics
+///
+/// Panics if the result would be negative.
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let den = self.den / math::gcd(self.den, rhs.den) * rhs.den;
+        let lhs = self.num * (den / self.den);
+        let rhs = rhs.num * (den / rhs.den);
+        assert!(lhs >= rhs, "a duration can't be negative");
+
+        Duration::new(lhs - rhs, den)
+    }
+}
+
+impl Sum for Duration {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Duration::ZERO, Add::add)
+    }
+}
+
+impl PartialOrd for Duration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Duration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (u128::from(self.num) * u128::from(other.den))
+            .cmp(&(u128::from(other.num) * u128::from(self.den)))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Error)]
 pub enum TimeSignatureError {
     #[error("Invalid ratio: {0}/{1}")]
     InvalidRatio(u8, u8),
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum TempoError {
+    #[error("A tempo can't have zero beats per minute")]
+    ZeroBpm,
+}
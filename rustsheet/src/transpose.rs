@@ -0,0 +1,156 @@
+//! Moving notes, key signatures and chord symbols up or down.
+//!
+//! A [`Transposition`] is either diatonic, by a number of letters and of semitones, which
+//! spells every note exactly (C up a minor third is E♭, not D♯), or chromatic, by semitones
+//! alone, which picks the letters that give the key with the fewest accidentals.
+//!
+//! Key signatures in the bar headers move along with the notes. Keys that would need more
+//! than 7 sharps or flats, like D♯ major, are written as the key with the same sound,
+//! E♭ major, and so are the notes in them.
+//! ```
+//! use rustsheet::{notation, notes::Tone, transpose::Transposition};
+//!
+//! fn main() {
+//!     // a minor third up: two letters and three semitones
+//!     let up = Transposition::diatonic(2, 3);
+//!     let tone: Tone = "C4".parse().unwrap();
+//!     assert_eq!(tone.transpose(up).unwrap().to_string(), "E4♭");
+//!
+//!     let line = notation::parse_line("key=D F#4:2 B4:2 | C#5:1 |").unwrap();
+//!     let line = line.transpose(up).unwrap();
+//!     assert_eq!(line.bars[0].header.key_signature.unwrap().to_string(), "F major");
+//!     assert_eq!(line.bars[1].voices[0][0].tones()[0].to_string(), "E5");
+//!
+//!     // three semitones, spelled for the key
+//!     let line = line.transpose(Transposition::Chromatic(3)).unwrap();
+//!     assert_eq!(line.bars[0].header.key_signature.unwrap().to_string(), "A♭ major");
+//! }
+//! ```
+
+use crate::{
+    key::KeySignature,
+    notes::{NoteError, Octave, ToneModifiers},
+};
+
+/// How far to move notes, up when positive and down when negative
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Transposition {
+    /// By `steps` letters and `semitones`, like 1 and 2 for a major second
+    Diatonic { steps: i32, semitones: i32 },
+    /// By semitones, in the key with the fewest accidentals
+    Chromatic(i32),
+}
+
+impl Transposition {
+    pub const fn diatonic(steps: i32, semitones: i32) -> Self {
+        Self::Diatonic { steps, semitones }
+    }
+
+    /// The transposition that undoes this one
+    pub fn inverse(&self) -> Self {
+        match *self {
+            Self::Diatonic { steps, semitones } => Self::diatonic(-steps, -semitones),
+            Self::Chromatic(semitones) => Self::Chromatic(-semitones),
+        }
+    }
+
+    /// The key signature `key` goes to, and the letters and semitones the notes written
+    /// in it move by
+    /// ```
+    /// use rustsheet::{key::KeySignature, transpose::Transposition};
+    ///
+    /// fn main() {
+    ///     let key: KeySignature = "E major".parse().unwrap();
+    ///     // E# major would have 11 sharps
+    ///     let (key, interval) = Transposition::diatonic(0, 1).in_key(key);
+    ///     assert_eq!(key.to_string(), "F major");
+    ///     assert_eq!(interval, (1, 1));
+    ///
+    ///     let (key, _) = Transposition::Chromatic(6).in_key(KeySignature::default());
+    ///     assert_eq!(key.to_string(), "F♯ major");
+    /// }
+    /// ```
+    pub fn in_key(&self, key: KeySignature) -> (KeySignature, (i32, i32)) {
+        let fifths = i32::from(key.fifths());
+        let (mut steps, semitones) = match *self {
+            Self::Diatonic { steps, semitones } => (steps, semitones),
+            // the letters that land between 5 flats and 6 sharps
+            Self::Chromatic(semitones) => ((fifths + 7 * semitones + 5).div_euclid(12), semitones),
+        };
+
+        // every letter further is 12 fifths less, and every semitone 7 more
+        let mut moved = fifths + 7 * semitones - 12 * steps;
+        while moved > 7 {
+            moved -= 12;
+            steps += 1;
+        }
+        while moved < -7 {
+            moved += 12;
+            steps -= 1;
+        }
+
+        let key = KeySignature::new(moved as i8, key.mode()).expect("the key was moved in range");
+        (key, (steps, semitones))
+    }
+
+    /// The letters and semitones to move by outside of any key, as given for diatonic
+    /// transpositions and as in C major for chromatic ones
+    pub fn interval(&self) -> (i32, i32) {
+        match *self {
+            Self::Diatonic { steps, semitones } => (steps, semitones),
+            Self::Chromatic(_) => self.in_key(KeySignature::default()).1,
+        }
+    }
+}
+
+/// Moves the `letter` with `alter` semitones in octave `position` by `steps` letters and
+/// `semitones`, returning the new letter, alteration and octave.
+///
+/// Anything that would need a double sharp or flat is written with the next letter instead,
+/// and fails if even that letter is too far from the pitch.
+pub(crate) fn transpose_letter(
+    (letter, alter, position): (Octave, i32, i32),
+    steps: i32,
+    semitones: i32,
+) -> Result<(Octave, i32, i32), NoteError> {
+    let index = i32::from(letter.index());
+    let pitch = position * 12 + letter as i32 + alter + semitones;
+    let diatonic = position * 7 + index + steps;
+
+    // the letter asked for if it only needs one accidental, the closest one that does if not
+    let spell = |diatonic: i32| {
+        let letter = Octave::from_index(diatonic);
+        let position = diatonic.div_euclid(7);
+        (letter, pitch - position * 12 - letter as i32, position)
+    };
+    [0, 1, -1]
+        .iter()
+        .map(|offset| spell(diatonic + offset))
+        .find(|(_, alter, _)| alter.abs() <= 1)
+        .ok_or(NoteError::TooManyAccidentals)
+}
+
+/// The semitones an accidental raises a letter by
+pub(crate) fn alteration(modifiers: Option<ToneModifiers>) -> i32 {
+    match modifiers {
+        Some(ToneModifiers::Sharp) => 1,
+        Some(ToneModifiers::Flat) => -1,
+        Some(ToneModifiers::Natural) | None => 0,
+    }
+}
+
+/// The accidental of an alteration of at most a semitone, keeping a written natural
+pub(crate) fn modifiers(alter: i32, before: Option<ToneModifiers>) -> Option<ToneModifiers> {
+    match alter {
+        1 => Some(ToneModifiers::Sharp),
+        -1 => Some(ToneModifiers::Flat),
+        _ => before.filter(|m| *m == ToneModifiers::Natural),
+    }
+}
+
+/// Fails for octaves that don't fit a tone
+pub(crate) fn octave(position: i32) -> Result<u8, NoteError> {
+    u8::try_from(position).map_err(|_| NoteError::OutOfRange)
+}